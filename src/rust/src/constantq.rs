@@ -0,0 +1,234 @@
+use std::f64::consts::PI;
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Zip};
+use num_complex::Complex;
+
+use crate::features;
+
+/// Frequency (Hz) of C1, the default lowest bin, as in librosa.
+pub const C1: f64 = 32.70319566257483;
+
+/// Center frequencies (Hz) of `n_bins` bins spaced `bins_per_octave` to the octave from `fmin`.
+pub fn frequencies(fmin: f64, n_bins: usize, bins_per_octave: usize) -> Vec<f64> {
+    (0..n_bins)
+        .map(|k| fmin * f64::powf(2., k as f64 / bins_per_octave as f64))
+        .collect()
+}
+
+/// Relative bandwidth of a bin, the distance to the next bin over its center frequency.
+fn alpha(bins_per_octave: usize) -> f64 {
+    f64::powf(2., 1. / bins_per_octave as f64) - 1.
+}
+
+/// Time-domain filters of a variable-Q transform, one per bin of `freqs`.
+/// Each is a Hann-windowed complex exponential at the bin frequency, `sr / (f * alpha + gamma)`
+/// samples long, so its bandwidth is `f * alpha + gamma` Hz: `gamma = 0` gives the
+/// constant-Q transform, and a positive `gamma` widens the low bins for better time resolution.
+/// The filters are L1 normalized, so a unit sinusoid at a bin frequency has a magnitude of 0.5.
+pub fn vqt_filters(
+    sr: u32,
+    freqs: &[f64],
+    bins_per_octave: usize,
+    gamma: f64,
+) -> Vec<Array1<Complex<f64>>> {
+    let alpha = alpha(bins_per_octave);
+    let nyquist = sr as f64 / 2.;
+    if let Some(top) = freqs.last() {
+        if top * (1. + alpha / 2.) + gamma / 2. > nyquist {
+            panic!(
+                "the highest bin of {:.1} Hz extends above the Nyquist frequency",
+                top
+            );
+        }
+    }
+
+    freqs
+        .iter()
+        .map(|f| {
+            let length = usize::max((sr as f64 / (f * alpha + gamma)).ceil() as usize, 1);
+            let window = features::hann(length);
+            let half = (length / 2) as f64;
+            let kernel = Array1::from_shape_fn(length, |n| {
+                window[n] * Complex::from_polar(1., 2. * PI * f * (n as f64 - half) / sr as f64)
+            });
+            let norm: f64 = kernel.iter().map(|z| z.norm()).sum();
+            kernel / Complex::from(norm)
+        })
+        .collect()
+}
+
+/// Variable-Q transform of `x` with `filters` from `vqt_filters`, in frames centered on
+/// multiples of `hop_length`, with zeros beyond the ends of `x`. Each bin is the inner product
+/// of the frame with its filter, evaluated only at the frame centers: the filters are much shorter
+/// than the signal, so this costs less than convolving the whole signal and keeping one sample per hop.
+/// Returns a (n_frames, n_bins) array with `x.len() / hop_length + 1` frames, like `stft`.
+pub fn vqt(
+    x: &ArrayView1<f64>,
+    filters: &[Array1<Complex<f64>>],
+    hop_length: usize,
+) -> Array2<Complex<f64>> {
+    let n_frames = x.len() / hop_length + 1;
+    let mut out = Array2::<Complex<f64>>::zeros((n_frames, filters.len()));
+    if x.is_empty() {
+        return out;
+    }
+
+    for (k, kernel) in filters.iter().enumerate() {
+        let conj: Vec<Complex<f64>> = kernel.iter().map(|z| z.conj()).collect();
+        let half = kernel.len() / 2;
+        for (t, bin) in out.column_mut(k).iter_mut().enumerate() {
+            // Sample `n` of the kernel lines up with `x[center + n - half]`; the samples of the
+            // kernel past either end of `x` multiply zeros.
+            let center = t * hop_length;
+            let start = half.saturating_sub(center);
+            let end = usize::min(conj.len(), x.len() + half - center);
+            *bin = (start..end).map(|n| conj[n] * x[center + n - half]).sum();
+        }
+    }
+
+    out
+}
+
+/// Synthesis weight of each filter for `icqt`. Resynthesizing the transform with the same
+/// filters passes a sinusoid through the responses of every bin twice, once on analysis and once
+/// on synthesis, so bin `k` is weighted by the inverse of the sum of the squared responses of all
+/// the filters at its center frequency. This makes `icqt` a diagonal approximation of the
+/// pseudo-inverse of the filterbank, including at the edges of the band where fewer bins overlap.
+fn synthesis_weights(sr: u32, freqs: &[f64], filters: &[Array1<Complex<f64>>]) -> Vec<f64> {
+    freqs
+        .iter()
+        .map(|f| {
+            let power: f64 = filters
+                .iter()
+                .map(|kernel| {
+                    let step = Complex::from_polar(1., -2. * PI * f / sr as f64);
+                    let mut phasor = Complex::new(1., 0.);
+                    let mut response = Complex::new(0., 0.);
+                    for z in kernel {
+                        response += z * phasor;
+                        phasor *= step;
+                    }
+                    response.norm_sqr()
+                })
+                .sum();
+            1. / power
+        })
+        .collect()
+}
+
+/// Inverse of `vqt`: overlap-add of the filters scaled by the bins of `c`, a (n_frames, n_bins)
+/// transform computed with `filters` at `freqs` and `hop_length`. Returns `length` samples.
+/// The reconstruction is close to exact when the frame rate `sr / hop_length` is above twice the
+/// bandwidth of the filters; with a longer hop the bins are undersampled and the result aliases.
+pub fn icqt(
+    c: &ArrayView2<Complex<f64>>,
+    sr: u32,
+    freqs: &[f64],
+    filters: &[Array1<Complex<f64>>],
+    hop_length: usize,
+    length: usize,
+) -> Array1<f64> {
+    if c.ncols() != filters.len() {
+        panic!(
+            "c has {} bins but the filterbank has {}",
+            c.ncols(),
+            filters.len()
+        );
+    }
+    let weights = synthesis_weights(sr, freqs, filters);
+
+    let mut y = Array1::<f64>::zeros(length);
+    let mut bin_signal = Array1::<f64>::zeros(length);
+    let mut envelope = Array1::<f64>::zeros(length);
+    for (k, kernel) in filters.iter().enumerate() {
+        bin_signal.fill(0.);
+        envelope.fill(0.);
+        let half = kernel.len() / 2;
+        for (t, bin) in c.column(k).iter().enumerate() {
+            let center = t * hop_length;
+            let start = center.saturating_sub(half);
+            let end = usize::min(center + kernel.len() - half, length);
+            for n in start..end {
+                let z = kernel[n + half - center];
+                bin_signal[n] += (bin * z).re;
+                envelope[n] += z.norm();
+            }
+        }
+
+        // Dividing by the overlap-added windows undoes their ripple when the filter is not much
+        // longer than `hop_length`. The real part of a bin is half the amplitude of its sinusoid.
+        let scale = 2. * weights[k];
+        Zip::from(&mut y)
+            .and(&bin_signal)
+            .and(&envelope)
+            .for_each(|y, &b, &e| {
+                if e > f64::EPSILON {
+                    *y += scale * b / e;
+                }
+            });
+    }
+
+    y
+}
+
+#[cfg(test)]
+mod test_constantq {
+    use super::*;
+    use ndarray::s;
+
+    #[test]
+    fn test_vqt_sine() {
+        let sr = 22050;
+        let bins_per_octave = 12;
+        let freqs = frequencies(C1, 72, bins_per_octave);
+        // A4, bin 57 from C1.
+        let x = Array1::from_shape_fn(sr as usize, |i| {
+            (2. * PI * freqs[57] * i as f64 / sr as f64).sin()
+        });
+
+        for gamma in [0., 10.] {
+            let filters = vqt_filters(sr, &freqs, bins_per_octave, gamma);
+            let c = vqt(&x.view(), &filters, 512);
+            assert_eq!(c.dim(), (sr as usize / 512 + 1, 72));
+
+            let middle = c.row(20).mapv(|z| z.norm());
+            let peak =
+                middle.iter().enumerate().fold(
+                    (0, 0.),
+                    |acc, (k, v)| if *v > acc.1 { (k, *v) } else { acc },
+                );
+            assert_eq!(peak.0, 57);
+            assert!((peak.1 - 0.5).abs() < 1e-2, "{}", peak.1);
+        }
+
+        // A positive gamma shortens the filters of the low bins the most.
+        let cqt = vqt_filters(sr, &freqs, bins_per_octave, 0.);
+        let vqt = vqt_filters(sr, &freqs, bins_per_octave, 10.);
+        assert!(vqt[0].len() * 2 < cqt[0].len());
+        assert!(vqt[71].len() * 10 > cqt[71].len() * 9);
+    }
+
+    #[test]
+    fn test_icqt_round_trip() {
+        let sr = 22050;
+        let bins_per_octave = 24;
+        let freqs = frequencies(220., 72, bins_per_octave);
+        let x = Array1::from_shape_fn(8192, |i| {
+            let t = i as f64 / sr as f64;
+            0.5 * (2. * PI * 440. * t).sin() + 0.3 * (2. * PI * 1234.5 * t + 1.).sin()
+        });
+
+        for gamma in [0., 20.] {
+            let filters = vqt_filters(sr, &freqs, bins_per_octave, gamma);
+            let c = vqt(&x.view(), &filters, 64);
+            let y = icqt(&c.view(), sr, &freqs, &filters, 64, x.len());
+            assert_eq!(y.len(), x.len());
+
+            // Away from the edges, where the filters run past the signal.
+            let middle = s![2000..6000];
+            let err = (&y.slice(middle) - &x.slice(middle)).mapv(|v| v * v).sum();
+            let energy = x.slice(middle).mapv(|v| v * v).sum();
+            assert!(err / energy < 1e-5, "{}", err / energy);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Convert frame indices to time in seconds, given the hop length used for framing.
+pub fn frames_to_time(frames: &[usize], sr: u32, hop_length: usize) -> Vec<f64> {
+    frames
+        .iter()
+        .map(|frame| (frame * hop_length) as f64 / sr as f64)
+        .collect()
+}
+
+/// Convert frame indices to time in seconds for a variable hop: `hops[i]` samples lie between
+/// frames `i` and `i + 1`, so frame `t` starts at the sum of the first `t` hops.
+pub fn frames_to_time_variable(frames: &[usize], sr: u32, hops: &[usize]) -> Vec<f64> {
+    let mut starts = Vec::with_capacity(hops.len() + 1);
+    starts.push(0);
+    for hop in hops {
+        starts.push(starts[starts.len() - 1] + hop);
+    }
+    frames
+        .iter()
+        .map(|frame| match starts.get(*frame) {
+            Some(start) => *start as f64 / sr as f64,
+            None => panic!(
+                "frame {} needs {} hops, but hop_length has {}",
+                frame,
+                frame,
+                hops.len()
+            ),
+        })
+        .collect()
+}
+
+/// Default amplitude floor used before taking logs, so silence maps to -100 dB instead of -inf.
+pub const DEFAULT_AMIN: f64 = 1e-5;
+
+// Bits of the amplitude floor set with `set_amin`, shared by all threads so batches on the rayon
+// pool use the floor of the R session.
+static AMIN_BITS: AtomicU64 = AtomicU64::new(DEFAULT_AMIN.to_bits());
+
+/// Amplitude floor of `amplitude_to_db`, `DEFAULT_AMIN` unless changed with `set_amin`.
+pub fn amin() -> f64 {
+    f64::from_bits(AMIN_BITS.load(Ordering::Relaxed))
+}
+
+/// Power floor of `power_to_db`, the square of `amin()`, so both floors are the same level in dB
+/// and an amplitude and its power convert to the same dB values.
+pub fn pmin() -> f64 {
+    amin() * amin()
+}
+
+pub fn set_amin(amin: f64) {
+    if !amin.is_finite() || amin <= 0. {
+        panic!("amin must be a positive number");
+    }
+    AMIN_BITS.store(amin.to_bits(), Ordering::Relaxed);
+}
+
+/// Convert an amplitude to dB relative to `reference`. Both are floored at `amin()`.
+pub fn amplitude_to_db(amplitude: f64, reference: f64) -> f64 {
+    let amin = amin();
+    20. * f64::max(amplitude, amin).log10() - 20. * f64::max(reference, amin).log10()
+}
+
+/// Convert a power to dB relative to `reference`. Both are floored at `pmin()`.
+pub fn power_to_db(power: f64, reference: f64) -> f64 {
+    let pmin = pmin();
+    10. * f64::max(power, pmin).log10() - 10. * f64::max(reference, pmin).log10()
+}
+
+/// Center frequencies (Hz) of the `n_fft / 2 + 1` bins of a real FFT.
+pub fn fft_frequencies(sr: u32, n_fft: usize) -> Vec<f64> {
+    (0..=n_fft / 2)
+        .map(|k| k as f64 * sr as f64 / n_fft as f64)
+        .collect()
+}
+
+// Slaney's mel scale: linear below 1 kHz, logarithmic above.
+const MEL_F_SP: f64 = 200. / 3.;
+const MEL_MIN_LOG_HZ: f64 = 1000.;
+const MEL_MIN_LOG_MEL: f64 = MEL_MIN_LOG_HZ / MEL_F_SP;
+
+fn mel_log_step() -> f64 {
+    6.4_f64.ln() / 27.
+}
+
+pub fn hz_to_mel(hz: f64) -> f64 {
+    if hz >= MEL_MIN_LOG_HZ {
+        MEL_MIN_LOG_MEL + (hz / MEL_MIN_LOG_HZ).ln() / mel_log_step()
+    } else {
+        hz / MEL_F_SP
+    }
+}
+
+pub fn mel_to_hz(mel: f64) -> f64 {
+    if mel >= MEL_MIN_LOG_MEL {
+        MEL_MIN_LOG_HZ * (mel_log_step() * (mel - MEL_MIN_LOG_MEL)).exp()
+    } else {
+        mel * MEL_F_SP
+    }
+}
+
+/// `n_mels` frequencies (Hz) evenly spaced on the mel scale from `fmin` to `fmax`, both included.
+pub fn mel_frequencies(n_mels: usize, fmin: f64, fmax: f64) -> Vec<f64> {
+    scale_frequencies(n_mels, fmin, fmax, hz_to_mel, mel_to_hz)
+}
+
+/// Traunmüller's Bark scale.
+pub fn hz_to_bark(hz: f64) -> f64 {
+    26.81 * hz / (1960. + hz) - 0.53
+}
+
+pub fn bark_to_hz(bark: f64) -> f64 {
+    1960. * (bark + 0.53) / (26.28 - bark)
+}
+
+/// Glasberg and Moore's ERB-rate scale, the number of equivalent rectangular bandwidths below `hz`.
+pub fn hz_to_erb(hz: f64) -> f64 {
+    21.4 * (1. + 0.00437 * hz).log10()
+}
+
+pub fn erb_to_hz(erb: f64) -> f64 {
+    (10_f64.powf(erb / 21.4) - 1.) / 0.00437
+}
+
+/// `n` frequencies (Hz) from `fmin` to `fmax`, both included, evenly spaced on the scale given by
+/// `to_scale` and its inverse `from_scale`.
+fn scale_frequencies(
+    n: usize,
+    fmin: f64,
+    fmax: f64,
+    to_scale: fn(f64) -> f64,
+    from_scale: fn(f64) -> f64,
+) -> Vec<f64> {
+    let (min, max) = (to_scale(fmin), to_scale(fmax));
+    let step = if n > 1 {
+        (max - min) / (n - 1) as f64
+    } else {
+        0.
+    };
+    (0..n).map(|i| from_scale(min + i as f64 * step)).collect()
+}
+
+/// `n_bands` frequencies (Hz) evenly spaced on the Bark scale from `fmin` to `fmax`, both included.
+pub fn bark_frequencies(n_bands: usize, fmin: f64, fmax: f64) -> Vec<f64> {
+    scale_frequencies(n_bands, fmin, fmax, hz_to_bark, bark_to_hz)
+}
+
+/// `n_bands` frequencies (Hz) evenly spaced on the ERB-rate scale from `fmin` to `fmax`, both included.
+pub fn erb_frequencies(n_bands: usize, fmin: f64, fmax: f64) -> Vec<f64> {
+    scale_frequencies(n_bands, fmin, fmax, hz_to_erb, erb_to_hz)
+}
+
+#[cfg(test)]
+mod test_convert {
+    use super::*;
+
+    #[test]
+    fn test_db_floors_agree() {
+        // Other tests leave the floor at its default.
+        assert_eq!(amin(), DEFAULT_AMIN);
+        assert!((amplitude_to_db(0., 1.) + 100.).abs() < 1e-9);
+        assert!((power_to_db(0., 1.) + 100.).abs() < 1e-9);
+        for a in [1e-6, 3e-5, 0.01, 0.5, 2.] {
+            assert!((amplitude_to_db(a, 1.) - power_to_db(a * a, 1.)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_frames_to_time_variable() {
+        assert_eq!(
+            frames_to_time_variable(&[0, 1, 3], 100, &[50, 100, 200]),
+            vec![0., 0.5, 3.5]
+        );
+        assert_eq!(
+            frames_to_time_variable(&[0, 2, 5], 100, &[25; 5]),
+            frames_to_time(&[0, 2, 5], 100, 25)
+        );
+    }
+
+    #[test]
+    fn test_bark_erb_round_trip() {
+        for hz in [0., 100., 1000., 8000.] {
+            assert!((bark_to_hz(hz_to_bark(hz)) - hz).abs() < 1e-9);
+            assert!((erb_to_hz(hz_to_erb(hz)) - hz).abs() < 1e-9);
+        }
+        // 1 kHz is about 8.5 Bark and 15.6 ERBs
+        assert!((hz_to_bark(1000.) - 8.5).abs() < 0.05);
+        assert!((hz_to_erb(1000.) - 15.62).abs() < 0.01);
+
+        let bands = bark_frequencies(5, 100., 4000.);
+        assert!((bands[0] - 100.).abs() < 1e-9 && (bands[4] - 4000.).abs() < 1e-9);
+        let steps: Vec<f64> = bands
+            .windows(2)
+            .map(|w| hz_to_bark(w[1]) - hz_to_bark(w[0]))
+            .collect();
+        assert!(steps.iter().all(|d| (d - steps[0]).abs() < 1e-9));
+        assert_eq!(erb_frequencies(3, 50., 5000.).len(), 3);
+    }
+}
@@ -1,229 +1,893 @@
-use std::convert::TryFrom;
 use std::fs::File;
+use std::io::Cursor;
 use std::path::Path;
 
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
+use memmap2::Mmap;
+
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer};
+use symphonia::core::codecs::{self, CodecType, Decoder, DecoderOptions};
 use symphonia::core::errors::Error;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, Value};
+use symphonia::core::probe::{Descriptor, Hint, ProbeResult, QueryDescriptor};
 
-use ndarray::{Array2, ArrayView2};
+use ndarray::{s, Array2, ArrayView2, ArrayViewMut2};
 
+use crate::error::AudioError;
 use crate::misc;
 
+#[allow(clippy::too_many_arguments)]
 pub fn load(
     path: &Path,
     mono: bool,
     offset: f64,
     duration: Option<f64>,
-    filetype: &str,
+    track: Option<usize>,
+    mmap: bool,
+    norm: &Norm,
+    filetype: Option<&str>,
 ) -> Array2<f64> {
-    // Create a media source. Note that the MediaSource trait is automatically implemented for File, among other types.
-    let file = Box::new(File::open(path).expect("cannot open file"));
-    // Create the media source stream using the boxed media source from above.
-    let mss = MediaSourceStream::new(file, Default::default());
-    // Create a hint to help the format registry guess what format reader is appropriate.
-    let mut hint = Hint::new();
-    hint.with_extension(filetype);
-    // Use the default options when reading and decoding.
-    let format_opts: FormatOptions = Default::default();
-    let metadata_opts: MetadataOptions = Default::default();
-    let decoder_opts: DecoderOptions = Default::default();
-    // Probe the media source stream for a format.
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .expect("unsupported format");
-    // Get the format reader yielded by the probe operation.
-    let mut format = probed.format;
-    // Get the default track.
-    let track = format.default_track().expect("cannot get default_track");
-    // Create a decoder for the track.
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &decoder_opts)
-        .expect("cannot create decoder");
-    let channels = decoder
-        .codec_params()
-        .channels
-        .expect("cannot retrieve the number of channels")
-        .count();
-    let n_frames = decoder
-        .codec_params()
-        .n_frames
-        .expect("cannot retrieve the number of frames"); // In PCM n_frames is the same as n_samples, but for each channel
-    let sr = decoder
-        .codec_params()
-        .sample_rate
-        .expect("cannot retrieve the sample rate");
-    let file_time_duration = n_frames as f64 / sr as f64; // fix sample_per_channel conversion.
-    let duration_to_decode = f64::min(
-        // assures duration max = duration from file - offset
-        duration.unwrap_or(file_time_duration - offset),
-        file_time_duration - offset,
-    );
+    AudioReader::open(path, track, mmap, filetype).read(offset, duration, mono, norm)
+}
+
+fn open_file(path: &Path) -> File {
+    try_open_file(path).unwrap_or_else(|err| err.throw())
+}
 
-    if duration_to_decode <= 0. {
-        panic!("duration must be a positive number")
+fn try_open_file(path: &Path) -> Result<File, AudioError> {
+    File::open(path)
+        .map_err(|err| AudioError::Io(format!("cannot open {}: {}", path.display(), err)))
+}
+
+/// Open `path` as a media source, memory-mapped if `mmap` is set and the file can be mapped.
+fn media_source(path: &Path, mmap: bool) -> Box<dyn MediaSource> {
+    let file = open_file(path);
+
+    if mmap {
+        // Safety: the map is read-only. As with any file mapping, the file must not be truncated while it is mapped.
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Box::new(Cursor::new(map));
+        }
     }
 
-    let mut offset_samples = (offset * (sr as f64)) as u32; // Round to the lower bound integer by default. fix conversion // offset_samples is by channel
+    Box::new(file)
+}
+
+/// What to do when a packet fails to decode.
+pub enum OnError {
+    /// Stop with an error.
+    Error,
+    /// Drop the packet and continue with the next one.
+    Skip,
+    /// Stop and keep what was decoded so far.
+    Truncate,
+}
 
-    if (offset_samples as u64) >= n_frames {
-        panic!("offset bigger than or equal to total duration");
+impl OnError {
+    pub fn from_name(name: &str) -> OnError {
+        match name {
+            "error" => OnError::Error,
+            "skip" => OnError::Skip,
+            "truncate" => OnError::Truncate,
+            _ => panic!("on_error must be one of \"error\", \"skip\" or \"truncate\""),
+        }
     }
+}
+
+/// How decoded samples are scaled.
+pub enum Norm {
+    /// Integer PCM divided by its full scale, 2^(bits - 1), so samples lie in [-1, 1]. Float samples are kept as is.
+    FullScale,
+    /// Integer PCM as its integer values, e.g. -32768 to 32767 for 16 bits. Float samples are kept as is.
+    None,
+    /// Divided by the largest absolute sample, so the peak is 1. Silence is kept as is.
+    Peak,
+}
 
-    let mut duration_to_decode_samples = (duration_to_decode * (sr as f64)) as u32; // Round to the lower bound integer by default. fix conversion
-                                                                                    // Store the track identifier, we'll use it to filter packets.
-    let track_id = track.id;
-    let mut sample_buf = None;
-    let mut arr = Array2::<f64>::zeros((channels, duration_to_decode_samples as usize));
-    let mut idx = 0_usize;
+impl Norm {
+    pub fn from_name(name: &str) -> Norm {
+        match name {
+            "fullscale" => Norm::FullScale,
+            "none" => Norm::None,
+            "peak" => Norm::Peak,
+            _ => panic!("norm must be one of \"fullscale\", \"none\" or \"peak\""),
+        }
+    }
 
-    'outer: loop {
-        // Get the next packet from the format reader.
-        let packet = match format.next_packet() {
-            Ok(packet_ok) => packet_ok,
-            Err(Error::IoError(ref packet_err))
-                if packet_err.kind() == std::io::ErrorKind::UnexpectedEof =>
-            {
-                break;
+    /// Rescale `arr`, decoded at full scale from a sample format whose full scale is `full_scale`.
+    pub fn apply(&self, arr: &mut ArrayViewMut2<f64>, full_scale: f64) {
+        match self {
+            Norm::FullScale => {}
+            Norm::None => arr.mapv_inplace(|v| v * full_scale),
+            Norm::Peak => {
+                let peak = arr.fold(0., |acc: f64, v| acc.max(v.abs()));
+                if peak > 0. {
+                    arr.mapv_inplace(|v| v / peak);
+                }
             }
-            Err(packet_err) => panic!("{:?}", packet_err),
+        }
+    }
+}
+
+/// Full scale of a decoded sample format: 2^(bits - 1) for integer PCM, which symphonia divides
+/// by when converting to f64 (after centering unsigned samples), and 1 for float.
+/// Symphonia's PCM and FLAC decoders left-justify samples of `bits_per_sample` bits in an i32
+/// buffer, so the codec's bit depth takes precedence over the width of the buffer.
+fn full_scale(buf: &AudioBufferRef, bits_per_sample: Option<u32>) -> f64 {
+    match integer_bits(buf, bits_per_sample) {
+        Some(bits) => 2_f64.powi(bits as i32 - 1),
+        None => 1.,
+    }
+}
+
+/// Significant bits of the integer samples in `buf`, see `full_scale`. `None` for float samples.
+fn integer_bits(buf: &AudioBufferRef, bits_per_sample: Option<u32>) -> Option<u32> {
+    let container_bits = match buf {
+        AudioBufferRef::U8(_) | AudioBufferRef::S8(_) => 8,
+        AudioBufferRef::U16(_) | AudioBufferRef::S16(_) => 16,
+        AudioBufferRef::U24(_) | AudioBufferRef::S24(_) => 24,
+        AudioBufferRef::U32(_) | AudioBufferRef::S32(_) => 32,
+        AudioBufferRef::F32(_) | AudioBufferRef::F64(_) => return None,
+    };
+    Some(bits_per_sample.map_or(container_bits, |b| u32::min(b, container_bits)))
+}
+
+/// Name of the sample format of `buf`: `"f32"` or `"f64"` for float samples, otherwise `"s"` or
+/// `"u"` for signed or unsigned integers followed by their significant bits, e.g. `"s16"`.
+fn sample_format_name(buf: &AudioBufferRef, bits_per_sample: Option<u32>) -> String {
+    let unsigned = matches!(
+        buf,
+        AudioBufferRef::U8(_)
+            | AudioBufferRef::U16(_)
+            | AudioBufferRef::U24(_)
+            | AudioBufferRef::U32(_)
+    );
+    match (integer_bits(buf, bits_per_sample), buf) {
+        (Some(bits), _) => format!("{}{}", if unsigned { "u" } else { "s" }, bits),
+        (None, AudioBufferRef::F32(_)) => "f32".to_string(),
+        (None, _) => "f64".to_string(),
+    }
+}
+
+/// Outcome of `AudioReader::read_into`.
+pub struct ReadStats {
+    /// Number of frames written from decoded packets. The rest of the output is zero.
+    pub n_decoded: usize,
+    /// Number of packets dropped with `OnError::Skip`.
+    pub skipped_packets: usize,
+    /// Full scale of the decoded sample format, for `Norm::apply`. 1 if nothing was decoded.
+    pub full_scale: f64,
+    /// Largest absolute sample written, of any channel, before `Norm::apply`.
+    pub peak: f64,
+}
+
+/// A probed file and the decoder for one of its tracks, kept open so that several
+/// segments can be read without re-opening and re-probing the file.
+pub struct AudioReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    pub channels: usize,
+    pub n_frames: u64,
+    pub sr: u32,
+    // Whether nothing has been decoded yet, so reading from the start needs no seek.
+    fresh: bool,
+}
+
+impl AudioReader {
+    pub fn open(
+        path: &Path,
+        track: Option<usize>,
+        mmap: bool,
+        filetype: Option<&str>,
+    ) -> AudioReader {
+        // Create a media source. Note that the MediaSource trait is automatically implemented for File
+        // and for a Cursor over the memory map, among other types.
+        let source = media_source(path, mmap);
+        // Create the media source stream using the boxed media source from above.
+        let mss = MediaSourceStream::new(source, Default::default());
+        // Create a hint to help the format registry guess what format reader is appropriate.
+        let mut hint = Hint::new();
+        if let Some(filetype) = filetype {
+            hint.with_extension(filetype);
+        }
+        // Use the default options when reading and decoding.
+        let format_opts: FormatOptions = Default::default();
+        let metadata_opts: MetadataOptions = Default::default();
+        let decoder_opts: DecoderOptions = Default::default();
+        // Probe the media source stream for a format.
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &format_opts, &metadata_opts)
+            .unwrap_or_else(|err| format_error(path, err));
+        // Get the format reader yielded by the probe operation.
+        let format = probed.format;
+        // Get the requested track, or the default track if none was requested.
+        let track = match track {
+            Some(idx) => format.tracks().get(idx).unwrap_or_else(|| {
+                AudioError::Offset(format!(
+                    "track {} out of range, {} has {} tracks",
+                    idx,
+                    path.display(),
+                    format.tracks().len()
+                ))
+                .throw()
+            }),
+            None => default_track(path, format.as_ref()),
         };
+        // Store the track identifier, we'll use it to filter packets.
+        let track_id = track.id;
+        // Create a decoder for the track.
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &decoder_opts)
+            .unwrap_or_else(|err| format_error(path, err));
+        let params = decoder.codec_params();
+        let channels = stated(path, params.channels, "the number of channels").count();
+        // In PCM n_frames is the same as n_samples, but for each channel
+        let n_frames = stated(path, params.n_frames, "the number of frames");
+        let sr = stated(path, params.sample_rate, "the sample rate");
 
-        // If the packet does not belong to the selected track, skip it.
-        if packet.track_id() != track_id {
-            continue;
-        }
-
-        // Decode the packet into audio samples, ignoring any decode errors.
-        match decoder.decode(&packet) {
-            Ok(audio_buf) => {
-                // The decoded audio samples may now be accessed via the audio buffer if per-channel
-                // slices of samples in their native decoded format is desired. Use-cases where
-                // the samples need to be accessed in an interleaved order or converted into
-                // another sample format, or a byte buffer is required, are covered by copying the
-                // audio buffer into a sample buffer or raw sample buffer, respectively. In the
-                // example below, we will copy the audio buffer into a sample buffer in an
-                // interleaved order while also converting to a f64 sample format.
-
-                // If this is the *first* decoded packet, create a sample buffer matching the
-                // decoded audio buffer format.
-                if sample_buf.is_none() {
-                    // Get the audio buffer specification.
-                    let spec = *audio_buf.spec();
-                    // Get the capacity of the decoded buffer.
-                    let cap = audio_buf.capacity() as u64;
-
-                    // Create the f64 sample buffer.
-                    sample_buf = Some(SampleBuffer::<f64>::new(cap, spec));
+        AudioReader {
+            format,
+            decoder,
+            track_id,
+            channels,
+            n_frames,
+            sr,
+            fresh: true,
+        }
+    }
+
+    /// Name of the sample format of the track, see `sample_format_name`, and its bit depth: the
+    /// codec's bits per sample, or for codecs without one the width of PCM samples or the
+    /// significant bits of decoded integer samples, and `None` for compressed float samples
+    /// such as MP3. Only compressed codecs decode their first packet, and without one, in an empty
+    /// file, the name is `None` with the codec's bits per sample if any.
+    pub fn sample_format(&mut self) -> (Option<String>, Option<u32>) {
+        let bits_per_sample = self.decoder.codec_params().bits_per_sample;
+        // Symphonia decodes every PCM codec to i32 samples, so for PCM the codec names the format.
+        if let Some(name) = pcm_format_name(self.decoder.codec_params().codec) {
+            let bits = bits_per_sample.or_else(|| pcm_bits(&name));
+            return (Some(name), bits);
+        }
+
+        self.fresh = false;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return (None, bits_per_sample);
                 }
+                Err(err) => AudioError::Decode(format!("cannot read a packet: {}", err)).throw(),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let audio_buf = self.decoder.decode(&packet).unwrap_or_else(|err| {
+                AudioError::Decode(format!("cannot decode a packet: {}", err)).throw()
+            });
+            return (
+                Some(sample_format_name(&audio_buf, bits_per_sample)),
+                bits_per_sample.or_else(|| integer_bits(&audio_buf, None)),
+            );
+        }
+    }
+
+    /// Bit depth of the track as in `sample_format`, decoding only when the codec has no bits
+    /// per sample.
+    pub fn bit_depth(&mut self) -> Option<u32> {
+        self.decoder
+            .codec_params()
+            .bits_per_sample
+            .or_else(|| self.sample_format().1)
+    }
+
+    /// Decode `duration` seconds starting at `offset` seconds. Returns a (channels, nsamples) array.
+    /// `norm` is applied after downmixing.
+    pub fn read(
+        &mut self,
+        offset: f64,
+        duration: Option<f64>,
+        mono: bool,
+        norm: &Norm,
+    ) -> Array2<f64> {
+        let (offset_samples, duration_to_decode_samples) = self.segment(offset, duration);
+        let mut arr = Array2::<f64>::zeros((self.channels, duration_to_decode_samples));
+        let stats = self.read_into(offset_samples, &mut arr.view_mut(), &OnError::Error);
 
-                // Copy the decoded audio buffer into the sample buffer in an interleaved format.
-                if let Some(buf) = &mut sample_buf {
-                    buf.copy_interleaved_ref(audio_buf);
-
-                    // The samples may now be access via the `samples()` function.
-                    let mut samples = buf.samples();
-                    let mut ch: usize;
-                    let frames_in_block = u32::try_from(samples.len())
-                        .expect("cannot safely convert u64 to u32")
-                        / channels as u32;
-
-                    if offset_samples >= frames_in_block {
-                        // deal with offset
-                        offset_samples -= frames_in_block;
-                        continue;
-                    } else if offset_samples != 0 {
-                        samples = &samples[(offset_samples as usize) * channels..];
-                        offset_samples = 0;
+        if mono {
+            arr = misc::to_mono_ndarray(&ArrayView2::from(&arr)); // use ArrayView so to_mono_ndarray only creates 1 copy.
+        }
+        norm.apply(&mut arr.view_mut(), stats.full_scale);
+
+        arr
+    }
+
+    /// First frame and number of frames of the segment `read` decodes for `offset` and `duration` in seconds.
+    /// Both are floored to whole frames separately, as librosa does, so the segment starts at frame
+    /// `floor(offset * sr)` and holds `floor(duration * sr)` frames unless the file ends first.
+    pub fn segment(&self, offset: f64, duration: Option<f64>) -> (u64, usize) {
+        if duration.is_some_and(|d| d <= 0.) {
+            AudioError::Offset("duration must be a positive number".to_string()).throw()
+        }
+
+        let sr = self.sr as f64;
+        self.segment_samples(
+            self.offset_frames(offset),
+            duration.map(|d| (d * sr) as u64),
+        )
+    }
+
+    /// First frame of `offset` seconds, floored to a whole frame. A negative offset counts from the
+    /// end of the file, like a negative index in Python: `-2` starts `floor(2 * sr)` frames before the end.
+    pub fn offset_frames(&self, offset: f64) -> u64 {
+        let sr = self.sr as f64;
+        if offset >= 0. {
+            return (offset * sr) as u64;
+        }
+        let from_end = (-offset * sr) as u64;
+        if from_end > self.n_frames {
+            AudioError::Offset(format!(
+                "offset of {} s is before the start of the file, which lasts {} s",
+                offset,
+                self.n_frames as f64 / sr
+            ))
+            .throw()
+        }
+        self.n_frames - from_end
+    }
+
+    /// Like `segment`, with the offset and duration counted in samples per channel, so no
+    /// rounding from seconds is involved. `None` reads to the end of the file.
+    pub fn segment_samples(
+        &self,
+        offset_samples: u64,
+        duration_samples: Option<u64>,
+    ) -> (u64, usize) {
+        if offset_samples >= self.n_frames {
+            AudioError::Offset("offset bigger than or equal to total duration".to_string()).throw()
+        }
+
+        let available = self.n_frames - offset_samples;
+        let duration_to_decode_samples =
+            duration_samples.map_or(available, |d| u64::min(d, available));
+
+        if duration_to_decode_samples == 0 {
+            AudioError::Offset("duration must be a positive number".to_string()).throw()
+        }
+
+        (offset_samples, duration_to_decode_samples as usize)
+    }
+
+    /// First frame and number of frames of a crop of `length_sec` seconds from `start_sec`, both
+    /// rounded to the nearest frame. A crop running past the end of the file is an error, or is
+    /// moved back to end with the file if `clamp` is set.
+    pub fn crop(&self, start_sec: f64, length_sec: f64, clamp: bool) -> (u64, u64) {
+        let sr = self.sr as f64;
+        let length = u64::max((length_sec * sr).round() as u64, 1);
+        let start = (start_sec * sr).round() as u64;
+        if start + length <= self.n_frames {
+            return (start, length);
+        }
+        if !clamp {
+            AudioError::Offset(format!(
+                "segment ends at {} s, past the end of the file at {} s",
+                (start + length) as f64 / sr,
+                self.n_frames as f64 / sr
+            ))
+            .throw()
+        }
+        (self.n_frames.saturating_sub(length), length)
+    }
+
+    /// Decode frames starting at `offset_samples` into `out`, a (channels, nsamples) array,
+    /// until it is full. Frames past the end of the file, or after a truncating decode error,
+    /// are set to zero.
+    /// `out` may be the memory of an R matrix, whose (nsamples, channels) column-major layout is
+    /// exactly a row-major (channels, nsamples) array, so no transposed copy is needed.
+    pub fn read_into(
+        &mut self,
+        offset_samples: u64,
+        out: &mut ArrayViewMut2<f64>,
+        on_error: &OnError,
+    ) -> ReadStats {
+        let channels = self.channels;
+        let duration_to_decode_samples = out.ncols();
+        let bits_per_sample = self.decoder.codec_params().bits_per_sample;
+
+        // Seek unless decoding starts at the beginning of an unread file. The seek lands on a
+        // packet at or before the offset; the samples before the offset are skipped below.
+        if offset_samples > 0 || !self.fresh {
+            self.format
+                .seek(
+                    SeekMode::Accurate,
+                    SeekTo::TimeStamp {
+                        ts: offset_samples,
+                        track_id: self.track_id,
+                    },
+                )
+                .unwrap_or_else(|err| AudioError::Offset(format!("cannot seek: {}", err)).throw());
+            self.decoder.reset();
+        }
+        self.fresh = false;
+
+        let mut sample_buf = None;
+        let mut idx = 0_usize;
+        let mut skipped_packets = 0_usize;
+        let mut scale = 1.;
+        let mut peak = 0_f64;
+
+        'outer: while idx < duration_to_decode_samples {
+            // Get the next packet from the format reader.
+            let packet = match self.format.next_packet() {
+                Ok(packet_ok) => packet_ok,
+                Err(Error::IoError(ref packet_err))
+                    if packet_err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    break;
+                }
+                Err(packet_err) => {
+                    AudioError::Decode(format!("cannot read a packet: {}", packet_err)).throw()
+                }
+            };
+
+            // If the packet does not belong to the selected track, skip it.
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            // Number of frames of this packet that lie before the offset.
+            let skip = offset_samples.saturating_sub(packet.ts()) as usize;
+
+            // Decode the packet into audio samples, handling errors as requested by `on_error`.
+            match self.decoder.decode(&packet) {
+                Ok(audio_buf) => {
+                    // The decoded audio samples may now be accessed via the audio buffer if per-channel
+                    // slices of samples in their native decoded format is desired. Use-cases where
+                    // the samples need to be accessed in an interleaved order or converted into
+                    // another sample format, or a byte buffer is required, are covered by copying the
+                    // audio buffer into a sample buffer or raw sample buffer, respectively. In the
+                    // example below, we will copy the audio buffer into a sample buffer in an
+                    // interleaved order while also converting to a f64 sample format.
+
+                    // If this is the *first* decoded packet, create a sample buffer matching the
+                    // decoded audio buffer format.
+                    if sample_buf.is_none() {
+                        // Get the audio buffer specification.
+                        let spec = *audio_buf.spec();
+                        // Get the capacity of the decoded buffer.
+                        let cap = audio_buf.capacity() as u64;
+
+                        // Create the f64 sample buffer. Float PCM is widened to f64 as is, while integer PCM
+                        // is scaled to [-1.0, 1.0] by symphonia's sample conversion. No other normalization is applied.
+                        sample_buf = Some(SampleBuffer::<f64>::new(cap, spec));
+                        scale = full_scale(&audio_buf, bits_per_sample);
                     }
 
-                    for (n, sample) in samples.iter().enumerate() {
-                        ch = n % channels;
-                        arr[[ch, idx]] = *sample;
+                    // Copy the decoded audio buffer into the sample buffer in an interleaved format.
+                    if let Some(buf) = &mut sample_buf {
+                        buf.copy_interleaved_ref(audio_buf);
 
-                        if ch == channels - 1 {
-                            idx += 1;
-                            duration_to_decode_samples -= 1; // deal with duration_to_decode
+                        // The samples may now be access via the `samples()` function.
+                        let samples = buf.samples();
+                        let frames_in_block = samples.len() / channels;
+
+                        if skip >= frames_in_block {
+                            // deal with offset
+                            continue;
                         }
 
-                        if duration_to_decode_samples == 0 {
-                            // then skip the rest
-                            break 'outer;
+                        for frame in samples[skip * channels..].chunks(channels) {
+                            for (ch, sample) in frame.iter().enumerate() {
+                                out[[ch, idx]] = *sample;
+                                peak = peak.max(sample.abs());
+                            }
+                            idx += 1;
+
+                            if idx == duration_to_decode_samples {
+                                // then skip the rest
+                                break 'outer;
+                            }
                         }
                     }
                 }
+                Err(err) => match on_error {
+                    OnError::Error => AudioError::Decode(err.to_string()).throw(),
+                    OnError::Skip => skipped_packets += 1,
+                    OnError::Truncate => break,
+                },
             }
-            Err(Error::DecodeError(err_str)) => panic!("{}", err_str),
-            Err(_) => break,
         }
-    }
 
-    if mono {
-        arr = misc::to_mono_ndarray(&ArrayView2::from(&arr)); // use ArrayView so to_mono_ndarray only creates 1 copy.
-    }
+        out.slice_mut(s![.., idx..]).fill(0.);
 
-    arr
+        ReadStats {
+            n_decoded: idx,
+            skipped_packets,
+            full_scale: scale,
+            peak,
+        }
+    }
 }
 
-// pub fn get_duration(path: &Path, filetype: &str) -> f64 {
-//     let file = Box::new(File::open(path).expect("cannot open file"));
-
-//     let mss = MediaSourceStream::new(file, Default::default());
+pub struct TrackInfo {
+    pub codec: Option<&'static str>,
+    pub channels: Option<usize>,
+    pub sample_rate: Option<u32>,
+    pub n_frames: Option<u64>,
+}
 
-//     let mut hint = Hint::new();
-//     hint.with_extension(filetype);
+impl TrackInfo {
+    fn of(track: &Track) -> TrackInfo {
+        TrackInfo {
+            codec: symphonia::default::get_codecs()
+                .get_codec(track.codec_params.codec)
+                .map(|descriptor| descriptor.short_name),
+            channels: track.codec_params.channels.map(|channels| channels.count()),
+            sample_rate: track.codec_params.sample_rate,
+            n_frames: track.codec_params.n_frames,
+        }
+    }
 
-//     let format_opts: FormatOptions = Default::default();
-//     let metadata_opts: MetadataOptions = Default::default();
+    /// Duration in seconds, if the header states the number of frames and the sampling rate.
+    pub fn duration(&self) -> Option<f64> {
+        match (self.n_frames, self.sample_rate) {
+            (Some(n), Some(sr)) if sr > 0 => Some(n as f64 / sr as f64),
+            _ => None,
+        }
+    }
+}
 
-//     let probed = symphonia::default::get_probe()
-//         .format(&hint, mss, &format_opts, &metadata_opts)
-//         .expect("unsupported format");
+fn format_error(path: &Path, err: Error) -> ! {
+    unsupported_format(path, err).throw()
+}
 
-//     let format = probed.format;
+fn unsupported_format(path: &Path, err: Error) -> AudioError {
+    AudioError::UnsupportedFormat(format!("unsupported format of {}: {}", path.display(), err))
+}
 
-//     let track = format.default_track().expect("cannot get default_track");
+/// The default track of `format`, the probed container of `path`.
+fn default_track<'a>(path: &Path, format: &'a dyn FormatReader) -> &'a Track {
+    try_default_track(path, format).unwrap_or_else(|err| err.throw())
+}
 
-//     let sr = track
-//         .codec_params
-//         .sample_rate
-//         .expect("cannot retrieve the sample rate");
+fn try_default_track<'a>(
+    path: &Path,
+    format: &'a dyn FormatReader,
+) -> Result<&'a Track, AudioError> {
+    format.default_track().ok_or_else(|| {
+        AudioError::UnsupportedFormat(format!("{} has no audio track", path.display()))
+    })
+}
 
-//     let n_frames = track
-//         .codec_params
-//         .n_frames
-//         .expect("cannot retrieve n_frames");
+/// A codec parameter the header of `path` may leave out, such as the sample rate.
+fn stated<T>(path: &Path, value: Option<T>, what: &str) -> T {
+    value.unwrap_or_else(|| {
+        AudioError::Decode(format!(
+            "the header of {} does not state {}",
+            path.display(),
+            what
+        ))
+        .throw()
+    })
+}
 
-//     n_frames as f64 / (sr as f64) // fix n_frames conversion
-// }
+/// Probe the container of `path`, reading only its header.
+fn probe(path: &Path, filetype: Option<&str>) -> ProbeResult {
+    try_probe(path, filetype).unwrap_or_else(|err| err.throw())
+}
 
-pub fn get_samplerate(path: &Path, filetype: &str) -> u32 {
-    let file = Box::new(File::open(path).expect("cannot open file"));
+/// Like `probe`, with the error of a file that cannot be opened or is not audio as `Err`.
+fn try_probe(path: &Path, filetype: Option<&str>) -> Result<ProbeResult, AudioError> {
+    let file = Box::new(try_open_file(path)?);
 
     let mss = MediaSourceStream::new(file, Default::default());
 
     let mut hint = Hint::new();
-    hint.with_extension(filetype);
+    if let Some(filetype) = filetype {
+        hint.with_extension(filetype);
+    }
 
     let format_opts: FormatOptions = Default::default();
     let metadata_opts: MetadataOptions = Default::default();
 
-    let probed = symphonia::default::get_probe()
+    symphonia::default::get_probe()
         .format(&hint, mss, &format_opts, &metadata_opts)
-        .expect("unsupported format");
+        .map_err(|err| unsupported_format(path, err))
+}
+
+/// Everything the header of a file states about its default track, from a single probe.
+pub struct FileInfo {
+    pub track: TrackInfo,
+    /// Bits per sample stated by the codec or implied by a PCM format, `None` for compressed
+    /// codecs that do not state it, unlike `AudioReader::bit_depth` which decodes a packet.
+    pub bit_depth: Option<u32>,
+    /// Text tags, such as the title or artist, named by their standard key in snake case, e.g.
+    /// `track_title`, or as stored in the file for keys without a standard meaning. Binary tags,
+    /// such as cover art, are left out.
+    pub tags: Vec<(String, String)>,
+}
+
+/// Probe `path` once for `FileInfo`, reading only the header and the metadata in front of it.
+pub fn probe_file(path: &Path, filetype: Option<&str>) -> FileInfo {
+    let mut probed = probe(path, filetype);
+    let track = default_track(path, probed.format.as_ref());
+    let params = &track.codec_params;
+    let bit_depth = params
+        .bits_per_sample
+        .or_else(|| pcm_format_name(params.codec).and_then(|name| pcm_bits(&name)));
+    let track = TrackInfo::of(track);
+
+    let mut tags = Vec::new();
+    let mut add_tags = |revision: Option<&MetadataRevision>| {
+        for tag in revision.map_or(&[][..], |r| r.tags()) {
+            if let Value::Binary(_) = tag.value {
+                continue;
+            }
+            let key = tag
+                .std_key
+                .map_or_else(|| tag.key.clone(), |key| snake_case(&format!("{:?}", key)));
+            tags.push((key, tag.value.to_string()));
+        }
+    };
+    // Tags in front of the container, such as ID3v2, then those of the container itself.
+    if let Some(metadata) = probed.metadata.get() {
+        add_tags(metadata.current());
+    }
+    add_tags(probed.format.metadata().current());
+
+    FileInfo {
+        track,
+        bit_depth,
+        tags,
+    }
+}
+
+/// `TrackTitle` as `track_title`.
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub fn list_tracks(path: &Path, filetype: Option<&str>) -> Vec<TrackInfo> {
+    probe(path, filetype)
+        .format
+        .tracks()
+        .iter()
+        .map(TrackInfo::of)
+        .collect()
+}
+
+/// Header information of the default track, the one `load` decodes, reading only the container.
+/// Files that cannot be probed, such as files that are not audio, are an `Err` rather than a panic,
+/// to be skipped cheaply when scanning many files.
+pub fn default_track_info(path: &Path, filetype: Option<&str>) -> Result<TrackInfo, AudioError> {
+    let probed = try_probe(path, filetype)?;
+    let track = try_default_track(path, probed.format.as_ref())?;
+
+    Ok(TrackInfo::of(track))
+}
+
+// Every audio codec type symphonia knows of, registered or not, in three groups.
+// Linear PCM, the codecs whose samples are stored as is.
+const LINEAR_PCM_CODEC_TYPES: [CodecType; 36] = [
+    codecs::CODEC_TYPE_PCM_S32LE,
+    codecs::CODEC_TYPE_PCM_S32LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_S32BE,
+    codecs::CODEC_TYPE_PCM_S32BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_S24LE,
+    codecs::CODEC_TYPE_PCM_S24LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_S24BE,
+    codecs::CODEC_TYPE_PCM_S24BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_S16LE,
+    codecs::CODEC_TYPE_PCM_S16LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_S16BE,
+    codecs::CODEC_TYPE_PCM_S16BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_S8,
+    codecs::CODEC_TYPE_PCM_S8_PLANAR,
+    codecs::CODEC_TYPE_PCM_U32LE,
+    codecs::CODEC_TYPE_PCM_U32LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_U32BE,
+    codecs::CODEC_TYPE_PCM_U32BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_U24LE,
+    codecs::CODEC_TYPE_PCM_U24LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_U24BE,
+    codecs::CODEC_TYPE_PCM_U24BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_U16LE,
+    codecs::CODEC_TYPE_PCM_U16LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_U16BE,
+    codecs::CODEC_TYPE_PCM_U16BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_U8,
+    codecs::CODEC_TYPE_PCM_U8_PLANAR,
+    codecs::CODEC_TYPE_PCM_F32LE,
+    codecs::CODEC_TYPE_PCM_F32LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_F32BE,
+    codecs::CODEC_TYPE_PCM_F32BE_PLANAR,
+    codecs::CODEC_TYPE_PCM_F64LE,
+    codecs::CODEC_TYPE_PCM_F64LE_PLANAR,
+    codecs::CODEC_TYPE_PCM_F64BE,
+    codecs::CODEC_TYPE_PCM_F64BE_PLANAR,
+];
+
+// PCM of logarithmically companded samples.
+const COMPANDED_PCM_CODEC_TYPES: [CodecType; 2] =
+    [codecs::CODEC_TYPE_PCM_ALAW, codecs::CODEC_TYPE_PCM_MULAW];
+
+// ADPCM and the compressed codecs, lossy or lossless.
+const COMPRESSED_CODEC_TYPES: [CodecType; 27] = [
+    codecs::CODEC_TYPE_ADPCM_G722,
+    codecs::CODEC_TYPE_ADPCM_G726,
+    codecs::CODEC_TYPE_ADPCM_G726LE,
+    codecs::CODEC_TYPE_ADPCM_MS,
+    codecs::CODEC_TYPE_ADPCM_IMA_WAV,
+    codecs::CODEC_TYPE_ADPCM_IMA_QT,
+    codecs::CODEC_TYPE_VORBIS,
+    codecs::CODEC_TYPE_MP1,
+    codecs::CODEC_TYPE_MP2,
+    codecs::CODEC_TYPE_MP3,
+    codecs::CODEC_TYPE_AAC,
+    codecs::CODEC_TYPE_OPUS,
+    codecs::CODEC_TYPE_SPEEX,
+    codecs::CODEC_TYPE_MUSEPACK,
+    codecs::CODEC_TYPE_ATRAC1,
+    codecs::CODEC_TYPE_ATRAC3,
+    codecs::CODEC_TYPE_ATRAC3PLUS,
+    codecs::CODEC_TYPE_ATRAC9,
+    codecs::CODEC_TYPE_EAC3,
+    codecs::CODEC_TYPE_AC4,
+    codecs::CODEC_TYPE_DCA,
+    codecs::CODEC_TYPE_WMA,
+    codecs::CODEC_TYPE_FLAC,
+    codecs::CODEC_TYPE_WAVPACK,
+    codecs::CODEC_TYPE_MONKEYS_AUDIO,
+    codecs::CODEC_TYPE_ALAC,
+    codecs::CODEC_TYPE_TTA,
+];
+
+/// Bits per sample of a PCM format named by `pcm_format_name`, e.g. 32 for "f32", which WAV
+/// files of float samples need not state.
+fn pcm_bits(name: &str) -> Option<u32> {
+    name.trim_start_matches(char::is_alphabetic).parse().ok()
+}
+
+/// Sample format of a PCM codec in the terms of `sample_format_name`, e.g. `"s16"` for
+/// `pcm_s16le`, or `"alaw"` and `"mulaw"`. `None` for the other codecs.
+fn pcm_format_name(codec: CodecType) -> Option<String> {
+    if !LINEAR_PCM_CODEC_TYPES.contains(&codec) && !COMPANDED_PCM_CODEC_TYPES.contains(&codec) {
+        return None;
+    }
+    let short_name = symphonia::default::get_codecs()
+        .get_codec(codec)?
+        .short_name;
+    let name = short_name.strip_prefix("pcm_").unwrap_or(short_name);
+    let name = name.strip_suffix("_planar").unwrap_or(name);
+    let name = name
+        .strip_suffix("le")
+        .or_else(|| name.strip_suffix("be"))
+        .unwrap_or(name);
+    Some(name.to_string())
+}
+
+/// Short names of the codecs registered with the default codec registry.
+pub fn supported_codecs() -> Vec<&'static str> {
+    let registry = symphonia::default::get_codecs();
+    LINEAR_PCM_CODEC_TYPES
+        .iter()
+        .chain(COMPANDED_PCM_CODEC_TYPES.iter())
+        .chain(COMPRESSED_CODEC_TYPES.iter())
+        .filter_map(|codec| {
+            registry
+                .get_codec(*codec)
+                .map(|descriptor| descriptor.short_name)
+        })
+        .collect()
+}
+
+/// Containers registered with the default probe. The probe cannot be enumerated, so this
+/// mirrors the symphonia features the crate is built with.
+pub fn supported_formats() -> Vec<&'static str> {
+    let mut formats = vec!["wav", "flac", "ogg", "mkv"];
+    if cfg!(feature = "isomp4") {
+        formats.push("isomp4");
+    }
+    if cfg!(feature = "aac") {
+        formats.push("adts");
+    }
+    if cfg!(feature = "mp3") {
+        formats.push("mp3");
+    }
+    formats
+}
+
+/// File extensions of the container readers the crate is built with, lower case, sorted and
+/// without duplicates, as the readers declare them to the probe. Extensions only hint at the
+/// format, so a file with another extension may still load.
+pub fn supported_extensions() -> Vec<&'static str> {
+    use symphonia::default::formats;
+
+    // Only extended with the optional containers.
+    #[allow(unused_mut)]
+    let mut descriptors: Vec<&[Descriptor]> = vec![
+        formats::WavReader::query(),
+        formats::FlacReader::query(),
+        formats::OggReader::query(),
+        formats::MkvReader::query(),
+    ];
+    #[cfg(feature = "isomp4")]
+    descriptors.push(formats::IsoMp4Reader::query());
+    #[cfg(feature = "aac")]
+    descriptors.push(formats::AdtsReader::query());
+    #[cfg(feature = "mp3")]
+    descriptors.push(formats::Mp3Reader::query());
+
+    let mut extensions: Vec<&'static str> = descriptors
+        .iter()
+        .flat_map(|d| d.iter())
+        .flat_map(|descriptor| descriptor.extensions.iter().copied())
+        .collect();
+    extensions.sort_unstable();
+    extensions.dedup();
+    extensions
+}
+
+pub fn get_duration(path: &Path, filetype: Option<&str>) -> f64 {
+    let probed = probe(path, filetype);
+
+    let format = probed.format;
+
+    let track = default_track(path, format.as_ref());
+
+    let sr = stated(path, track.codec_params.sample_rate, "the sample rate");
+
+    let n_frames = stated(path, track.codec_params.n_frames, "the number of frames");
+
+    n_frames as f64 / (sr as f64)
+}
+
+/// Whether `codec` reproduces the original samples exactly: linear PCM and the lossless
+/// compressed codecs. A-law and mu-law PCM, ADPCM and the perceptual codecs are lossy.
+pub fn is_lossless_codec(codec: CodecType) -> bool {
+    LINEAR_PCM_CODEC_TYPES.contains(&codec)
+        || [
+            codecs::CODEC_TYPE_FLAC,
+            codecs::CODEC_TYPE_ALAC,
+            codecs::CODEC_TYPE_WAVPACK,
+            codecs::CODEC_TYPE_MONKEYS_AUDIO,
+            codecs::CODEC_TYPE_TTA,
+        ]
+        .contains(&codec)
+}
+
+/// Whether the codec of the default track is lossless. Only the container is probed, so this
+/// also works for codecs the crate cannot decode.
+pub fn is_lossless(path: &Path, filetype: Option<&str>) -> bool {
+    let probed = probe(path, filetype);
 
     let format = probed.format;
 
-    let track = format.default_track().expect("cannot get default_track");
+    let track = default_track(path, format.as_ref());
 
-    track
-        .codec_params
-        .sample_rate
-        .expect("cannot retrieve the sample rate")
+    is_lossless_codec(track.codec_params.codec)
+}
+
+pub fn get_samplerate(path: &Path, filetype: Option<&str>) -> u32 {
+    let probed = probe(path, filetype);
+
+    let format = probed.format;
+
+    let track = default_track(path, format.as_ref());
+
+    stated(path, track.codec_params.sample_rate, "the sample rate")
 }
 
 //pub fn stream(
@@ -241,3 +905,364 @@ pub fn get_samplerate(path: &Path, filetype: &str) -> u32 {
 //
 //    arr
 //}
+
+#[cfg(test)]
+mod test_load {
+    use super::*;
+
+    #[test]
+    fn test_load_f32_wav() {
+        // float32.wav holds 0.5 * sin(2 * pi * 440 * t) at 8000 Hz, stored as 32-bit IEEE float.
+        let path = Path::new("../../test_files/float32.wav");
+        let decoded_arr = load(
+            path,
+            false,
+            0.,
+            None,
+            None,
+            false,
+            &Norm::FullScale,
+            Some("wav"),
+        );
+
+        assert_eq!(decoded_arr.dim(), (1, 1000));
+        for i in 0..16 {
+            let expected =
+                (0.5 * (2. * std::f64::consts::PI * 440. * i as f64 / 8000.).sin()) as f32;
+            assert_eq!(decoded_arr[[0, i]], expected as f64);
+        }
+    }
+    #[test]
+    fn test_reader_segments() {
+        let path = Path::new("../../test_files/float32.wav");
+        let full = load(
+            path,
+            false,
+            0.,
+            None,
+            None,
+            false,
+            &Norm::FullScale,
+            Some("wav"),
+        );
+
+        // Read a later segment first so the second read has to seek backwards.
+        let mut reader = AudioReader::open(path, None, false, Some("wav"));
+        let late = reader.read(0.1, Some(0.01), false, &Norm::FullScale);
+        let early = reader.read(0.02, Some(0.05), false, &Norm::FullScale);
+
+        assert_eq!(late, full.slice(ndarray::s![.., 800..880]));
+        assert_eq!(early, full.slice(ndarray::s![.., 160..560]));
+    }
+
+    #[test]
+    fn test_read_into_buffer_rows() {
+        // The leading rows of a larger (rows, channels) R matrix, as filled by `load_into`.
+        let path = Path::new("../../test_files/float32.wav");
+        let full = load(
+            path,
+            false,
+            0.,
+            None,
+            None,
+            false,
+            &Norm::FullScale,
+            Some("wav"),
+        );
+        let channels = full.nrows();
+        let mut buffer = vec![7.; channels * 500];
+        let mut arr = ArrayViewMut2::from_shape((channels, 500), &mut buffer[..]).unwrap();
+        let mut reader = AudioReader::open(path, None, false, Some("wav"));
+        let stats = reader.read_into(
+            160,
+            &mut arr.slice_mut(ndarray::s![.., ..400]),
+            &OnError::Error,
+        );
+
+        assert_eq!(
+            arr.slice(ndarray::s![.., ..400]),
+            full.slice(ndarray::s![.., 160..560])
+        );
+        assert!(arr.slice(ndarray::s![.., 400..]).iter().all(|v| *v == 7.));
+        let peak = arr
+            .slice(ndarray::s![.., ..400])
+            .fold(0., |acc: f64, v| acc.max(v.abs()));
+        assert_eq!(stats.peak, peak);
+    }
+
+    #[test]
+    fn test_norm() {
+        // mono.wav is 16-bit PCM
+        let path = Path::new("../../test_files/mono.wav");
+        let full = load(
+            path,
+            false,
+            0.,
+            Some(0.1),
+            None,
+            false,
+            &Norm::FullScale,
+            Some("wav"),
+        );
+        let raw = load(
+            path,
+            false,
+            0.,
+            Some(0.1),
+            None,
+            false,
+            &Norm::None,
+            Some("wav"),
+        );
+        let peak = load(
+            path,
+            false,
+            0.,
+            Some(0.1),
+            None,
+            false,
+            &Norm::Peak,
+            Some("wav"),
+        );
+
+        assert_eq!(raw, &full * 32768.);
+        assert!(raw
+            .iter()
+            .all(|v| v.fract() == 0. && (-32768. ..32768.).contains(v)));
+        let max = full.fold(0., |acc: f64, v| acc.max(v.abs()));
+        assert_eq!(peak.fold(0., |acc: f64, v| acc.max(v.abs())), 1.);
+        assert_eq!(peak, &full / max);
+    }
+
+    #[test]
+    fn test_load_without_extension() {
+        let path = std::env::temp_dir().join("audiotest_test_load_without_extension");
+        std::fs::copy("../../test_files/mono.wav", &path).unwrap();
+        let probed = load(&path, false, 0., None, None, false, &Norm::FullScale, None);
+        let hinted = load(
+            Path::new("../../test_files/mono.wav"),
+            false,
+            0.,
+            None,
+            None,
+            false,
+            &Norm::FullScale,
+            Some("wav"),
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(probed, hinted);
+    }
+
+    #[test]
+    fn test_sample_format() {
+        let open = |fname| AudioReader::open(Path::new(fname), None, false, Some("wav"));
+        assert_eq!(
+            open("../../test_files/mono.wav").sample_format(),
+            (Some("s16".to_string()), Some(16))
+        );
+        assert_eq!(
+            open("../../test_files/float32.wav").sample_format(),
+            (Some("f32".to_string()), Some(32))
+        );
+    }
+
+    #[test]
+    fn test_default_track_info() {
+        let info = default_track_info(Path::new("../../test_files/mono.wav"), Some("wav")).unwrap();
+        assert_eq!(info.channels, Some(1));
+        let sr = get_samplerate(Path::new("../../test_files/mono.wav"), Some("wav"));
+        assert_eq!(info.sample_rate, Some(sr));
+        assert_eq!(
+            info.duration(),
+            Some(get_duration(
+                Path::new("../../test_files/mono.wav"),
+                Some("wav")
+            ))
+        );
+        assert_eq!(info.codec, Some("pcm_s16le"));
+
+        let not_audio = default_track_info(Path::new("Cargo.toml"), None);
+        assert!(matches!(not_audio, Err(AudioError::UnsupportedFormat(_))));
+        let missing = default_track_info(Path::new("../../test_files/missing.wav"), Some("wav"));
+        assert!(matches!(missing, Err(AudioError::Io(_))));
+    }
+
+    #[test]
+    fn test_probe_file() {
+        let info = probe_file(Path::new("../../test_files/float32.wav"), Some("wav"));
+        assert_eq!(info.track.sample_rate, Some(8000));
+        assert_eq!(info.track.n_frames, Some(1000));
+        assert_eq!(info.bit_depth, Some(32));
+        assert!(info.tags.is_empty());
+        assert_eq!(snake_case("TrackTitle"), "track_title");
+    }
+
+    #[test]
+    fn test_is_lossless() {
+        assert!(is_lossless(
+            Path::new("../../test_files/mono.wav"),
+            Some("wav")
+        ));
+        assert!(is_lossless(
+            Path::new("../../test_files/float32.wav"),
+            Some("wav")
+        ));
+        assert!(is_lossless_codec(codecs::CODEC_TYPE_PCM_F64BE_PLANAR));
+        assert!(is_lossless_codec(codecs::CODEC_TYPE_FLAC));
+        assert!(!is_lossless_codec(codecs::CODEC_TYPE_PCM_MULAW));
+        assert!(!is_lossless_codec(codecs::CODEC_TYPE_MP3));
+        assert!(!is_lossless_codec(codecs::CODEC_TYPE_VORBIS));
+    }
+
+    #[test]
+    fn test_supported_codecs() {
+        let codecs = supported_codecs();
+        assert!(codecs.contains(&"pcm_f32le"));
+        assert_eq!(codecs.contains(&"mp3"), cfg!(feature = "mp3"));
+
+        let extensions = supported_extensions();
+        for ext in ["wav", "flac", "ogg", "mkv"] {
+            assert!(extensions.contains(&ext), "{}", ext);
+        }
+        assert_eq!(extensions.contains(&"mp3"), cfg!(feature = "mp3"));
+        assert!(extensions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_segment_samples() {
+        let path = Path::new("../../test_files/float32.wav");
+        let full = load(
+            path,
+            false,
+            0.,
+            None,
+            None,
+            false,
+            &Norm::FullScale,
+            Some("wav"),
+        );
+
+        let mut reader = AudioReader::open(path, None, false, Some("wav"));
+        assert_eq!(reader.segment_samples(123, Some(100)), (123, 100));
+        assert_eq!(reader.segment_samples(950, Some(100)), (950, 50));
+        assert_eq!(reader.segment_samples(950, None), (950, 50));
+
+        let mut arr = Array2::<f64>::zeros((1, 100));
+        reader.read_into(123, &mut arr.view_mut(), &OnError::Error);
+        assert_eq!(arr, full.slice(ndarray::s![.., 123..223]));
+    }
+
+    #[test]
+    fn test_segment_matches_librosa() {
+        // librosa seeks to `int(offset * sr)` and reads `int(duration * sr)` frames.
+        for (file, filetype) in [("float32.wav", "wav"), ("mono.wav", "wav")] {
+            let path = Path::new("../../test_files").join(file);
+            let full = load(
+                &path,
+                false,
+                0.,
+                None,
+                None,
+                false,
+                &Norm::FullScale,
+                Some(filetype),
+            );
+            let mut reader = AudioReader::open(&path, None, false, Some(filetype));
+            let sr = reader.sr as f64;
+            let n_frames = full.ncols();
+            assert_eq!(reader.n_frames, n_frames as u64);
+            let file_duration = n_frames as f64 / sr;
+
+            let mut windows = vec![(0., None), (0.0013, None), (0.1, Some(0.01))];
+            for fraction in [0.013, 0.1, 0.377, 0.7, 0.999] {
+                windows.push((fraction * file_duration, None));
+                windows.push((fraction * file_duration, Some(0.0171)));
+                windows.push((fraction * file_duration, Some(0.7 * file_duration)));
+            }
+            for (offset, duration) in windows {
+                let start = (offset * sr) as usize;
+                let length = duration.map_or(n_frames - start, |d: f64| {
+                    usize::min((d * sr) as usize, n_frames - start)
+                });
+                assert_eq!(
+                    reader.segment(offset, duration),
+                    (start as u64, length),
+                    "{} {} {:?}",
+                    file,
+                    offset,
+                    duration
+                );
+
+                let segment = reader.read(offset, duration, false, &Norm::FullScale);
+                assert_eq!(segment, full.slice(ndarray::s![.., start..start + length]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_offset() {
+        let path = Path::new("../../test_files/float32.wav");
+        let reader = AudioReader::open(path, None, false, Some("wav"));
+        // 1000 frames at 8000 Hz.
+        assert_eq!(reader.offset_frames(-0.01), 920);
+        assert_eq!(reader.segment(-0.01, None), (920, 80));
+        assert_eq!(reader.segment(-0.1, Some(0.05)), (200, 400));
+        assert_eq!(reader.segment(-0.125, None), (0, 1000));
+
+        let err =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| reader.offset_frames(-0.2)))
+                .unwrap_err();
+        assert_eq!(
+            crate::error::downcast(err.as_ref()).map(|e| e.class()),
+            Some("audiotest_offset_error")
+        );
+    }
+
+    /// Write `n_frames` of mono 32-bit float silence as a WAV file.
+    fn write_f32_wav(path: &Path, n_frames: u32, sr: u32) {
+        use std::io::Write;
+
+        let data_len = n_frames * 4;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(36 + data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16_u32.to_le_bytes());
+        header.extend_from_slice(&3_u16.to_le_bytes()); // IEEE float
+        header.extend_from_slice(&1_u16.to_le_bytes());
+        header.extend_from_slice(&sr.to_le_bytes());
+        header.extend_from_slice(&(sr * 4).to_le_bytes());
+        header.extend_from_slice(&4_u16.to_le_bytes());
+        header.extend_from_slice(&32_u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_len.to_le_bytes());
+
+        let mut file = File::create(path).expect("cannot create file");
+        file.write_all(&header).expect("cannot write header");
+        file.write_all(&vec![0_u8; data_len as usize])
+            .expect("cannot write data");
+    }
+
+    // Seek-heavy benchmark of buffered versus memory-mapped reads.
+    // Run with `cargo test --release -- --ignored --nocapture bench_mmap_seeks`.
+    #[test]
+    #[ignore]
+    fn bench_mmap_seeks() {
+        let sr = 44100;
+        let path = std::env::temp_dir().join("audiotest_bench_mmap.wav");
+        write_f32_wav(&path, 10 * 60 * sr, sr);
+
+        for mmap in [false, true] {
+            let start = std::time::Instant::now();
+            let mut reader = AudioReader::open(&path, None, mmap, Some("wav"));
+            for i in 0..1000_u64 {
+                // Pseudo-random offsets spread over the whole file.
+                let offset = (i * 7919 % 5900) as f64 / 10.;
+                reader.read(offset, Some(0.1), false, &Norm::FullScale);
+            }
+            println!("mmap = {}: {:?} for 1000 reads", mmap, start.elapsed());
+        }
+
+        std::fs::remove_file(&path).expect("cannot remove file");
+    }
+}
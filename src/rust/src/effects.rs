@@ -0,0 +1,386 @@
+use std::f64::consts::FRAC_PI_2;
+
+use ndarray::{s, stack, Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1, Axis};
+
+/// How gains are interpolated between the control points of an envelope.
+pub enum Interp {
+    /// Ramp linearly from one control point to the next.
+    Linear,
+    /// Hold each control point's gain until the next one.
+    Step,
+}
+
+impl Interp {
+    pub fn from_name(name: &str) -> Interp {
+        match name {
+            "linear" => Interp::Linear,
+            "step" => Interp::Step,
+            _ => panic!("interp must be either \"linear\" or \"step\""),
+        }
+    }
+}
+
+/// Per-sample gains for `n` samples at `sr`, from control points `(times, gains)` in seconds.
+/// `times` must be sorted and lie within the clip. Samples before the first or after the last
+/// control point keep that point's gain.
+pub fn gain_envelope(
+    times: &[f64],
+    gains: &[f64],
+    sr: u32,
+    n: usize,
+    interp: &Interp,
+) -> Array1<f64> {
+    if times.len() != gains.len() {
+        panic!("times and gains must have the same length");
+    }
+    if times.is_empty() {
+        panic!("at least one control point is needed");
+    }
+    if times.iter().chain(gains).any(|v| v.is_nan()) {
+        panic!("times and gains must not be NA");
+    }
+    if times.windows(2).any(|w| w[0] > w[1]) {
+        panic!("times must be sorted");
+    }
+    let duration = n as f64 / sr as f64;
+    if times[0] < 0. || times[times.len() - 1] > duration {
+        panic!(
+            "times must be within the clip, i.e. between 0 and {} seconds",
+            duration
+        );
+    }
+
+    // index of the last control point at or before the current sample
+    let mut j = 0;
+    Array1::from_shape_fn(n, |i| {
+        let t = i as f64 / sr as f64;
+        while j + 1 < times.len() && times[j + 1] <= t {
+            j += 1;
+        }
+        if t < times[0] || j + 1 == times.len() {
+            return if t < times[0] { gains[0] } else { gains[j] };
+        }
+        match interp {
+            Interp::Linear => {
+                let frac = (t - times[j]) / (times[j + 1] - times[j]);
+                gains[j] + (gains[j + 1] - gains[j]) * frac
+            }
+            Interp::Step => gains[j],
+        }
+    })
+}
+
+/// Multiply every channel of a (channels, nsamples) array by a per-sample `envelope`.
+pub fn apply_gain(arr: &ArrayView2<f64>, envelope: &Array1<f64>) -> Array2<f64> {
+    arr * &envelope.view().insert_axis(Axis(0))
+}
+
+fn check_stereo(arr: &ArrayView2<f64>) {
+    if arr.nrows() != 2 {
+        panic!("audio must have exactly two channels, got {}", arr.nrows());
+    }
+}
+
+/// Convert a (2, nsamples) left/right array to mid (L + R) / 2 and side (L - R) / 2.
+pub fn to_midside(arr: &ArrayView2<f64>) -> Array2<f64> {
+    check_stereo(arr);
+    let (left, right) = (arr.row(0), arr.row(1));
+    let (mid, side) = ((&left + &right) / 2., (&left - &right) / 2.);
+    stack(Axis(0), &[mid.view(), side.view()]).expect("cannot stack channels")
+}
+
+/// Inverse of `to_midside`: left M + S and right M - S.
+pub fn from_midside(arr: &ArrayView2<f64>) -> Array2<f64> {
+    check_stereo(arr);
+    let (mid, side) = (arr.row(0), arr.row(1));
+    let (left, right) = (&mid + &side, &mid - &side);
+    stack(Axis(0), &[left.view(), right.view()]).expect("cannot stack channels")
+}
+
+/// Scale the side channel of a (2, nsamples) left/right array by `width`: 0 collapses it to
+/// mono, 1 leaves it unchanged and larger values widen the stereo image.
+pub fn adjust_width(arr: &ArrayView2<f64>, width: f64) -> Array2<f64> {
+    let mut midside = to_midside(arr);
+    midside.row_mut(1).mapv_inplace(|v| v * width);
+    from_midside(&midside.view())
+}
+
+/// Place a mono signal in the stereo field with the equal-power pan law: `position` -1 is hard
+/// left, 0 the center, where each channel gets `cos(pi / 4)`, and 1 hard right. `positions` holds
+/// either one position for the whole signal or one per sample. Returns a (2, nsamples) array.
+pub fn pan(x: &ArrayView1<f64>, positions: &[f64]) -> Array2<f64> {
+    let mut out = Array2::<f64>::zeros((2, x.len()));
+    for (i, v) in x.iter().enumerate() {
+        let position = if positions.len() == 1 {
+            positions[0]
+        } else {
+            positions[i]
+        };
+        let angle = (position + 1.) / 2. * FRAC_PI_2;
+        out[[0, i]] = v * angle.cos();
+        out[[1, i]] = v * angle.sin();
+    }
+
+    out
+}
+
+/// Channels of a (channels, nsamples) array picked by 0-based `mapping`, which may reorder,
+/// drop or repeat them. Returns a (mapping.len(), nsamples) array.
+pub fn remap_channels(arr: &ArrayView2<f64>, mapping: &[usize]) -> Array2<f64> {
+    arr.select(Axis(0), mapping)
+}
+
+/// Join two (channels, nsamples) arrays, overlapping the last `n_fade` samples of `a` with the
+/// first `n_fade` samples of `b`. The overlap uses equal-power curves, cos and sin of a quarter
+/// turn, so uncorrelated material keeps its loudness through the fade.
+/// Returns a (channels, a.ncols() + b.ncols() - n_fade) array.
+pub fn crossfade(a: &ArrayView2<f64>, b: &ArrayView2<f64>, n_fade: usize) -> Array2<f64> {
+    if a.nrows() != b.nrows() {
+        panic!("a and b must have the same number of channels");
+    }
+    if n_fade > a.ncols() || n_fade > b.ncols() {
+        panic!("fade_duration must not be longer than a or b");
+    }
+    let (na, nb) = (a.ncols(), b.ncols());
+
+    let mut out = Array2::<f64>::zeros((a.nrows(), na + nb - n_fade));
+    out.slice_mut(s![.., ..na]).assign(a);
+    for i in 0..n_fade {
+        let t = (i as f64 + 0.5) / n_fade as f64 * FRAC_PI_2;
+        let (fade_out, fade_in) = (t.cos(), t.sin());
+        let mut column = out.column_mut(na - n_fade + i);
+        column *= fade_out;
+        column.scaled_add(fade_in, &b.column(i));
+    }
+    out.slice_mut(s![.., na..])
+        .assign(&b.slice(s![.., n_fade..]));
+
+    out
+}
+
+/// One-pole smoothing coefficient reaching 1 - 1/e of a step after `time` seconds at `sr`.
+fn one_pole(time: f64, sr: u32) -> f64 {
+    if time > 0. {
+        (-1. / (time * sr as f64)).exp()
+    } else {
+        0.
+    }
+}
+
+/// Smooth `x` in place with a one-pole filter using the `attack` coefficient while it rises and
+/// the `release` coefficient while it falls, starting from 0.
+fn follow(mut x: ArrayViewMut1<f64>, attack: f64, release: f64) {
+    let mut state = 0.;
+    for v in x.iter_mut() {
+        let coef = if *v > state { attack } else { release };
+        state = coef * state + (1. - coef) * *v;
+        *v = state;
+    }
+}
+
+/// Amplitude envelope of each channel of a (channels, nsamples) array: the rectified signal
+/// followed by a one-pole filter that rises with the `attack` and falls with the `release`
+/// time constant, both in seconds. Returns an array of the same shape.
+pub fn amplitude_envelope(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    attack: f64,
+    release: f64,
+) -> Array2<f64> {
+    let (attack, release) = (one_pole(attack, sr), one_pole(release, sr));
+    let mut out = arr.mapv(f64::abs);
+    for channel in out.outer_iter_mut() {
+        follow(channel, attack, release);
+    }
+    out
+}
+
+/// Feed-forward compressor for a (channels, nsamples) array. The detector takes the peak over
+/// the channels, so they all get the same gain and the stereo image is kept. Above
+/// `threshold_db`, the level is reduced by `1 - 1 / ratio` dB per dB; the reduction is smoothed
+/// with the `attack` time constant while it grows and `release` while it shrinks, in seconds,
+/// and `makeup_db` is added to the gain of every sample.
+pub fn compress(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    threshold_db: f64,
+    ratio: f64,
+    attack: f64,
+    release: f64,
+    makeup_db: f64,
+) -> Array2<f64> {
+    let slope = 1. - 1. / ratio;
+    let mut reduction_db = Array1::from_shape_fn(arr.ncols(), |i| {
+        let level = arr.column(i).fold(0., |peak: f64, v| peak.max(v.abs()));
+        if level > 0. {
+            f64::max(20. * level.log10() - threshold_db, 0.) * slope
+        } else {
+            0.
+        }
+    });
+    follow(
+        reduction_db.view_mut(),
+        one_pole(attack, sr),
+        one_pole(release, sr),
+    );
+
+    let gains = reduction_db.mapv(|r| 10_f64.powf((makeup_db - r) / 20.));
+    apply_gain(arr, &gains)
+}
+
+/// Curve of the `soft_clip` saturation.
+pub enum Saturation {
+    /// `tanh(x)`, the smoothest, approaching the limits asymptotically.
+    Tanh,
+    /// `2 / pi * atan(x)`, approaching the limits more slowly than `tanh`.
+    Atan,
+    /// `1.5 * (x - x^3 / 3)` up to `|x| = 1` and the limit beyond, reaching it with zero slope.
+    Cubic,
+}
+
+impl Saturation {
+    pub fn from_name(name: &str) -> Saturation {
+        match name {
+            "tanh" => Saturation::Tanh,
+            "atan" => Saturation::Atan,
+            "cubic" => Saturation::Cubic,
+            _ => panic!("kind must be one of \"tanh\", \"atan\" or \"cubic\""),
+        }
+    }
+
+    pub fn apply(&self, x: f64) -> f64 {
+        match self {
+            Saturation::Tanh => x.tanh(),
+            Saturation::Atan => x.atan() / FRAC_PI_2,
+            Saturation::Cubic => {
+                let x = x.clamp(-1., 1.);
+                1.5 * (x - x * x * x / 3.)
+            }
+        }
+    }
+}
+
+/// Saturate every sample of `arr` after multiplying it by `drive`. The curves are odd and
+/// bounded by 1, so the result stays within [-1, 1]: a larger `drive` pushes more of the signal
+/// into the bend of the curve, adding more harmonics.
+pub fn soft_clip(arr: &ArrayView2<f64>, drive: f64, kind: &Saturation) -> Array2<f64> {
+    arr.mapv(|v| kind.apply(drive * v))
+}
+
+#[cfg(test)]
+mod test_effects {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_gain_envelope() {
+        let linear = gain_envelope(&[0.25, 0.75], &[1., 0.], 8, 8, &Interp::Linear);
+        assert_eq!(linear, array![1., 1., 1., 0.75, 0.5, 0.25, 0., 0.]);
+
+        let step = gain_envelope(&[0., 0.5], &[0.5, 2.], 8, 8, &Interp::Step);
+        assert_eq!(step, array![0.5, 0.5, 0.5, 0.5, 2., 2., 2., 2.]);
+
+        let arr = array![[1., 1., 1., 1.], [2., 2., 2., 2.]];
+        let ducked = apply_gain(&arr.view(), &array![1., 0.5, 0.5, 1.]);
+        assert_eq!(ducked, array![[1., 0.5, 0.5, 1.], [2., 1., 1., 2.]]);
+    }
+
+    #[test]
+    fn test_midside() {
+        let arr = array![[1., 0.5, -1.], [0., 0.5, 1.]];
+        let midside = to_midside(&arr.view());
+        assert_eq!(midside, array![[0.5, 0.5, 0.], [0.5, 0., -1.]]);
+        assert_eq!(from_midside(&midside.view()), arr);
+
+        assert_eq!(adjust_width(&arr.view(), 1.), arr);
+        let mono = adjust_width(&arr.view(), 0.);
+        assert_eq!(mono.row(0), mono.row(1));
+    }
+
+    #[test]
+    fn test_pan() {
+        let x = array![1., 1., 1.];
+        let out = pan(&x.view(), &[-1., 0., 1.]);
+        let center = 0.5_f64.sqrt();
+        assert!((out[[0, 0]] - 1.).abs() < 1e-12 && out[[1, 0]].abs() < 1e-12);
+        assert!((out[[0, 1]] - center).abs() < 1e-12 && (out[[1, 1]] - center).abs() < 1e-12);
+        assert!(out[[0, 2]].abs() < 1e-12 && (out[[1, 2]] - 1.).abs() < 1e-12);
+        // Equal power everywhere.
+        let out = pan(&x.view(), &[0.3]);
+        assert!((out.mapv(|v| v * v).sum() - 3.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_remap_channels() {
+        let arr = array![[1., 2.], [3., 4.], [5., 6.]];
+        assert_eq!(
+            remap_channels(&arr.view(), &[2, 0, 0]),
+            array![[5., 6.], [1., 2.], [1., 2.]]
+        );
+    }
+
+    #[test]
+    fn test_amplitude_envelope() {
+        let mut x = Array2::<f64>::zeros((1, 2000));
+        x.slice_mut(s![.., ..1000]).fill(-1.);
+        // one time constant is 100 samples of attack and 200 of release
+        let envelope = amplitude_envelope(&x.view(), 10000, 0.01, 0.02);
+        let e = (-1_f64).exp();
+        assert!((envelope[[0, 99]] - (1. - e)).abs() < 1e-3);
+        assert!(envelope[[0, 999]] > 0.9999);
+        assert!((envelope[[0, 1199]] - envelope[[0, 999]] * e).abs() < 1e-3);
+
+        let instant = amplitude_envelope(&x.view(), 10000, 0., 0.);
+        assert_eq!(instant, x.mapv(f64::abs));
+    }
+
+    #[test]
+    fn test_compress() {
+        // 0 dBFS is 20 dB above the threshold and settles 15 dB lower at 4:1
+        let loud = Array2::<f64>::ones((2, 4000));
+        let out = compress(&loud.view(), 8000, -20., 4., 0.005, 0.05, 0.);
+        let expected = 10_f64.powf(-15. / 20.);
+        assert!((out[[0, 3999]] - expected).abs() < 1e-6);
+        assert_eq!(out.row(0), out.row(1));
+
+        // below the threshold only the makeup gain applies
+        let quiet = Array2::<f64>::from_elem((1, 100), 0.01);
+        let out = compress(&quiet.view(), 8000, -20., 4., 0.005, 0.05, 6.);
+        assert!(out
+            .iter()
+            .all(|v| (v - 0.01 * 10_f64.powf(6. / 20.)).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let a = Array2::<f64>::ones((2, 10));
+        let b = Array2::<f64>::from_elem((2, 6), 2.);
+        let joined = crossfade(&a.view(), &b.view(), 4);
+
+        assert_eq!(joined.dim(), (2, 12));
+        assert_eq!(joined.slice(s![.., ..6]), a.slice(s![.., ..6]));
+        assert_eq!(joined.slice(s![.., 10..]), b.slice(s![.., 4..]));
+        // the gains of both clips have unit power throughout the fade
+        for i in 6..10 {
+            let t = (i as f64 - 5.5) / 4. * FRAC_PI_2;
+            assert!((joined[[0, i]] - (t.cos() + 2. * t.sin())).abs() < 1e-12);
+        }
+        assert_eq!(crossfade(&a.view(), &b.view(), 0).ncols(), 16);
+    }
+
+    #[test]
+    fn test_soft_clip() {
+        let arr = array![[-100., -1., 0., 0.25, 1., 100.]];
+        for kind in [Saturation::Tanh, Saturation::Atan, Saturation::Cubic] {
+            let out = soft_clip(&arr.view(), 2., &kind);
+            assert!(out.iter().all(|v| v.abs() <= 1.));
+            assert_eq!(out[[0, 2]], 0.);
+            assert_eq!(out[[0, 0]], -out[[0, 5]]);
+            // Monotonic, so the waveform keeps its shape.
+            assert!(out.row(0).windows(2).into_iter().all(|w| w[0] <= w[1]));
+        }
+        let cubic = soft_clip(&arr.view(), 2., &Saturation::Cubic);
+        assert_eq!(cubic.row(0), array![-1., -1., 0., 0.6875, 1., 1.]);
+        assert!((Saturation::Tanh.apply(0.5) - 0.5_f64.tanh()).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,496 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use ndarray::{Array2, ArrayView2};
+use symphonia::core::checksum::Md5;
+use symphonia::core::io::Monitor;
+
+/// Samples per frame. The last frame holds the remainder.
+const BLOCK_SIZE: usize = 4096;
+
+/// Largest rice parameter of the 4-bit partitioned rice coding, 15 being the escape code.
+const MAX_RICE_PARAM: u32 = 14;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    n_bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            acc: 0,
+            n_bits: 0,
+        }
+    }
+
+    /// Write the `n` (at most 32) low bits of `value`, most significant first.
+    fn write(&mut self, value: u64, n: u32) {
+        if n == 0 {
+            return;
+        }
+        self.acc = (self.acc << n) | (value & ((1 << n) - 1));
+        self.n_bits += n;
+        while self.n_bits >= 8 {
+            self.n_bits -= 8;
+            self.bytes.push((self.acc >> self.n_bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, n: u32) {
+        self.write(value as u64, n);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        for _ in 0..zeros / 32 {
+            self.write(0, 32);
+        }
+        self.write(1, (zeros % 32) as u32 + 1);
+    }
+
+    fn len_bits(&self) -> u64 {
+        8 * self.bytes.len() as u64 + self.n_bits as u64
+    }
+
+    fn append(&mut self, other: &BitWriter) {
+        for byte in &other.bytes {
+            self.write(*byte as u64, 8);
+        }
+        self.write(other.acc, other.n_bits);
+    }
+
+    /// Pad with zero bits to the next byte boundary.
+    fn align(&mut self) {
+        if self.n_bits > 0 {
+            self.write(0, 8 - self.n_bits);
+        }
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0_u8, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0_u16, |mut crc, byte| {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Frame number in FLAC's extended UTF-8 coding.
+fn write_utf8(writer: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        writer.write(value, 8);
+        return;
+    }
+    let n_bytes = (2..=7)
+        .find(|n| value < 1 << (5 * n + 1))
+        .expect("frame number is too large");
+    let lead = (0xff_u64 << (8 - n_bytes)) & 0xff;
+    writer.write(lead | (value >> (6 * (n_bytes - 1))), 8);
+    for i in (0..n_bytes - 1).rev() {
+        writer.write(0x80 | ((value >> (6 * i)) & 0x3f), 8);
+    }
+}
+
+/// Search space of the encoder for a `compression` level from 0 (fastest) to 8 (smallest).
+struct Settings {
+    max_fixed_order: usize,
+    max_partition_order: u32,
+    stereo_decorrelation: bool,
+}
+
+impl Settings {
+    fn from_level(compression: u32) -> Self {
+        Settings {
+            max_fixed_order: if compression < 3 { 2 } else { 4 },
+            max_partition_order: u32::min(compression, 6),
+            stereo_decorrelation: compression > 0,
+        }
+    }
+}
+
+/// Residuals of the fixed polynomial predictor of `order`, starting at sample `order`.
+fn fixed_residuals(x: &[i64], order: usize) -> Vec<i64> {
+    (order..x.len())
+        .map(|i| match order {
+            0 => x[i],
+            1 => x[i] - x[i - 1],
+            2 => x[i] - 2 * x[i - 1] + x[i - 2],
+            3 => x[i] - 3 * x[i - 1] + 3 * x[i - 2] - x[i - 3],
+            _ => x[i] - 4 * x[i - 1] + 6 * x[i - 2] - 4 * x[i - 3] + x[i - 4],
+        })
+        .collect()
+}
+
+fn zigzag(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// Cheapest rice parameter of a partition and its size in bits, parameter included.
+fn rice_partition(values: &[u64]) -> (u32, u64) {
+    let sum: u64 = values.iter().sum();
+    let mean = sum / u64::max(values.len() as u64, 1);
+    // The optimum is within one of log2 of the mean.
+    let estimate = (64 - mean.leading_zeros()).saturating_sub(1);
+    (estimate.saturating_sub(1)..=estimate + 1)
+        .map(|k| u32::min(k, MAX_RICE_PARAM))
+        .map(|k| {
+            let bits: u64 = values.iter().map(|u| (u >> k) + 1 + k as u64).sum();
+            (k, bits + 4)
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .expect("cannot choose a rice parameter")
+}
+
+/// Rice parameters of the cheapest partitioning of the residuals of a block of `block_size`, with its size in bits.
+fn rice_partitions(
+    residuals: &[u64],
+    block_size: usize,
+    order: usize,
+    max_partition_order: u32,
+) -> (u32, Vec<u32>, u64) {
+    (0..=max_partition_order)
+        .filter(|p| block_size & ((1 << p) - 1) == 0 && block_size >> p > order)
+        .map(|p| {
+            let partition_len = block_size >> p;
+            let mut start = 0;
+            let mut params = Vec::new();
+            let mut bits = 0;
+            for i in 0..1 << p {
+                let len = if i == 0 {
+                    partition_len - order
+                } else {
+                    partition_len
+                };
+                let (k, partition_bits) = rice_partition(&residuals[start..start + len]);
+                params.push(k);
+                bits += partition_bits;
+                start += len;
+            }
+            (p, params, bits + 6)
+        })
+        .min_by_key(|(_, _, bits)| *bits)
+        .expect("cannot partition the residuals")
+}
+
+/// Encode one subframe of `x`, whose samples fit in `bps` bits, choosing the smallest of the
+/// constant, verbatim and fixed predictor subframes.
+fn write_subframe(writer: &mut BitWriter, x: &[i64], bps: u32, settings: &Settings) {
+    if x.iter().all(|v| *v == x[0]) {
+        writer.write(0b0000_0000, 8);
+        writer.write_signed(x[0], bps);
+        return;
+    }
+
+    let verbatim_bits = x.len() as u64 * bps as u64;
+    let best = (0..=usize::min(settings.max_fixed_order, x.len() - 1))
+        .map(|order| {
+            let residuals: Vec<u64> = fixed_residuals(x, order).into_iter().map(zigzag).collect();
+            let (p, params, bits) =
+                rice_partitions(&residuals, x.len(), order, settings.max_partition_order);
+            (
+                order,
+                residuals,
+                p,
+                params,
+                bits + order as u64 * bps as u64,
+            )
+        })
+        .min_by_key(|candidate| candidate.4);
+
+    match best {
+        Some((order, residuals, p, params, bits)) if bits < verbatim_bits => {
+            writer.write(0b0001_0000 | (order as u64) << 1, 8);
+            for v in &x[..order] {
+                writer.write_signed(*v, bps);
+            }
+            writer.write(0b00, 2);
+            writer.write(p as u64, 4);
+            let partition_len = x.len() >> p;
+            let mut start = 0;
+            for (i, k) in params.iter().enumerate() {
+                let len = if i == 0 {
+                    partition_len - order
+                } else {
+                    partition_len
+                };
+                writer.write(*k as u64, 4);
+                for u in &residuals[start..start + len] {
+                    writer.write_unary(u >> k);
+                    writer.write(*u, *k);
+                }
+                start += len;
+            }
+        }
+        _ => {
+            writer.write(0b0000_0010, 8);
+            for v in x {
+                writer.write_signed(*v, bps);
+            }
+        }
+    }
+}
+
+/// Channel assignment code of a frame, with the samples and sample size of each subframe.
+type Assignment = (u64, Vec<(Vec<i64>, u32)>);
+
+/// Encode one frame of the (channels, block_size) `block`.
+fn write_frame(
+    block: &ArrayView2<i64>,
+    frame_number: u64,
+    bit_depth: u32,
+    settings: &Settings,
+) -> Vec<u8> {
+    let (channels, block_size) = block.dim();
+    let samples: Vec<Vec<i64>> = block.outer_iter().map(|c| c.to_vec()).collect();
+
+    let mut candidates: Vec<Assignment> = vec![(
+        channels as u64 - 1,
+        samples.iter().map(|c| (c.clone(), bit_depth)).collect(),
+    )];
+    if channels == 2 && settings.stereo_decorrelation {
+        let (left, right) = (&samples[0], &samples[1]);
+        let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+        let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+        candidates.push((
+            0b1000,
+            vec![(left.clone(), bit_depth), (side.clone(), bit_depth + 1)],
+        ));
+        candidates.push((
+            0b1001,
+            vec![(side.clone(), bit_depth + 1), (right.clone(), bit_depth)],
+        ));
+        candidates.push((0b1010, vec![(mid, bit_depth), (side, bit_depth + 1)]));
+    }
+
+    let (assignment, subframes) = candidates
+        .into_iter()
+        .map(|(assignment, channels)| {
+            let encoded: Vec<BitWriter> = channels
+                .iter()
+                .map(|(x, bps)| {
+                    let mut writer = BitWriter::new();
+                    write_subframe(&mut writer, x, *bps, settings);
+                    writer
+                })
+                .collect();
+            (assignment, encoded)
+        })
+        .min_by_key(|(_, encoded)| encoded.iter().map(|w| w.len_bits()).sum::<u64>())
+        .expect("cannot choose a channel assignment");
+
+    let mut writer = BitWriter::new();
+    writer.write(0b1111_1111_1111_1000, 16);
+    let sample_size = match bit_depth {
+        8 => 0b001,
+        12 => 0b010,
+        16 => 0b100,
+        20 => 0b101,
+        _ => 0b110,
+    };
+    writer.write(0b0111, 4);
+    writer.write(0b0000, 4);
+    writer.write(assignment, 4);
+    writer.write(sample_size, 3);
+    writer.write(0, 1);
+    write_utf8(&mut writer, frame_number);
+    writer.write(block_size as u64 - 1, 16);
+    let crc = crc8(&writer.bytes);
+    writer.write(crc as u64, 8);
+
+    for subframe in &subframes {
+        writer.append(subframe);
+    }
+    writer.align();
+    let crc = crc16(&writer.bytes);
+    writer.write(crc as u64, 16);
+
+    writer.bytes
+}
+
+/// Quantize the (channels, n) `arr` in [-1, 1] to `bit_depth` bits, clipping samples out of range.
+fn quantize(arr: &ArrayView2<f64>, bit_depth: u32) -> Array2<i64> {
+    let full_scale = (1_i64 << (bit_depth - 1)) as f64;
+    arr.mapv(|v| (v * full_scale).round().clamp(-full_scale, full_scale - 1.) as i64)
+}
+
+/// MD5 signature of the STREAMINFO block: of the interleaved samples, each as a little-endian signed
+/// integer of `bit_depth` bits rounded up to whole bytes.
+fn md5_signature(samples: &Array2<i64>, bit_depth: u32) -> [u8; 16] {
+    let width = bit_depth.div_ceil(8) as usize;
+    let mut md5 = Md5::default();
+    let mut bytes = Vec::with_capacity(samples.nrows() * width);
+    for frame in samples.columns() {
+        bytes.clear();
+        for v in frame {
+            bytes.extend_from_slice(&v.to_le_bytes()[..width]);
+        }
+        md5.process_buf_bytes(&bytes);
+    }
+    md5.md5()
+}
+
+/// Write the (channels, n) `arr` to a FLAC file at `path`, with fixed-predictor subframes, partitioned
+/// rice coding and, for stereo, mid/side decorrelation. `compression` from 0 to 8 trades speed for size.
+pub fn write_flac(path: &Path, arr: &ArrayView2<f64>, sr: u32, bit_depth: u32, compression: u32) {
+    let channels = arr.nrows();
+    if channels == 0 || channels > 8 {
+        panic!("FLAC supports 1 to 8 channels");
+    }
+    if ![8, 12, 16, 20, 24].contains(&bit_depth) {
+        panic!("bit_depth must be one of 8, 12, 16, 20 or 24");
+    }
+    if sr == 0 || sr > 655_350 {
+        panic!("sr must be between 1 and 655350");
+    }
+    if compression > 8 {
+        panic!("compression must be between 0 and 8");
+    }
+
+    let settings = Settings::from_level(compression);
+    let samples = quantize(arr, bit_depth);
+    let n = samples.ncols();
+
+    let frames: Vec<Vec<u8>> = (0..n)
+        .step_by(BLOCK_SIZE)
+        .enumerate()
+        .map(|(i, start)| {
+            let end = usize::min(start + BLOCK_SIZE, n);
+            write_frame(
+                &samples.slice(ndarray::s![.., start..end]),
+                i as u64,
+                bit_depth,
+                &settings,
+            )
+        })
+        .collect();
+
+    let mut header = BitWriter::new();
+    header.write(u32::from_be_bytes(*b"fLaC") as u64, 32);
+    // Last metadata block, of type STREAMINFO and 34 bytes.
+    header.write(1, 1);
+    header.write(0, 7);
+    header.write(34, 24);
+    let block_size = usize::min(BLOCK_SIZE, usize::max(n, 16)) as u64;
+    header.write(block_size, 16);
+    header.write(block_size, 16);
+    header.write(frames.iter().map(|f| f.len()).min().unwrap_or(0) as u64, 24);
+    header.write(frames.iter().map(|f| f.len()).max().unwrap_or(0) as u64, 24);
+    header.write(sr as u64, 20);
+    header.write(channels as u64 - 1, 3);
+    header.write(bit_depth as u64 - 1, 5);
+    header.write((n as u64) >> 32, 4);
+    header.write(n as u64, 32);
+    for byte in md5_signature(&samples, bit_depth) {
+        header.write(byte as u64, 8);
+    }
+
+    let mut file = File::create(path).expect("cannot create the output file");
+    file.write_all(&header.bytes)
+        .expect("cannot write the output file");
+    for frame in frames {
+        file.write_all(&frame)
+            .expect("cannot write the output file");
+    }
+}
+
+#[cfg(test)]
+mod test_encode_flac {
+    use super::*;
+    use crate::decode_symphonia::{self, Norm};
+
+    /// Whether the decoded samples match the MD5 signature of the STREAMINFO block, `None` if the
+    /// decoder cannot tell.
+    fn verify_md5(path: &Path) -> Option<bool> {
+        use symphonia::core::codecs::DecoderOptions;
+        use symphonia::core::io::MediaSourceStream;
+        use symphonia::core::probe::Hint;
+
+        let mss = MediaSourceStream::new(Box::new(File::open(path).unwrap()), Default::default());
+        let mut format = symphonia::default::get_probe()
+            .format(&Hint::new(), mss, &Default::default(), &Default::default())
+            .unwrap()
+            .format;
+        let options = DecoderOptions { verify: true };
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&format.default_track().unwrap().codec_params, &options)
+            .unwrap();
+        while let Ok(packet) = format.next_packet() {
+            decoder.decode(&packet).unwrap();
+        }
+        decoder.finalize().verify_ok
+    }
+
+    #[test]
+    fn test_flac_round_trip() {
+        let n = 10_000;
+        let arr = Array2::from_shape_fn((2, n), |(c, i)| {
+            let t = i as f64 / 8000.;
+            0.5 * (2. * std::f64::consts::PI * (220. + 110. * c as f64) * t).sin()
+                + if i == 5000 { 2. } else { 0. }
+        });
+        // Named after the process, so that concurrent test runs do not share the file.
+        let path = std::env::temp_dir().join(format!(
+            "audiotest_test_flac_round_trip_{}.flac",
+            std::process::id()
+        ));
+
+        let mut sizes = Vec::new();
+        for (bit_depth, compression) in [(16, 0), (16, 5), (24, 8), (8, 2)] {
+            write_flac(&path, &arr.view(), 8000, bit_depth, compression);
+            let decoded = decode_symphonia::load(
+                &path,
+                false,
+                0.,
+                None,
+                None,
+                false,
+                &Norm::FullScale,
+                Some("flac"),
+            );
+            let expected = quantize(&arr.view(), bit_depth)
+                .mapv(|v| v as f64 / (1_i64 << (bit_depth - 1)) as f64);
+            assert_eq!(decoded, expected);
+            assert_eq!(decode_symphonia::get_samplerate(&path, Some("flac")), 8000);
+            assert_eq!(verify_md5(&path), Some(true));
+            let mut reader = decode_symphonia::AudioReader::open(&path, None, false, Some("flac"));
+            assert_eq!(
+                reader.sample_format(),
+                (Some(format!("s{}", bit_depth)), Some(bit_depth))
+            );
+            sizes.push(std::fs::metadata(&path).unwrap().len());
+        }
+        // Smaller than 16-bit PCM, and more so at a higher compression level.
+        assert!(sizes[1] < sizes[0] && sizes[0] < 4 * n as u64, "{sizes:?}");
+
+        // Without a packet to decode, the sample format is unknown: keep only the "fLaC" marker and
+        // the STREAMINFO block of the last, 8-bit file, which states the length of the stream.
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(4 + 4 + 34).unwrap();
+        let mut reader = decode_symphonia::AudioReader::open(&path, None, false, Some("flac"));
+        assert_eq!(reader.sample_format(), (None, Some(8)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
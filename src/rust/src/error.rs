@@ -0,0 +1,147 @@
+use std::any::Any;
+use std::fmt;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+
+use extendr_api::prelude::*;
+
+/// Errors of reading audio files that R code may want to handle differently, e.g. skipping
+/// missing files but failing on corrupt ones. Each is signalled in R as a condition of its own
+/// class, see `raise`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioError {
+    /// The file cannot be opened, e.g. it does not exist or is not readable, or its handle is closed.
+    Io(String),
+    /// No container or codec is registered for the file, or it has no audio track.
+    UnsupportedFormat(String),
+    /// The file is recognized but its header lacks a parameter to decode it, or its packets cannot
+    /// be read or decoded.
+    Decode(String),
+    /// The requested track, offset or duration lies outside of the file.
+    Offset(String),
+}
+
+impl AudioError {
+    /// R class of the condition signalled for the error. The classes are part of the API.
+    pub fn class(&self) -> &'static str {
+        match self {
+            AudioError::Io(_) => "audiotest_io_error",
+            AudioError::UnsupportedFormat(_) => "audiotest_format_error",
+            AudioError::Decode(_) => "audiotest_decode_error",
+            AudioError::Offset(_) => "audiotest_offset_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AudioError::Io(m)
+            | AudioError::UnsupportedFormat(m)
+            | AudioError::Decode(m)
+            | AudioError::Offset(m) => m,
+        }
+    }
+
+    /// Abort the current operation with this error. It unwinds like a panic, so `par_map_catch`
+    /// turns it into `None` for one file, and `raise` into an R condition, but without printing
+    /// the panic message: the condition carries it instead.
+    pub fn throw(self) -> ! {
+        resume_unwind(Box::new(self))
+    }
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// The `AudioError` a panic payload carries, if any.
+pub fn downcast(payload: &(dyn Any + Send)) -> Option<&AudioError> {
+    payload.downcast_ref::<AudioError>()
+}
+
+/// The R condition of `err`, with the classes `c(<class>, "audiotest_error", "error", "condition")`,
+/// so R code can tell the errors apart with `tryCatch(audiotest_io_error = ...)`.
+pub fn condition(err: &AudioError) -> Robj {
+    call!(
+        "structure",
+        list!(message = err.message(), call = ()),
+        class = [err.class(), "audiotest_error", "error", "condition"]
+    )
+    .expect("cannot create the condition")
+}
+
+/// Run `f`, the body of a function exported to R, returning the `condition` of an `AudioError`
+/// it throws in place of its value. Other panics are resumed and reported by extendr as before.
+/// The condition is signalled by the R wrapper of the function, see `R/zzz.R`: calling `stop`
+/// from here would jump back to R over the Rust frames without dropping their values.
+pub fn raise<T, F>(f: F) -> Robj
+where
+    F: FnOnce() -> T,
+    T: Into<Robj>,
+{
+    let payload = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(out) => return out.into(),
+        Err(payload) => payload,
+    };
+    match downcast(payload.as_ref()) {
+        Some(err) => condition(err),
+        None => resume_unwind(payload),
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+    use crate::decode_symphonia;
+    use std::path::Path;
+
+    fn thrown<T, F: FnOnce() -> T>(f: F) -> Option<AudioError> {
+        let payload = catch_unwind(AssertUnwindSafe(f)).err()?;
+        downcast(payload.as_ref()).cloned()
+    }
+
+    #[test]
+    fn test_error_classes() {
+        let err = thrown(|| decode_symphonia::get_samplerate(Path::new("missing.wav"), None));
+        assert_eq!(err.map(|e| e.class()), Some("audiotest_io_error"));
+
+        let err = thrown(|| decode_symphonia::get_samplerate(Path::new("Cargo.toml"), None));
+        assert_eq!(err.map(|e| e.class()), Some("audiotest_format_error"));
+
+        let reader = decode_symphonia::AudioReader::open(
+            Path::new("../../test_files/mono.wav"),
+            None,
+            false,
+            Some("wav"),
+        );
+        let err = thrown(|| reader.segment_samples(reader.n_frames, None));
+        assert_eq!(err.map(|e| e.class()), Some("audiotest_offset_error"));
+
+        // A crop past the end of the file, as `read_segment` takes it, unless it is clamped.
+        let duration = reader.n_frames as f64 / reader.sr as f64;
+        let err = thrown(|| reader.crop(duration - 0.5, 1., false));
+        assert_eq!(err.map(|e| e.class()), Some("audiotest_offset_error"));
+        assert_eq!(
+            reader.crop(duration - 0.5, 1., true),
+            (reader.n_frames - reader.sr as u64, reader.sr as u64)
+        );
+
+        let err = thrown(|| {
+            decode_symphonia::AudioReader::open(
+                Path::new("../../test_files/mono.wav"),
+                Some(1),
+                false,
+                Some("wav"),
+            )
+        });
+        assert_eq!(err.map(|e| e.class()), Some("audiotest_offset_error"));
+
+        // Argument errors stay ordinary panics.
+        assert_eq!(
+            thrown(|| decode_symphonia::OnError::from_name("retry")),
+            None
+        );
+    }
+}
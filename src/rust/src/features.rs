@@ -0,0 +1,1354 @@
+//! Framed and spectral features. Spectral features are computed from each channel on its own,
+//! never from a downmix, and stacked with the channels last in a (t, f, channels) array of
+//! frames by features, the layout the R exports return. `mean_channels` averages the channels
+//! where a single result is wanted.
+
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use ndarray::{
+    s, Array, Array1, Array2, Array3, ArrayView, ArrayView1, ArrayView2, ArrayView3, Axis,
+    Dimension, Zip,
+};
+use num::{FromPrimitive, Zero};
+use num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+use crate::convert;
+use crate::misc;
+use crate::util;
+
+pub enum PadMode {
+    Constant,
+    Edge,
+    Reflect,
+}
+
+impl PadMode {
+    pub fn from_name(name: &str) -> PadMode {
+        match name {
+            "constant" => PadMode::Constant,
+            "edge" => PadMode::Edge,
+            "reflect" => PadMode::Reflect,
+            _ => panic!("pad_mode must be one of \"constant\", \"edge\" or \"reflect\""),
+        }
+    }
+
+    /// Sample of `x` at `i`, extended past both ends according to the mode.
+    fn sample(&self, x: &ArrayView1<f64>, i: isize) -> f64 {
+        let n = x.len() as isize;
+        if (0..n).contains(&i) {
+            return x[i as usize];
+        }
+        match self {
+            PadMode::Constant => 0.,
+            PadMode::Edge => x[i.clamp(0, n - 1) as usize],
+            // Mirrored around the first and last samples without repeating them, as `numpy.pad`.
+            PadMode::Reflect if n == 1 => x[0],
+            PadMode::Reflect => {
+                let period = 2 * (n - 1);
+                let j = i.rem_euclid(period);
+                x[if j < n { j } else { period - j } as usize]
+            }
+        }
+    }
+}
+
+/// Slice a signal into overlapping frames centered on multiples of `hop_length`.
+/// The signal is padded by `frame_length / 2` on both ends according to `pad_mode`.
+/// Returns a (n_frames, frame_length) array.
+pub fn frame(
+    x: &ArrayView1<f64>,
+    frame_length: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+) -> Array2<f64> {
+    let pad = frame_length / 2;
+    if x.is_empty() && !matches!(pad_mode, PadMode::Constant) {
+        panic!("cannot pad an empty signal");
+    }
+    let padded = Array1::from_shape_fn(x.len() + 2 * pad, |i| {
+        pad_mode.sample(x, i as isize - pad as isize)
+    });
+
+    if padded.len() < frame_length {
+        panic!("frame_length is longer than the padded signal");
+    }
+
+    let n_frames = 1 + (padded.len() - frame_length) / hop_length;
+    let mut frames = Array2::<f64>::zeros((n_frames, frame_length));
+    for (t, mut row) in frames.outer_iter_mut().enumerate() {
+        let start = t * hop_length;
+        row.assign(&padded.slice(s![start..start + frame_length]));
+    }
+
+    frames
+}
+
+/// Root-mean-square energy of each frame, per channel. Returns a (channels, n_frames) array.
+pub fn rms(
+    arr: &ArrayView2<f64>,
+    frame_length: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+) -> Array2<f64> {
+    let rows: Vec<Array1<f64>> = arr
+        .outer_iter()
+        .map(|channel| {
+            frame(&channel, frame_length, hop_length, pad_mode)
+                .map_axis(Axis(1), |f| f.mapv(|v| v * v).mean().unwrap_or(0.).sqrt())
+        })
+        .collect();
+    let views: Vec<ArrayView1<f64>> = rows.iter().map(|row| row.view()).collect();
+
+    ndarray::stack(Axis(0), &views).expect("cannot stack channels")
+}
+
+/// Energy-based voice activity detection on the mono downmix.
+/// Returns the per-frame decisions and the frame energy in dBFS.
+pub fn vad(
+    arr: &ArrayView2<f64>,
+    frame_length: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    energy_threshold_db: f64,
+    hangover: usize,
+) -> (Vec<bool>, Vec<f64>) {
+    let mono = misc::to_mono_ndarray(arr);
+    let energy = rms(&mono.view(), frame_length, hop_length, pad_mode);
+
+    // With zero padding the centered edge frames drag their rms down.
+    // Rescale each frame by the fraction of it that overlaps the signal.
+    let zero_padded = matches!(pad_mode, PadMode::Constant);
+    let n_samples = arr.ncols() as i64;
+    let pad = (frame_length / 2) as i64;
+    let energy_db: Vec<f64> = energy
+        .row(0)
+        .iter()
+        .enumerate()
+        .map(|(t, e)| {
+            let start = (t * hop_length) as i64 - pad;
+            let end = start + frame_length as i64;
+            let n_valid = (i64::min(end, n_samples) - i64::max(start, 0)).max(1);
+            let corrected = match zero_padded {
+                true => e * (frame_length as f64 / n_valid as f64).sqrt(),
+                false => *e,
+            };
+            convert::amplitude_to_db(corrected, 1.)
+        })
+        .collect();
+
+    let mut hold = 0_usize;
+    let voiced = energy_db
+        .iter()
+        .map(|db| {
+            if *db > energy_threshold_db {
+                hold = hangover;
+                true
+            } else if hold > 0 {
+                hold -= 1;
+                true
+            } else {
+                false
+            }
+        })
+        .collect();
+
+    (voiced, energy_db)
+}
+
+/// Average of a (t, f, channels) feature array over its channels. Returns a (t, f, 1) array.
+pub fn mean_channels<A>(data: &ArrayView3<A>) -> Array3<A>
+where
+    A: Clone + Zero + FromPrimitive + std::ops::Add<Output = A> + std::ops::Div<Output = A>,
+{
+    data.mean_axis(Axis(2))
+        .expect("cannot average an array without channels")
+        .insert_axis(Axis(2))
+}
+
+/// How the channels of a feature are returned, the `aggregate` argument of the spectral R exports.
+#[derive(Clone, Copy)]
+pub enum Channels {
+    /// One feature per channel.
+    Keep,
+    /// The average of the channels, see `mean_channels`.
+    Mean,
+}
+
+impl Channels {
+    /// `None` is R's `NA`.
+    pub fn from_name(name: Option<&str>) -> Channels {
+        match name {
+            None => Channels::Keep,
+            Some("mean") => Channels::Mean,
+            Some(_) => panic!("aggregate must be NA or \"mean\""),
+        }
+    }
+
+    pub fn apply<A>(self, data: Array3<A>) -> Array3<A>
+    where
+        A: Clone + Zero + FromPrimitive + std::ops::Add<Output = A> + std::ops::Div<Output = A>,
+    {
+        match self {
+            Channels::Keep => data,
+            Channels::Mean => mean_channels(&data.view()),
+        }
+    }
+}
+
+/// Stack `n_steps` copies of a (t, f, channels) feature array along the feature axis,
+/// copy `i` being delayed by `i * delay` frames. Frames shifted in from outside the data are zero.
+/// Returns a (t, f * n_steps, channels) array.
+pub fn stack_memory(data: &ArrayView3<f64>, n_steps: usize, delay: isize) -> Array3<f64> {
+    let (n_frames, n_features, channels) = data.dim();
+    let mut out = Array3::<f64>::zeros((n_frames, n_features * n_steps, channels));
+
+    for step in 0..n_steps {
+        let shift = step as isize * delay;
+        for t in 0..n_frames {
+            let src = t as isize - shift;
+            if src < 0 || src >= n_frames as isize {
+                continue;
+            }
+            out.slice_mut(s![t, step * n_features..(step + 1) * n_features, ..])
+                .assign(&data.slice(s![src as usize, .., ..]));
+        }
+    }
+
+    out
+}
+
+pub enum Aggregate {
+    Mean,
+    Median,
+    Max,
+}
+
+/// Aggregate the frames of a (t, f, channels) feature array between consecutive boundaries in `idx`.
+/// The boundaries are clipped to the number of frames and completed with the first and last frame,
+/// so every frame falls into exactly one segment. Returns a (n_segments, f, channels) array.
+pub fn sync(data: &ArrayView3<f64>, idx: &[usize], aggregate: Aggregate) -> Array3<f64> {
+    let (n_frames, n_features, channels) = data.dim();
+
+    let mut bounds: Vec<usize> = idx.iter().map(|i| usize::min(*i, n_frames)).collect();
+    bounds.push(0);
+    bounds.push(n_frames);
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut out = Array3::<f64>::zeros((bounds.len() - 1, n_features, channels));
+    for (k, segment) in bounds.windows(2).enumerate() {
+        let frames = data.slice(s![segment[0]..segment[1], .., ..]);
+        let aggregated = match aggregate {
+            Aggregate::Mean => frames.mean_axis(Axis(0)).expect("cannot mean_axis"),
+            Aggregate::Median => frames.map_axis(Axis(0), |lane| util::median(lane.to_vec())),
+            Aggregate::Max => frames.fold_axis(Axis(0), f64::NEG_INFINITY, |acc, x| acc.max(*x)),
+        };
+        out.slice_mut(s![k, .., ..]).assign(&aggregated);
+    }
+
+    out
+}
+
+/// Periodic Hann window of length `n`, as used for spectral analysis.
+pub fn hann(n: usize) -> Array1<f64> {
+    Array1::from_shape_fn(n, |i| 0.5 - 0.5 * (2. * PI * i as f64 / n as f64).cos())
+}
+
+pub enum Window {
+    Hann,
+    Hamming,
+    Rectangular,
+}
+
+impl Window {
+    pub fn from_name(name: &str) -> Window {
+        match name {
+            "hann" => Window::Hann,
+            "hamming" => Window::Hamming,
+            "rectangular" => Window::Rectangular,
+            _ => panic!("window must be one of \"hann\", \"hamming\" or \"rectangular\""),
+        }
+    }
+
+    /// Periodic window of length `n`.
+    pub fn values(&self, n: usize) -> Array1<f64> {
+        match self {
+            Window::Hann => hann(n),
+            Window::Hamming => {
+                Array1::from_shape_fn(n, |i| 0.54 - 0.46 * (2. * PI * i as f64 / n as f64).cos())
+            }
+            Window::Rectangular => Array1::ones(n),
+        }
+    }
+}
+
+/// Number of FFT plans of each direction each thread keeps.
+const FFT_CACHE_SIZE: usize = 8;
+
+type PlanCache<P> = RefCell<Vec<(usize, Arc<P>)>>;
+
+thread_local! {
+    static FORWARD_FFTS: PlanCache<dyn RealToComplex<f64>> = const { RefCell::new(Vec::new()) };
+    static INVERSE_FFTS: PlanCache<dyn ComplexToReal<f64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Plan of length `n_fft` from `cache`, planned by `plan` unless it is among the `FFT_CACHE_SIZE`
+/// most recently used ones. Whole-signal transforms plan lengths that are rarely seen twice, so
+/// the cache is bounded like that of the filterbanks rather than keeping every plan.
+fn cached_plan<P, F>(cache: &PlanCache<P>, n_fft: usize, plan: F) -> Arc<P>
+where
+    P: ?Sized,
+    F: FnOnce(&mut RealFftPlanner<f64>) -> Arc<P>,
+{
+    let mut cache = cache.borrow_mut();
+    // The most recently used plan is last.
+    if let Some(i) = cache.iter().position(|(n, _)| *n == n_fft) {
+        let entry = cache.remove(i);
+        cache.push(entry);
+    } else {
+        if cache.len() == FFT_CACHE_SIZE {
+            cache.remove(0);
+        }
+        cache.push((n_fft, plan(&mut RealFftPlanner::new())));
+    }
+    cache[cache.len() - 1].1.clone()
+}
+
+/// Real-to-complex FFT of length `n_fft`, cached per thread, so the frames of a spectrogram and
+/// later calls with the same `n_fft`, e.g. over a batch of files on the rayon pool, reuse one plan.
+pub fn forward_fft(n_fft: usize) -> Arc<dyn RealToComplex<f64>> {
+    FORWARD_FFTS.with(|cache| cached_plan(cache, n_fft, |planner| planner.plan_fft_forward(n_fft)))
+}
+
+/// Complex-to-real inverse of `forward_fft`, cached the same way.
+pub fn inverse_fft(n_fft: usize) -> Arc<dyn ComplexToReal<f64>> {
+    INVERSE_FFTS.with(|cache| cached_plan(cache, n_fft, |planner| planner.plan_fft_inverse(n_fft)))
+}
+
+/// FFT length and hop length keeping the time resolution of 2048 and 512 at 22050 Hz: the FFT
+/// length is scaled with `sr` and rounded to the nearest power of two, and the hop is a quarter of it.
+pub fn default_frame_params(sr: u32) -> (usize, usize) {
+    let exponent = (2048. * sr as f64 / 22050.).log2().round().max(2.);
+    let n_fft = 2_usize.pow(exponent as u32);
+    (n_fft, n_fft / 4)
+}
+
+/// Real FFT of the whole of `x`, unnormalised. Returns the `x.len() / 2 + 1` non-negative frequency bins.
+pub fn rfft(x: &[f64]) -> Vec<Complex<f64>> {
+    let fft = forward_fft(x.len());
+    let mut input = x.to_vec();
+    let mut spectrum = fft.make_output_vec();
+    fft.process(&mut input, &mut spectrum)
+        .expect("cannot compute the fft");
+    spectrum
+}
+
+/// Inverse of `rfft` for a signal of length `n`, scaled by `1 / n` so `irfft(rfft(x), x.len())`
+/// gives back `x`. The imaginary parts of the DC and, for even `n`, Nyquist bins are ignored.
+pub fn irfft(spectrum: &[Complex<f64>], n: usize) -> Vec<f64> {
+    if spectrum.len() != n / 2 + 1 {
+        panic!(
+            "a signal of length {} needs {} frequency bins, got {}",
+            n,
+            n / 2 + 1,
+            spectrum.len()
+        );
+    }
+    let fft = inverse_fft(n);
+    let mut input = spectrum.to_vec();
+    input[0].im = 0.;
+    if 2 * (spectrum.len() - 1) == n {
+        input[spectrum.len() - 1].im = 0.;
+    }
+    let mut output = fft.make_output_vec();
+    fft.process(&mut input, &mut output)
+        .expect("cannot compute the inverse fft");
+    output.iter().map(|v| v / n as f64).collect()
+}
+
+/// Short-time Fourier transform of a signal with a Hann window of length `n_fft`.
+/// Frames are centered and padded as in `frame`, and one real FFT plan is used for all of them.
+/// Returns a (n_frames, n_fft / 2 + 1) array of the non-negative frequency bins.
+pub fn stft(
+    x: &ArrayView1<f64>,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+) -> Array2<Complex<f64>> {
+    spectrum_frames(&frame(x, n_fft, hop_length, pad_mode).view())
+}
+
+/// Range of the STFT bins whose center frequency lies in `[fmin, fmax]` Hz.
+pub fn band_bins(sr: u32, n_fft: usize, fmin: f64, fmax: f64) -> Range<usize> {
+    let freqs = convert::fft_frequencies(sr, n_fft);
+    let start = freqs.iter().position(|f| *f >= fmin).unwrap_or(freqs.len());
+    let end = freqs.iter().rposition(|f| *f <= fmax).map_or(0, |k| k + 1);
+    start..usize::max(start, end)
+}
+
+/// Magnitude of a spectrogram raised to `power`: 1 gives the magnitude and 2 the power spectrogram.
+pub fn spectrogram_power<D: Dimension>(
+    spec: &ArrayView<Complex<f64>, D>,
+    power: f64,
+) -> Array<f64, D> {
+    if power == 2. {
+        spec.mapv(|v| v.norm_sqr())
+    } else {
+        spec.mapv(|v| v.norm().powf(power))
+    }
+}
+
+/// Hann-windowed real FFT of each row of a (n_frames, n_fft) array of frames.
+/// Returns a (n_frames, n_fft / 2 + 1) array.
+pub fn spectrum_frames(frames: &ArrayView2<f64>) -> Array2<Complex<f64>> {
+    let n_fft = frames.ncols();
+    let window = hann(n_fft);
+    let fft = forward_fft(n_fft);
+
+    let mut out = Array2::<Complex<f64>>::zeros((frames.nrows(), n_fft / 2 + 1));
+    let mut input = fft.make_input_vec();
+    let mut spectrum = fft.make_output_vec();
+    for (frame, mut row) in frames.outer_iter().zip(out.outer_iter_mut()) {
+        for (b, (v, w)) in input.iter_mut().zip(frame.iter().zip(window.iter())) {
+            *b = v * w;
+        }
+        fft.process(&mut input, &mut spectrum)
+            .expect("cannot compute the fft");
+        row.assign(&ArrayView1::from(&spectrum));
+    }
+
+    out
+}
+
+/// Welch estimate of the one-sided power spectral density of `x`, in power per Hz.
+/// The periodograms of the windowed segments of length `n_fft` starting every `hop_length` samples
+/// are averaged, with the density scaling of `scipy.signal.welch`. Segments are not padded, except
+/// a signal shorter than `n_fft`, which gives a single zero-padded segment.
+/// Returns the `n_fft / 2 + 1` values of the frequency bins.
+pub fn welch_psd(
+    x: &ArrayView1<f64>,
+    sr: u32,
+    n_fft: usize,
+    hop_length: usize,
+    window: &Window,
+) -> Array1<f64> {
+    let window = window.values(n_fft);
+    let fft = forward_fft(n_fft);
+    let n_segments = if x.len() < n_fft {
+        1
+    } else {
+        1 + (x.len() - n_fft) / hop_length
+    };
+
+    let mut psd = Array1::<f64>::zeros(n_fft / 2 + 1);
+    let mut input = fft.make_input_vec();
+    let mut spectrum = fft.make_output_vec();
+    for segment in 0..n_segments {
+        let start = segment * hop_length;
+        for (i, (b, w)) in input.iter_mut().zip(window.iter()).enumerate() {
+            *b = x.get(start + i).map_or(0., |v| v * w);
+        }
+        fft.process(&mut input, &mut spectrum)
+            .expect("cannot compute the fft");
+        Zip::from(&mut psd)
+            .and(&ArrayView1::from(&spectrum))
+            .for_each(|p, v| *p += v.norm_sqr());
+    }
+
+    let scale = 1. / (sr as f64 * window.mapv(|w| w * w).sum() * n_segments as f64);
+    psd.mapv_inplace(|p| p * scale);
+    // Fold the negative frequencies onto the positive ones, except for DC and, with an even
+    // `n_fft`, Nyquist.
+    psd.slice_mut(s![1..n_fft.div_ceil(2)])
+        .mapv_inplace(|p| 2. * p);
+
+    psd
+}
+
+/// Instantaneous frequency (Hz) and magnitude of each bin of the STFT of `x`.
+/// The frequency comes from the phase advance between the STFT of `x` and that of `x` advanced
+/// by one sample, so it is precise well below the bin spacing for a stable sinusoid.
+/// Bins without energy get their center frequency. Returns two (n_frames, n_fft / 2 + 1) arrays.
+pub fn if_gram(
+    x: &ArrayView1<f64>,
+    sr: u32,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+) -> (Array2<f64>, Array2<f64>) {
+    let mut advanced = Array1::<f64>::zeros(x.len());
+    if !x.is_empty() {
+        advanced
+            .slice_mut(s![..x.len() - 1])
+            .assign(&x.slice(s![1..]));
+    }
+    let spec = stft(x, n_fft, hop_length, pad_mode);
+    let spec_advanced = stft(&advanced.view(), n_fft, hop_length, pad_mode);
+    let bin_freqs = convert::fft_frequencies(sr, n_fft);
+
+    let mut freqs = Array2::<f64>::zeros(spec.dim());
+    Zip::indexed(&mut freqs)
+        .and(&spec)
+        .and(&spec_advanced)
+        .for_each(|(_, k), f, now, next| {
+            let product = next * now.conj();
+            *f = if product.norm() > f64::MIN_POSITIVE {
+                product.arg() * sr as f64 / (2. * PI)
+            } else {
+                bin_freqs[k]
+            };
+        });
+
+    (freqs, spec.mapv(|v| v.norm()))
+}
+
+/// Inverse of `stft` by windowed overlap-add of the inverse real FFT of each frame.
+/// `n_fft` is the FFT length of the analysis, `2 * (n_bins - 1)` or `2 * n_bins - 1` as with `irfft`.
+/// The output is trimmed like the centered analysis frames, then cropped or zero-padded to `length` samples if given.
+pub fn istft(
+    spec: &ArrayView2<Complex<f64>>,
+    n_fft: usize,
+    hop_length: usize,
+    length: Option<usize>,
+) -> Array1<f64> {
+    let (n_frames, n_bins) = spec.dim();
+    if n_bins < 2 {
+        panic!("stft must have at least two frequency bins");
+    }
+    if n_bins != n_fft / 2 + 1 {
+        panic!(
+            "a stft with n_fft = {} has {} frequency bins, got {}",
+            n_fft,
+            n_fft / 2 + 1,
+            n_bins
+        );
+    }
+    let window = hann(n_fft);
+    let fft = inverse_fft(n_fft);
+
+    let n_padded = n_fft + hop_length * n_frames.saturating_sub(1);
+    let mut signal = Array1::<f64>::zeros(n_padded);
+    let mut norm = Array1::<f64>::zeros(n_padded);
+    let mut spectrum = fft.make_input_vec();
+    let mut output = fft.make_output_vec();
+    for (t, row) in spec.outer_iter().enumerate() {
+        for (b, v) in spectrum.iter_mut().zip(row.iter()) {
+            *b = *v;
+        }
+        // The half-spectrum of a real signal has a real DC bin, and a real Nyquist bin for an even `n_fft`.
+        spectrum[0].im = 0.;
+        if 2 * (n_bins - 1) == n_fft {
+            spectrum[n_bins - 1].im = 0.;
+        }
+        fft.process(&mut spectrum, &mut output)
+            .expect("cannot compute the inverse fft");
+
+        let start = t * hop_length;
+        for (i, (v, w)) in output.iter().zip(window.iter()).enumerate() {
+            signal[start + i] += v / n_fft as f64 * w;
+            norm[start + i] += w * w;
+        }
+    }
+    Zip::from(&mut signal).and(&norm).for_each(|s, n| {
+        if *n > f64::EPSILON {
+            *s /= n;
+        }
+    });
+
+    // The last frame also covers up to `n_fft / 2` samples past its center, which a `length`
+    // that is not a multiple of `hop_length` needs.
+    let pad = n_fft / 2;
+    let covered = signal.slice(s![pad..]);
+    match length {
+        Some(length) => {
+            let mut out = Array1::<f64>::zeros(length);
+            let n = usize::min(length, covered.len());
+            out.slice_mut(s![..n]).assign(&covered.slice(s![..n]));
+            out
+        }
+        None => covered.slice(s![..n_padded - n_fft]).to_owned(),
+    }
+}
+
+/// Triangular mel filterbank with Slaney area normalization. Returns a (n_mels, n_fft / 2 + 1) array.
+pub fn mel_filters(sr: u32, n_fft: usize, n_mels: usize, fmin: f64, fmax: f64) -> Array2<f64> {
+    let fft_freqs = convert::fft_frequencies(sr, n_fft);
+    let mel_freqs = convert::mel_frequencies(n_mels + 2, fmin, fmax);
+
+    let mut weights = Array2::<f64>::zeros((n_mels, fft_freqs.len()));
+    for (m, mut row) in weights.outer_iter_mut().enumerate() {
+        let (lower, center, upper) = (mel_freqs[m], mel_freqs[m + 1], mel_freqs[m + 2]);
+        let enorm = 2. / (upper - lower);
+        for (w, f) in row.iter_mut().zip(fft_freqs.iter()) {
+            let rising = (f - lower) / (center - lower);
+            let falling = (upper - f) / (upper - center);
+            *w = f64::max(0., f64::min(rising, falling)) * enorm;
+        }
+    }
+
+    weights
+}
+
+/// Parameters a cached filterbank was computed with. Frequencies are compared by their bits.
+#[derive(Clone, Copy, PartialEq)]
+enum FilterbankKey {
+    Mel {
+        sr: u32,
+        n_fft: usize,
+        n_mels: usize,
+        fmin: u64,
+        fmax: u64,
+    },
+    Chroma {
+        sr: u32,
+        n_fft: usize,
+    },
+}
+
+/// Number of filterbanks each thread keeps.
+const FILTERBANK_CACHE_SIZE: usize = 8;
+
+thread_local! {
+    static FILTERBANKS: RefCell<Vec<(FilterbankKey, Rc<Array2<f64>>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Filterbank for `key`, computed by `compute` unless it is among the `FILTERBANK_CACHE_SIZE`
+/// most recently used ones of this thread. As with the FFT plans, a batch of files of the same
+/// format on the rayon pool computes its filterbank once per thread.
+fn cached_filterbank<F>(key: FilterbankKey, compute: F) -> Rc<Array2<f64>>
+where
+    F: FnOnce() -> Array2<f64>,
+{
+    FILTERBANKS.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        // The most recently used filterbank is last.
+        if let Some(i) = cache.iter().position(|(k, _)| *k == key) {
+            let entry = cache.remove(i);
+            cache.push(entry);
+        } else {
+            if cache.len() == FILTERBANK_CACHE_SIZE {
+                cache.remove(0);
+            }
+            cache.push((key, Rc::new(compute())));
+        }
+        cache[cache.len() - 1].1.clone()
+    })
+}
+
+/// `mel_filters`, cached per thread.
+pub fn mel_filters_cached(
+    sr: u32,
+    n_fft: usize,
+    n_mels: usize,
+    fmin: f64,
+    fmax: f64,
+) -> Rc<Array2<f64>> {
+    let key = FilterbankKey::Mel {
+        sr,
+        n_fft,
+        n_mels,
+        fmin: fmin.to_bits(),
+        fmax: fmax.to_bits(),
+    };
+    cached_filterbank(key, || mel_filters(sr, n_fft, n_mels, fmin, fmax))
+}
+
+/// `chroma_filters`, cached per thread.
+pub fn chroma_filters_cached(sr: u32, n_fft: usize) -> Rc<Array2<f64>> {
+    cached_filterbank(FilterbankKey::Chroma { sr, n_fft }, || {
+        chroma_filters(sr, n_fft)
+    })
+}
+
+/// Check that a user-supplied (n_bands, n_bins) filterbank applies to the bins of an `n_fft` FFT.
+pub fn check_filterbank(filterbank: &ArrayView2<f64>, n_fft: usize) {
+    if filterbank.ncols() != n_fft / 2 + 1 {
+        panic!(
+            "filterbank must have n_fft / 2 + 1 = {} columns, got {}",
+            n_fft / 2 + 1,
+            filterbank.ncols()
+        );
+    }
+}
+
+/// Mel-scaled spectrogram of the STFT magnitude raised to `power`, per channel. Returns a (t, n_mels, channels) array.
+/// A (n_bands, n_fft / 2 + 1) `filterbank`, e.g. on the Bark or ERB scale, replaces the mel filters
+/// and `n_mels`, giving a (t, n_bands, channels) array.
+#[allow(clippy::too_many_arguments)]
+pub fn melspectrogram(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    n_mels: usize,
+    filterbank: Option<&ArrayView2<f64>>,
+    power: f64,
+) -> Array3<f64> {
+    let filters = match filterbank {
+        Some(filterbank) => {
+            check_filterbank(filterbank, n_fft);
+            Rc::new(filterbank.to_owned())
+        }
+        None => mel_filters_cached(sr, n_fft, n_mels, 0., sr as f64 / 2.),
+    };
+    let channels: Vec<Array2<f64>> = arr
+        .outer_iter()
+        .map(|channel| {
+            let spec = stft(&channel, n_fft, hop_length, pad_mode);
+            let power = spectrogram_power(&spec.view(), power);
+            power.dot(&filters.t())
+        })
+        .collect();
+    let views: Vec<ArrayView2<f64>> = channels.iter().map(|c| c.view()).collect();
+
+    ndarray::stack(Axis(2), &views).expect("cannot stack channels")
+}
+
+/// Orthonormal DCT-II basis. Returns a (n_out, n_in) array.
+pub fn dct_matrix(n_out: usize, n_in: usize) -> Array2<f64> {
+    Array2::from_shape_fn((n_out, n_in), |(k, n)| {
+        let scale = if k == 0 {
+            (1. / n_in as f64).sqrt()
+        } else {
+            (2. / n_in as f64).sqrt()
+        };
+        scale * (PI * k as f64 * (2 * n + 1) as f64 / (2 * n_in) as f64).cos()
+    })
+}
+
+/// Mel-frequency cepstral coefficients, per channel. The log-mel spectrogram is clipped
+/// 80 dB below its peak before the DCT. Returns a (t, n_mfcc, channels) array.
+pub fn mfcc(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    n_mfcc: usize,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    n_mels: usize,
+) -> Array3<f64> {
+    let mut db = melspectrogram(arr, sr, n_fft, hop_length, pad_mode, n_mels, None, 2.)
+        .mapv(|p| convert::power_to_db(p, 1.));
+    let floor = db.fold(f64::NEG_INFINITY, |acc, x| acc.max(*x)) - 80.;
+    db.mapv_inplace(|v| v.max(floor));
+
+    let basis = dct_matrix(n_mfcc, n_mels);
+    let (n_frames, _, n_channels) = db.dim();
+    let mut out = Array3::<f64>::zeros((n_frames, n_mfcc, n_channels));
+    for c in 0..n_channels {
+        out.slice_mut(s![.., .., c])
+            .assign(&db.slice(s![.., .., c]).dot(&basis.t()));
+    }
+
+    out
+}
+
+/// Onset strength envelope of a (t, f, channels) feature array, typically a log-power spectrogram:
+/// the increase of every feature over the frame `lag` frames earlier, half-wave rectified and
+/// averaged across features. The first `lag` frames are zero. Returns a (t, channels) array.
+pub fn onset_strength(s: &ArrayView3<f64>, lag: usize) -> Array2<f64> {
+    let (n_frames, n_features, n_channels) = s.dim();
+    let mut out = Array2::<f64>::zeros((n_frames, n_channels));
+    for t in lag..n_frames {
+        for c in 0..n_channels {
+            let flux: f64 = (0..n_features)
+                .map(|f| f64::max(s[[t, f, c]] - s[[t - lag, f, c]], 0.))
+                .sum();
+            out[[t, c]] = flux / n_features as f64;
+        }
+    }
+
+    out
+}
+
+/// Window lengths in frames and thresholds of `peak_pick`, as in `librosa.util.peak_pick`.
+pub struct PeakPick {
+    pub pre_max: usize,
+    pub post_max: usize,
+    pub pre_avg: usize,
+    pub post_avg: usize,
+    pub delta: f64,
+    pub wait: usize,
+}
+
+impl PeakPick {
+    /// librosa's `onset_detect` defaults: a peak is the maximum of the 30 ms before it, exceeds
+    /// the mean of the 100 ms around it by `delta`, and follows the previous peak by over 30 ms.
+    pub fn for_onsets(sr: u32, hop_length: usize, delta: f64) -> PeakPick {
+        let frames = |seconds: f64| (seconds * sr as f64) as usize / hop_length;
+        PeakPick {
+            pre_max: frames(0.03),
+            post_max: frames(0.) + 1,
+            pre_avg: frames(0.1),
+            post_avg: frames(0.1) + 1,
+            delta,
+            wait: frames(0.03),
+        }
+    }
+}
+
+/// Indices of the peaks of `x`: the frames `n` that are the maximum of
+/// `x[n - pre_max..n + post_max]`, exceed the mean of `x[n - pre_avg..n + post_avg]` by at least
+/// `delta`, and come more than `wait` frames after the previous peak. Windows are cropped at the ends.
+pub fn peak_pick(x: &ArrayView1<f64>, opts: &PeakPick) -> Vec<usize> {
+    let n = x.len();
+    let window = |n_before: usize, n_after: usize, i: usize| {
+        x.slice(s![i.saturating_sub(n_before)..usize::min(i + n_after, n)])
+    };
+
+    let mut peaks: Vec<usize> = Vec::new();
+    for i in 0..n {
+        if peaks.last().is_some_and(|last| i <= last + opts.wait) {
+            continue;
+        }
+        let is_max = window(opts.pre_max, opts.post_max, i)
+            .iter()
+            .all(|v| *v <= x[i]);
+        let avg = window(opts.pre_avg, opts.post_avg, i)
+            .mean()
+            .unwrap_or(x[i]);
+        if is_max && x[i] >= avg + opts.delta {
+            peaks.push(i);
+        }
+    }
+    peaks
+}
+
+/// Sinusoidal lifter of HTK and Kaldi, the weight `1 + lifter / 2 * sin(pi * n / lifter)` of cepstral coefficient `n`.
+/// A `lifter` of 0 weights every coefficient by 1.
+pub fn lifter_weights(n_coefficients: usize, lifter: f64) -> Array1<f64> {
+    Array1::from_shape_fn(n_coefficients, |n| {
+        if lifter == 0. {
+            1.
+        } else {
+            1. + lifter / 2. * (PI * n as f64 / lifter).sin()
+        }
+    })
+}
+
+/// Divide every bin of a (t, f, channels) spectrogram by its average over time, per channel,
+/// so that the long-term spectrum is flat. Bins that are zero throughout are left at zero.
+pub fn whiten(s: &ArrayView3<f64>) -> Array3<f64> {
+    let mut out = s.to_owned();
+    for mut channel in out.axis_iter_mut(Axis(2)) {
+        for mut bin in channel.axis_iter_mut(Axis(1)) {
+            let mean = bin.mean().unwrap_or(0.);
+            if mean != 0. {
+                bin.mapv_inplace(|v| v / mean);
+            }
+        }
+    }
+
+    out
+}
+
+/// Shannon entropy in bits of the power spectrum of each STFT frame, per channel, treating the
+/// frame's power across bins as a probability distribution. A pure tone is close to 0 and white
+/// noise close to `log2(n_fft / 2 + 1)`; `normalize` divides by that maximum to scale to \[0, 1\].
+/// Silent frames are 0. Returns a (t, 1, channels) array.
+pub fn spectral_entropy(
+    arr: &ArrayView2<f64>,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    normalize: bool,
+) -> Array3<f64> {
+    let n_bins = n_fft / 2 + 1;
+    let max_entropy = if n_bins > 1 {
+        (n_bins as f64).log2()
+    } else {
+        1.
+    };
+    let channels: Vec<Array2<f64>> = arr
+        .outer_iter()
+        .map(|channel| {
+            let spec = stft(&channel, n_fft, hop_length, pad_mode);
+            let entropy = spectrogram_power(&spec.view(), 2.).map_axis(Axis(1), |frame| {
+                let total = frame.sum();
+                if total <= 0. {
+                    return 0.;
+                }
+                let h: f64 = frame
+                    .iter()
+                    .filter(|p| **p > 0.)
+                    .map(|p| {
+                        let p = p / total;
+                        -p * p.log2()
+                    })
+                    .sum();
+                if normalize {
+                    h / max_entropy
+                } else {
+                    h
+                }
+            });
+            entropy.insert_axis(Axis(1))
+        })
+        .collect();
+    let views: Vec<ArrayView2<f64>> = channels.iter().map(|c| c.view()).collect();
+
+    ndarray::stack(Axis(2), &views).expect("cannot stack channels")
+}
+
+/// Frequency (Hz) of C0, the lowest C of the piano's octave numbering, from A4 = 440 Hz.
+const C0: f64 = 16.351597831287414;
+
+/// Chroma filterbank mapping the bins of a real FFT to 12 pitch classes, starting at C.
+/// Each bin spreads over the pitch classes with a Gaussian of at least half a semitone, wider for
+/// the low bins that span several semitones, and is weighted by a Gaussian two octaves wide around C5.
+/// The DC bin is left out. Returns a (12, n_fft / 2 + 1) array whose rows have unit L2 norm.
+pub fn chroma_filters(sr: u32, n_fft: usize) -> Array2<f64> {
+    let fft_freqs = convert::fft_frequencies(sr, n_fft);
+    let bin_width = sr as f64 / n_fft as f64;
+
+    let mut weights = Array2::<f64>::zeros((12, fft_freqs.len()));
+    for (k, f) in fft_freqs.iter().enumerate().skip(1) {
+        let semitones = 12. * (f / C0).log2();
+        let width = 12. * ((f + bin_width / 2.) / f64::max(f - bin_width / 2., C0 / 2.)).log2();
+        let sigma = 0.5 * f64::max(width, 1.);
+        let octave = (semitones / 12. - 5.) / 2.;
+        let octave_weight = (-0.5 * octave * octave).exp();
+        for p in 0..12 {
+            // distance to the pitch class, wrapped to [-6, 6) semitones
+            let d = (semitones - p as f64 + 6.).rem_euclid(12.) - 6.;
+            weights[[p, k]] = (-0.5 * (d / sigma).powi(2)).exp() * octave_weight;
+        }
+    }
+    for mut row in weights.outer_iter_mut() {
+        let norm = row.dot(&row).sqrt();
+        if norm > 0. {
+            row /= norm;
+        }
+    }
+
+    weights
+}
+
+/// Chromagram of the STFT magnitude raised to `power`, per channel. Each frame is scaled so its largest pitch
+/// class is 1, unless it is silent. Returns a (t, 12, channels) array.
+/// A (n_bands, n_fft / 2 + 1) `filterbank` replaces the chroma filters, giving a (t, n_bands, channels) array.
+pub fn chroma_stft(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    filterbank: Option<&ArrayView2<f64>>,
+    power: f64,
+) -> Array3<f64> {
+    let filters = match filterbank {
+        Some(filterbank) => {
+            check_filterbank(filterbank, n_fft);
+            Rc::new(filterbank.to_owned())
+        }
+        None => chroma_filters_cached(sr, n_fft),
+    };
+    let channels: Vec<Array2<f64>> = arr
+        .outer_iter()
+        .map(|channel| {
+            let spec = stft(&channel, n_fft, hop_length, pad_mode);
+            let power = spectrogram_power(&spec.view(), power);
+            let mut chroma = power.dot(&filters.t());
+            for mut frame in chroma.outer_iter_mut() {
+                let peak = frame.fold(0., |acc: f64, x| acc.max(*x));
+                if peak > 0. {
+                    frame /= peak;
+                }
+            }
+            chroma
+        })
+        .collect();
+    let views: Vec<ArrayView2<f64>> = channels.iter().map(|c| c.view()).collect();
+
+    ndarray::stack(Axis(2), &views).expect("cannot stack channels")
+}
+
+/// Krumhansl-Kessler key profiles for C major and C minor.
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+pub const PITCH_CLASSES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+fn pearson(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let (mx, my) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let cov: f64 = x.iter().zip(y).map(|(a, b)| (a - mx) * (b - my)).sum();
+    let vx: f64 = x.iter().map(|a| (a - mx).powi(2)).sum();
+    let vy: f64 = y.iter().map(|b| (b - my).powi(2)).sum();
+    if vx == 0. || vy == 0. {
+        0.
+    } else {
+        cov / (vx * vy).sqrt()
+    }
+}
+
+/// Krumhansl-Schmuckler key finding: the correlation of a 12-bin pitch class distribution with the
+/// major and minor key profiles rotated to every tonic. Returns 24 correlations, the 12 major keys
+/// from C to B followed by the 12 minor keys.
+pub fn key_correlations(chroma: &[f64]) -> Vec<f64> {
+    [MAJOR_PROFILE, MINOR_PROFILE]
+        .iter()
+        .flat_map(|profile| {
+            (0..12).map(move |tonic| {
+                let rotated: Vec<f64> = (0..12).map(|p| profile[(p + 12 - tonic) % 12]).collect();
+                pearson(chroma, &rotated)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_features {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_frame_pad_modes() {
+        let x = array![1., 2., 3., 4.];
+        let first = |mode| frame(&x.view(), 6, 2, &mode).row(0).to_vec();
+        assert_eq!(first(PadMode::Constant), vec![0., 0., 0., 1., 2., 3.]);
+        assert_eq!(first(PadMode::Edge), vec![1., 1., 1., 1., 2., 3.]);
+        assert_eq!(first(PadMode::Reflect), vec![4., 3., 2., 1., 2., 3.]);
+    }
+
+    #[test]
+    fn test_mean_channels() {
+        let s = Array3::from_shape_fn((3, 2, 2), |(t, f, c)| (t + f) as f64 * (c + 1) as f64);
+        let mean = Channels::from_name(Some("mean")).apply(s.clone());
+        assert_eq!(mean.dim(), (3, 2, 1));
+        assert_eq!(mean.slice(s![.., 1, 0]), array![1.5, 3., 4.5]);
+        assert_eq!(Channels::from_name(None).apply(s.clone()), s);
+
+        let z = Array3::from_elem((1, 1, 2), Complex::new(1., -1.));
+        assert_eq!(mean_channels(&z.view())[[0, 0, 0]], Complex::new(1., -1.));
+    }
+
+    #[test]
+    fn test_rms_pad_modes() {
+        let x = Array2::<f64>::ones((1, 64));
+        let energy = |mode| rms(&x.view(), 16, 8, &mode);
+        assert!(energy(PadMode::Reflect)
+            .iter()
+            .all(|v| (v - 1.).abs() < 1e-12));
+        assert!(energy(PadMode::Edge).iter().all(|v| (v - 1.).abs() < 1e-12));
+        let zero_padded = energy(PadMode::Constant);
+        assert!((zero_padded[[0, 0]] - 0.5_f64.sqrt()).abs() < 1e-12);
+        assert!((zero_padded[[0, 4]] - 1.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_band_bins() {
+        // bins are 31.25 Hz apart
+        assert_eq!(band_bins(8000, 256, 80., 1000.), 3..33);
+        assert_eq!(band_bins(8000, 256, 0., 4000.), 0..129);
+        assert_eq!(band_bins(8000, 256, 4500., 5000.), 129..129);
+    }
+
+    #[test]
+    fn test_onset_strength() {
+        // A single feature stepping up at frame 3 and back down at frame 5.
+        let mut s = Array3::<f64>::zeros((8, 2, 1));
+        s.slice_mut(s![3..5, 0, 0]).fill(4.);
+        let env = onset_strength(&s.view(), 1);
+        assert_eq!(env.column(0), array![0., 0., 0., 2., 0., 0., 0., 0.]);
+
+        let env = onset_strength(&s.view(), 2);
+        assert_eq!(env.column(0), array![0., 0., 0., 2., 2., 0., 0., 0.]);
+    }
+
+    #[test]
+    fn test_peak_pick() {
+        let x = array![0., 1., 0., 0., 0.5, 0.6, 0., 0., 0., 0.05, 0.];
+        let opts = PeakPick {
+            pre_max: 1,
+            post_max: 2,
+            pre_avg: 2,
+            post_avg: 3,
+            delta: 0.1,
+            wait: 0,
+        };
+        // 4 is not a local maximum and 9 is within `delta` of its neighbourhood.
+        assert_eq!(peak_pick(&x.view(), &opts), [1, 5]);
+        let opts = PeakPick { wait: 4, ..opts };
+        assert_eq!(peak_pick(&x.view(), &opts), [1]);
+
+        let opts = PeakPick::for_onsets(22050, 512, 0.07);
+        assert_eq!(
+            (
+                opts.pre_max,
+                opts.post_max,
+                opts.pre_avg,
+                opts.post_avg,
+                opts.wait
+            ),
+            (1, 1, 4, 5, 1)
+        );
+    }
+
+    #[test]
+    fn test_lifter_and_whiten() {
+        let w = lifter_weights(23, 22.);
+        assert_eq!(w[0], 1.);
+        assert!((w[11] - 12.).abs() < 1e-12);
+        assert!((w[22] - 1.).abs() < 1e-12);
+        assert_eq!(lifter_weights(3, 0.), array![1., 1., 1.]);
+
+        let s = Array3::from_shape_fn((4, 3, 2), |(t, f, c)| (f * (t + 1) * c) as f64);
+        let white = whiten(&s.view());
+        assert_eq!(white.slice(s![.., 0, ..]), Array2::<f64>::zeros((4, 2)));
+        assert_eq!(white.slice(s![.., .., 0]), Array2::<f64>::zeros((4, 3)));
+        assert_eq!(white.slice(s![.., 2, 1]), array![0.4, 0.8, 1.2, 1.6]);
+    }
+
+    #[test]
+    fn test_spectral_entropy() {
+        // A tone, noise from a linear congruential generator, and silence.
+        let mut state = 12345_u64;
+        let x = Array2::from_shape_fn((3, 8192), |(c, i)| match c {
+            0 => (i as f64 * 0.3).sin(),
+            1 => {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (state >> 11) as f64 / (1_u64 << 53) as f64 - 0.5
+            }
+            _ => 0.,
+        });
+        let h = spectral_entropy(&x.view(), 512, 128, &PadMode::Constant, true);
+        assert_eq!(h.dim(), (65, 1, 3));
+
+        let middle = s![8..56, 0, ..];
+        assert!(h.slice(middle).column(0).iter().all(|v| *v < 0.3));
+        assert!(h
+            .slice(middle)
+            .column(1)
+            .iter()
+            .all(|v| *v > 0.85 && *v <= 1.));
+        assert!(h.slice(s![.., 0, 2]).iter().all(|v| *v == 0.));
+
+        let bits = spectral_entropy(&x.view(), 512, 128, &PadMode::Constant, false);
+        let ratio = bits[[20, 0, 1]] / h[[20, 0, 1]];
+        assert!((ratio - 257_f64.log2()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_welch_psd_sine_power() {
+        // Integrating the density gives back the mean power of the signal.
+        let (sr, n_fft) = (8000, 256);
+        let x = Array1::from_shape_fn(80000, |i| (2. * PI * 1000. * i as f64 / sr as f64).sin());
+        for window in [Window::Hann, Window::Hamming, Window::Rectangular] {
+            let psd = welch_psd(&x.view(), sr, n_fft, 128, &window);
+            assert_eq!(psd.len(), n_fft / 2 + 1);
+            let power = psd.sum() * sr as f64 / n_fft as f64;
+            assert!((power - 0.5).abs() < 1e-3, "{}", power);
+            assert_eq!(
+                psd.iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                    .unwrap()
+                    .0,
+                32
+            );
+        }
+    }
+
+    #[test]
+    fn test_stft_sine_peak() {
+        // A 1 kHz sine at 8 kHz sits exactly on bin 32 of a 256-point FFT.
+        let (sr, n_fft) = (8000., 256);
+        let x = Array1::from_shape_fn(4000, |i| (2. * PI * 1000. * i as f64 / sr).sin());
+        let spec = stft(&x.view(), n_fft, 64, &PadMode::Constant);
+
+        assert_eq!(spec.ncols(), n_fft / 2 + 1);
+        assert_eq!(spec.nrows(), 1 + 4000 / 64);
+        let mid = spec.row(spec.nrows() / 2).mapv(|v| v.norm());
+        let peak = mid.iter().enumerate().fold(
+            (0, 0.),
+            |best, (k, v)| if *v > best.1 { (k, *v) } else { best },
+        );
+        assert_eq!(peak.0, 32);
+    }
+
+    #[test]
+    fn test_istft_round_trip() {
+        let x = Array1::from_shape_fn(3072, |i| {
+            (i as f64 * 0.05).sin() + 0.3 * (i as f64 * 0.71).cos()
+        });
+        let spec = stft(&x.view(), 512, 128, &PadMode::Constant);
+        let y = istft(&spec.view(), 512, 128, Some(x.len()));
+
+        assert!(Zip::from(&x).and(&y).all(|a, b| (a - b).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_istft_round_trip_odd_n_fft() {
+        let x = Array1::from_shape_fn(3000, |i| {
+            (i as f64 * 0.05).sin() + 0.3 * (i as f64 * 0.71).cos()
+        });
+        for pad_mode in [PadMode::Constant, PadMode::Reflect] {
+            let spec = stft(&x.view(), 511, 128, &pad_mode);
+            assert_eq!(spec.ncols(), 256);
+            let y = istft(&spec.view(), 511, 128, Some(x.len()));
+            assert!(Zip::from(&x).and(&y).all(|a, b| (a - b).abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_istft_round_trip_file() {
+        let x = crate::decode_symphonia::load(
+            std::path::Path::new("../../test_files/mono.wav"),
+            true,
+            0.,
+            None,
+            None,
+            false,
+            &crate::decode_symphonia::Norm::FullScale,
+            Some("wav"),
+        );
+        for pad_mode in [PadMode::Constant, PadMode::Reflect] {
+            let spec = stft(&x.row(0), 2048, 512, &pad_mode);
+            let y = istft(&spec.view(), 2048, 512, Some(x.ncols()));
+            assert!(Zip::from(x.row(0)).and(&y).all(|a, b| (a - b).abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_fft_plan_cache() {
+        assert!(Arc::ptr_eq(&forward_fft(512), &forward_fft(512)));
+        assert!(!Arc::ptr_eq(&forward_fft(512), &forward_fft(1024)));
+
+        // Planning other lengths evicts the least recently used plan.
+        let plan = inverse_fft(512);
+        for n in 0..FFT_CACHE_SIZE {
+            inverse_fft(1000 + n);
+        }
+        assert!(!Arc::ptr_eq(&plan, &inverse_fft(512)));
+        INVERSE_FFTS.with(|cache| assert_eq!(cache.borrow().len(), FFT_CACHE_SIZE));
+    }
+
+    #[test]
+    fn test_chroma_and_key() {
+        // A, C# and E: an A major triad
+        let sr = 22050;
+        let x = Array1::from_shape_fn(sr as usize, |i| {
+            [440., 554.37, 659.26]
+                .iter()
+                .map(|f| (2. * PI * f * i as f64 / sr as f64).sin())
+                .sum::<f64>()
+        });
+        let arr = x.insert_axis(Axis(0));
+
+        let chroma = chroma_stft(&arr.view(), sr, 2048, 512, &PadMode::Constant, None, 2.);
+        assert_eq!(chroma.dim().1, 12);
+        let mean = chroma.mean_axis(Axis(0)).unwrap().column(0).to_vec();
+        let mut strongest: Vec<usize> = (0..12).collect();
+        strongest.sort_by(|a, b| mean[*b].partial_cmp(&mean[*a]).unwrap());
+        strongest.truncate(3);
+        strongest.sort();
+        assert_eq!(strongest, vec![1, 4, 9]);
+
+        let correlations = key_correlations(&mean);
+        let best = (0..24)
+            .max_by(|a, b| correlations[*a].partial_cmp(&correlations[*b]).unwrap())
+            .unwrap();
+        assert_eq!(best, 9);
+    }
+
+    #[test]
+    fn test_rfft_round_trip() {
+        for n in [7, 8] {
+            let x: Vec<f64> = (0..n).map(|i| (i as f64 * 0.7).sin() + 0.25).collect();
+            let spectrum = rfft(&x);
+            assert_eq!(spectrum.len(), n / 2 + 1);
+            assert!((spectrum[0].re - x.iter().sum::<f64>()).abs() < 1e-12);
+            let back = irfft(&spectrum, n);
+            assert!(x.iter().zip(&back).all(|(a, b)| (a - b).abs() < 1e-12));
+        }
+    }
+
+    #[test]
+    fn test_if_gram_sub_bin() {
+        // 1010 Hz lies between bins 32 (1000 Hz) and 33 (1031.25 Hz) of a 256-point FFT at 8 kHz.
+        let sr = 8000;
+        let x = Array1::from_shape_fn(4000, |i| (2. * PI * 1010. * i as f64 / sr as f64).sin());
+        let (freqs, mag) = if_gram(&x.view(), sr, 256, 64, &PadMode::Constant);
+
+        let frame = mag.nrows() / 2;
+        assert!(mag[[frame, 32]] > mag[[frame, 30]]);
+        assert!((freqs[[frame, 32]] - 1010.).abs() < 1.);
+        assert!((freqs[[frame, 33]] - 1010.).abs() < 1.);
+    }
+
+    #[test]
+    fn test_filterbank_cache() {
+        let mel = mel_filters_cached(8000, 256, 20, 0., 4000.);
+        assert_eq!(*mel, mel_filters(8000, 256, 20, 0., 4000.));
+        assert!(Rc::ptr_eq(
+            &mel,
+            &mel_filters_cached(8000, 256, 20, 0., 4000.)
+        ));
+        assert!(!Rc::ptr_eq(
+            &mel,
+            &mel_filters_cached(8000, 256, 20, 0., 3999.)
+        ));
+
+        // Filling the cache with other filterbanks evicts the least recently used one.
+        for n_fft in 0..FILTERBANK_CACHE_SIZE {
+            chroma_filters_cached(8000, 64 + 2 * n_fft);
+        }
+        assert!(!Rc::ptr_eq(
+            &mel,
+            &mel_filters_cached(8000, 256, 20, 0., 4000.)
+        ));
+        FILTERBANKS.with(|cache| assert_eq!(cache.borrow().len(), FILTERBANK_CACHE_SIZE));
+    }
+
+    #[test]
+    fn test_custom_filterbank() {
+        let x = Array1::from_shape_fn(2048, |i| (i as f64 * 0.3).sin()).insert_axis(Axis(0));
+        let mel = mel_filters(8000, 256, 20, 0., 4000.);
+        assert_eq!(
+            melspectrogram(
+                &x.view(),
+                8000,
+                256,
+                64,
+                &PadMode::Constant,
+                20,
+                Some(&mel.view()),
+                2.
+            ),
+            melspectrogram(&x.view(), 8000, 256, 64, &PadMode::Constant, 20, None, 2.)
+        );
+
+        let bands = Array2::<f64>::ones((3, 129));
+        let out = chroma_stft(
+            &x.view(),
+            8000,
+            256,
+            64,
+            &PadMode::Constant,
+            Some(&bands.view()),
+            2.,
+        );
+        assert_eq!(out.dim().1, 3);
+    }
+
+    #[test]
+    fn test_default_frame_params() {
+        assert_eq!(default_frame_params(22050), (2048, 512));
+        assert_eq!(default_frame_params(44100), (4096, 1024));
+        assert_eq!(default_frame_params(48000), (4096, 1024));
+        assert_eq!(default_frame_params(8000), (1024, 256));
+    }
+}
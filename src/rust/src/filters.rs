@@ -0,0 +1,184 @@
+use std::f64::consts::PI;
+
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2};
+
+use crate::features;
+
+/// Biquad filter coefficients, normalised so a0 = 1.
+pub struct Biquad {
+    pub b: [f64; 3],
+    pub a: [f64; 2],
+}
+
+impl Biquad {
+    /// Band-stop at `f0` Hz with quality factor `q`, the center frequency over the -3 dB
+    /// bandwidth (bilinear transform of the analog notch, from the RBJ audio EQ cookbook).
+    pub fn notch(f0: f64, q: f64, sr: u32) -> Biquad {
+        let w0 = 2. * PI * f0 / sr as f64;
+        let alpha = w0.sin() / (2. * q);
+        let a0 = 1. + alpha;
+        let cos = w0.cos();
+        Biquad {
+            b: [1. / a0, -2. * cos / a0, 1. / a0],
+            a: [-2. * cos / a0, (1. - alpha) / a0],
+        }
+    }
+
+    /// Direct form II transposed.
+    pub fn filter(&self, x: &ArrayView1<f64>) -> Array1<f64> {
+        let (mut z1, mut z2) = (0., 0.);
+        x.mapv(|v| {
+            let y = self.b[0] * v + z1;
+            z1 = self.b[1] * v - self.a[0] * y + z2;
+            z2 = self.b[2] * v - self.a[1] * y;
+            y
+        })
+    }
+}
+
+/// Remove hum at `freq` Hz and its first `harmonics - 1` overtones from each channel of the
+/// (channels, nsamples) `arr` with a cascade of notches of quality factor `q`. Harmonics at or
+/// above the Nyquist frequency are skipped.
+pub fn notch(arr: &ArrayView2<f64>, sr: u32, freq: f64, q: f64, harmonics: usize) -> Array2<f64> {
+    let sections: Vec<Biquad> = (1..=harmonics)
+        .map(|h| h as f64 * freq)
+        .take_while(|f| *f < sr as f64 / 2.)
+        .map(|f| Biquad::notch(f, q, sr))
+        .collect();
+
+    let mut out = arr.to_owned();
+    for mut channel in out.outer_iter_mut() {
+        for section in &sections {
+            let filtered = section.filter(&channel.view());
+            channel.assign(&filtered);
+        }
+    }
+    out
+}
+
+/// Linear convolution of `x` with the impulse response `ir` by overlap-add: the FFT of blocks of
+/// `x` is multiplied with that of `ir` and the inverse FFTs are summed at their offsets. The FFT
+/// length is the power of two at least twice as long as `ir`, so long signals are processed in
+/// blocks about as long as `ir`. Returns `x.len() + ir.len() - 1` samples.
+pub fn convolve(x: &ArrayView1<f64>, ir: &ArrayView1<f64>) -> Array1<f64> {
+    if x.is_empty() || ir.is_empty() {
+        panic!("cannot convolve empty signals");
+    }
+    let n_fft = (2 * ir.len()).next_power_of_two();
+    let block = n_fft - ir.len() + 1;
+    let forward = features::forward_fft(n_fft);
+    let inverse = features::inverse_fft(n_fft);
+
+    let mut input = forward.make_input_vec();
+    for (b, v) in input.iter_mut().zip(ir) {
+        *b = *v;
+    }
+    let mut ir_spectrum = forward.make_output_vec();
+    forward
+        .process(&mut input, &mut ir_spectrum)
+        .expect("cannot compute the fft");
+
+    let mut out = Array1::<f64>::zeros(x.len() + ir.len() - 1);
+    let mut spectrum = forward.make_output_vec();
+    let mut output = inverse.make_output_vec();
+    for start in (0..x.len()).step_by(block) {
+        let end = usize::min(start + block, x.len());
+        input.iter_mut().for_each(|v| *v = 0.);
+        for (b, v) in input.iter_mut().zip(x.slice(s![start..end])) {
+            *b = *v;
+        }
+        forward
+            .process(&mut input, &mut spectrum)
+            .expect("cannot compute the fft");
+        for (v, h) in spectrum.iter_mut().zip(ir_spectrum.iter()) {
+            *v *= h / n_fft as f64;
+        }
+        // The imaginary parts of DC and Nyquist are rounding errors of a real signal.
+        spectrum[0].im = 0.;
+        spectrum[n_fft / 2].im = 0.;
+        inverse
+            .process(&mut spectrum, &mut output)
+            .expect("cannot compute the inverse fft");
+
+        let n_out = usize::min(end - start + ir.len() - 1, out.len() - start);
+        out.slice_mut(s![start..start + n_out])
+            .zip_mut_with(&ArrayView1::from(&output[..n_out]), |o, v| *o += v);
+    }
+    out
+}
+
+/// Convolve the (channels, nsamples) `arr` with the (ir_channels, ir_len) `ir`. Each channel is
+/// convolved with the channel of `ir` of the same index; a single channel on either side is used
+/// for every channel of the other, e.g. a stereo room response turns a mono signal into stereo.
+/// Returns a (max(channels, ir_channels), nsamples + ir_len - 1) array.
+pub fn convolve_channels(arr: &ArrayView2<f64>, ir: &ArrayView2<f64>) -> Array2<f64> {
+    let channels = match (arr.nrows(), ir.nrows()) {
+        (a, b) if a == b || b == 1 => a,
+        (1, b) => b,
+        (a, b) => panic!(
+            "cannot convolve {} channels with an impulse response of {}, it must have 1 or {}",
+            a, b, a
+        ),
+    };
+    let pick = |m: &ArrayView2<f64>, c: usize| m.row(if m.nrows() == 1 { 0 } else { c }).to_owned();
+
+    let mut out = Array2::<f64>::zeros((channels, arr.ncols() + ir.ncols() - 1));
+    for (c, mut row) in out.outer_iter_mut().enumerate() {
+        row.assign(&convolve(&pick(arr, c).view(), &pick(ir, c).view()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test_filters {
+    use super::*;
+
+    #[test]
+    fn test_convolve_matches_direct() {
+        let x = Array1::from_shape_fn(1000, |i| ((i * 7919) % 113) as f64 / 56. - 1.);
+        let ir = Array1::from_shape_fn(37, |i| {
+            (-(i as f64) / 8.).exp() * if i % 2 == 0 { 1. } else { -0.5 }
+        });
+        let direct = Array1::from_shape_fn(x.len() + ir.len() - 1, |n| {
+            (0..ir.len())
+                .filter(|k| n >= *k && n - k < x.len())
+                .map(|k| ir[k] * x[n - k])
+                .sum::<f64>()
+        });
+
+        let out = convolve(&x.view(), &ir.view());
+        assert_eq!(out.len(), direct.len());
+        assert!(out
+            .iter()
+            .zip(direct.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-9));
+
+        // a mono signal through a stereo impulse response
+        let ir = ndarray::array![[1., 0.5], [0., -1.]];
+        let arr = ndarray::array![[1., 2., 3.]];
+        let out = convolve_channels(&arr.view(), &ir.view());
+        let expected = ndarray::array![[1., 2.5, 4., 1.5], [0., -1., -2., -3.]];
+        assert!(out
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_notch_removes_hum() {
+        let sr = 8000;
+        let n = 4 * sr as usize;
+        let sine = |f: f64| crate::synth::tone(f, sr, n, 1.);
+        let rms = |x: ArrayView1<f64>| (x.dot(&x) / x.len() as f64).sqrt();
+        // skip the first second while the notches settle
+        let settled = s![sr as usize..];
+
+        let hum = (sine(50.) + sine(150.)).insert_axis(ndarray::Axis(0));
+        let cleaned = notch(&hum.view(), sr, 50., 30., 3);
+        assert!(rms(cleaned.row(0).slice(settled)) < 1e-3);
+
+        let tone = sine(1000.).insert_axis(ndarray::Axis(0));
+        let kept = notch(&tone.view(), sr, 50., 30., 3);
+        assert!((rms(kept.row(0).slice(settled)) - rms(tone.row(0).slice(settled))).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,325 @@
+use std::f64::consts::PI;
+
+use ndarray::{s, Array1, Array2, ArrayView1, Axis};
+
+use crate::features;
+use crate::util::SplitMix64;
+
+/// Floor of the energies before taking their log, `FLT_EPSILON` as in Kaldi.
+const EPSILON: f64 = f32::EPSILON as f64;
+
+/// Lowest frequency (Hz) of the mel filterbank, Kaldi's `--low-freq` default. The highest is the Nyquist frequency.
+const LOW_FREQ: f64 = 20.;
+
+/// Framing and preprocessing of Kaldi's `compute-fbank-feats` and `compute-mfcc-feats`.
+/// The defaults are Kaldi's, except `dither`, which is 0 so the features are reproducible.
+pub struct KaldiOptions {
+    /// Frame length in milliseconds.
+    pub frame_length: f64,
+    /// Frame shift in milliseconds.
+    pub frame_shift: f64,
+    pub n_mels: usize,
+    /// Add the log energy of each frame, as the first filterbank channel or in place of C0.
+    pub use_energy: bool,
+    /// Standard deviation of the Gaussian noise added to every sample.
+    pub dither: f64,
+    pub remove_dc_offset: bool,
+    pub preemph_coeff: f64,
+    /// Only keep frames that fit entirely in the signal, rather than one frame per `frame_shift`
+    /// with the signal mirrored at the ends.
+    pub snip_edges: bool,
+    /// Take the energy before pre-emphasis and windowing.
+    pub raw_energy: bool,
+}
+
+impl Default for KaldiOptions {
+    fn default() -> KaldiOptions {
+        KaldiOptions {
+            frame_length: 25.,
+            frame_shift: 10.,
+            n_mels: 23,
+            use_energy: false,
+            dither: 0.,
+            remove_dc_offset: true,
+            preemph_coeff: 0.97,
+            snip_edges: true,
+            raw_energy: true,
+        }
+    }
+}
+
+/// Kaldi's default window, a Hann window raised to the power 0.85 that does not go to zero as fast.
+fn povey_window(n: usize) -> Array1<f64> {
+    let a = 2. * PI / (n as f64 - 1.);
+    Array1::from_shape_fn(n, |i| (0.5 - 0.5 * (a * i as f64).cos()).powf(0.85))
+}
+
+fn mel(f: f64) -> f64 {
+    1127. * (1. + f / 700.).ln()
+}
+
+/// Kaldi's mel filterbank, triangles equally spaced on its mel scale from `LOW_FREQ` to the
+/// Nyquist frequency with a peak of 1. Returns a (n_mels, padded / 2) array: the Nyquist bin is not used.
+fn mel_banks(sr: u32, padded: usize, n_mels: usize) -> Array2<f64> {
+    let mel_low = mel(LOW_FREQ);
+    let mel_high = mel(sr as f64 / 2.);
+    let delta = (mel_high - mel_low) / (n_mels as f64 + 1.);
+    let bin_width = sr as f64 / padded as f64;
+
+    Array2::from_shape_fn((n_mels, padded / 2), |(m, i)| {
+        let left = mel_low + m as f64 * delta;
+        let center = left + delta;
+        let right = center + delta;
+        let mel = mel(bin_width * i as f64);
+        if mel <= left || mel >= right {
+            0.
+        } else if mel <= center {
+            (mel - left) / (center - left)
+        } else {
+            (right - mel) / (right - center)
+        }
+    })
+}
+
+/// Frame, preprocess and transform `x` as Kaldi does. Returns the (n_frames, n_mels) mel energies
+/// and the log energy of every frame.
+fn mel_energies(x: &ArrayView1<f64>, sr: u32, opts: &KaldiOptions) -> (Array2<f64>, Vec<f64>) {
+    let window_size = (sr as f64 * 0.001 * opts.frame_length) as usize;
+    let window_shift = (sr as f64 * 0.001 * opts.frame_shift) as usize;
+    if window_size < 2 || window_shift == 0 {
+        panic!("frame_length and frame_shift are too short for the sampling rate");
+    }
+    let padded = window_size.next_power_of_two();
+    let n = x.len();
+    let n_frames = match opts.snip_edges {
+        true if n < window_size => 0,
+        true => 1 + (n - window_size) / window_shift,
+        false => (n + window_shift / 2) / window_shift,
+    };
+    if n == 0 && n_frames > 0 {
+        panic!("cannot frame an empty signal");
+    }
+
+    let window = povey_window(window_size);
+    let banks = mel_banks(sr, padded, opts.n_mels);
+    let mut rng = SplitMix64::from_time();
+
+    let mut energies = Array2::<f64>::zeros((n_frames, opts.n_mels));
+    let mut log_energy = Vec::with_capacity(n_frames);
+    let mut frame = vec![0.; padded];
+    for t in 0..n_frames {
+        let start = match opts.snip_edges {
+            true => (t * window_shift) as isize,
+            false => (t * window_shift + window_shift / 2) as isize - (window_size / 2) as isize,
+        };
+        // Samples past the ends are mirrored, without repeating the first and last ones twice.
+        for (i, v) in frame[..window_size].iter_mut().enumerate() {
+            let mut j = start + i as isize;
+            if j < 0 {
+                j = -j - 1;
+            } else if j >= n as isize {
+                j = 2 * n as isize - 1 - j;
+            }
+            *v = x[j.clamp(0, n as isize - 1) as usize];
+        }
+        let samples = &mut frame[..window_size];
+
+        if opts.dither != 0. {
+            samples
+                .iter_mut()
+                .for_each(|v| *v += opts.dither * rng.next_normal());
+        }
+        if opts.remove_dc_offset {
+            let mean = samples.iter().sum::<f64>() / window_size as f64;
+            samples.iter_mut().for_each(|v| *v -= mean);
+        }
+        let energy = |frame: &[f64]| f64::max(frame.iter().map(|v| v * v).sum(), EPSILON).ln();
+        let raw = energy(samples);
+        if opts.preemph_coeff != 0. {
+            for i in (1..window_size).rev() {
+                samples[i] -= opts.preemph_coeff * samples[i - 1];
+            }
+            samples[0] -= opts.preemph_coeff * samples[0];
+        }
+        samples
+            .iter_mut()
+            .zip(window.iter())
+            .for_each(|(v, w)| *v *= w);
+        log_energy.push(if opts.raw_energy { raw } else { energy(&frame) });
+
+        let power: Array1<f64> = features::rfft(&frame)[..padded / 2]
+            .iter()
+            .map(|z| z.norm_sqr())
+            .collect();
+        energies.row_mut(t).assign(&banks.dot(&power));
+    }
+
+    (energies, log_energy)
+}
+
+/// Log mel filterbank energies as computed by Kaldi's `compute-fbank-feats`.
+/// Returns a (n_frames, n_mels) array, or (n_frames, n_mels + 1) with the log energy first when `use_energy`.
+pub fn fbank(x: &ArrayView1<f64>, sr: u32, opts: &KaldiOptions) -> Array2<f64> {
+    let (energies, log_energy) = mel_energies(x, sr, opts);
+    let log_mel = energies.mapv(|e| f64::max(e, EPSILON).ln());
+    if !opts.use_energy {
+        return log_mel;
+    }
+
+    let mut out = Array2::<f64>::zeros((log_mel.nrows(), opts.n_mels + 1));
+    out.column_mut(0).assign(&Array1::from(log_energy));
+    out.slice_mut(s![.., 1..]).assign(&log_mel);
+    out
+}
+
+/// MFCCs as computed by Kaldi's `compute-mfcc-feats`: the orthonormal DCT of the log mel energies,
+/// liftered, with C0 replaced by the log energy when `use_energy`. Returns a (n_frames, n_mfcc) array.
+pub fn mfcc(
+    x: &ArrayView1<f64>,
+    sr: u32,
+    n_mfcc: usize,
+    lifter: f64,
+    opts: &KaldiOptions,
+) -> Array2<f64> {
+    if n_mfcc > opts.n_mels {
+        panic!("n_mfcc must not exceed n_mels");
+    }
+    let (energies, log_energy) = mel_energies(x, sr, opts);
+    let log_mel = energies.mapv(|e| f64::max(e, EPSILON).ln());
+
+    let basis = features::dct_matrix(n_mfcc, opts.n_mels);
+    let mut out = log_mel.dot(&basis.t());
+    let weights = features::lifter_weights(n_mfcc, lifter);
+    for mut row in out.axis_iter_mut(Axis(0)) {
+        row *= &weights;
+    }
+    if opts.use_energy && n_mfcc > 0 {
+        out.column_mut(0).assign(&Array1::from(log_energy));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test_kaldi {
+    use super::*;
+
+    /// Matrices of a Kaldi text archive, as written by `copy-feats ark:- ark,t:-`, by key.
+    fn read_ark(path: &str) -> Vec<(String, Array2<f64>)> {
+        let text = std::fs::read_to_string(path).expect("cannot read the archive");
+        text.split(']')
+            .filter(|m| !m.trim().is_empty())
+            .map(|m| {
+                let (key, values) = m.split_once('[').expect("cannot parse the archive");
+                let rows: Vec<Vec<f64>> = values
+                    .lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(|l| l.split_whitespace().map(|v| v.parse().unwrap()).collect())
+                    .collect();
+                let shape = (rows.len(), rows[0].len());
+                let values = Array2::from_shape_vec(shape, rows.concat()).unwrap();
+                (key.trim().to_string(), values)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kaldi_reference() {
+        // 0.1 s of mono.wav from 1 s on, as integer samples like Kaldi reads them.
+        // See test_files/kaldi_reference.py for how the features were computed.
+        let x = crate::decode_symphonia::load(
+            std::path::Path::new("../../test_files/mono.wav"),
+            true,
+            1.,
+            None,
+            None,
+            false,
+            &crate::decode_symphonia::Norm::None,
+            Some("wav"),
+        );
+        let x = x.slice(s![0, ..4410]);
+        let sr = 44100;
+
+        for (key, expected) in read_ark("../../test_files/kaldi_mono.ark") {
+            let out = match key.as_str() {
+                "fbank" => fbank(&x, sr, &KaldiOptions::default()),
+                "mfcc" => mfcc(
+                    &x,
+                    sr,
+                    13,
+                    22.,
+                    &KaldiOptions {
+                        use_energy: true,
+                        ..KaldiOptions::default()
+                    },
+                ),
+                "fbank_energy_nosnip" => fbank(
+                    &x,
+                    sr,
+                    &KaldiOptions {
+                        use_energy: true,
+                        snip_edges: false,
+                        ..KaldiOptions::default()
+                    },
+                ),
+                _ => panic!("unexpected matrix {} in the archive", key),
+            };
+            assert_eq!(out.dim(), expected.dim(), "{}", key);
+            // Kaldi computes in single precision.
+            for (a, b) in out.iter().zip(expected.iter()) {
+                assert!(
+                    (a - b).abs() < 1e-4 * f64::max(1., b.abs()),
+                    "{}: {} != {}",
+                    key,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_kaldi_framing_and_energy() {
+        let sr = 16000;
+        // One second of a 1 kHz sine at 16-bit scale.
+        let x = Array1::from_shape_fn(16000, |i| {
+            10000. * (2. * PI * 1000. * i as f64 / sr as f64).sin()
+        });
+
+        let opts = KaldiOptions::default();
+        let fb = fbank(&x.view(), sr, &opts);
+        assert_eq!(fb.dim(), (98, 23));
+        let snipped = KaldiOptions {
+            snip_edges: false,
+            ..KaldiOptions::default()
+        };
+        assert_eq!(fbank(&x.view(), sr, &snipped).nrows(), 100);
+
+        // The sine falls in the bank whose peak is closest to 1 kHz on Kaldi's mel scale.
+        let delta = (mel(8000.) - mel(LOW_FREQ)) / 24.;
+        let expected = ((mel(1000.) - mel(LOW_FREQ)) / delta).round() as usize - 1;
+        let peak = fb
+            .row(50)
+            .iter()
+            .enumerate()
+            .fold((0, f64::NEG_INFINITY), |acc, (m, v)| {
+                if *v > acc.1 {
+                    (m, *v)
+                } else {
+                    acc
+                }
+            });
+        assert_eq!(peak.0, expected);
+
+        // The raw energy of a frame is that of the sine over 400 samples.
+        let opts = KaldiOptions {
+            use_energy: true,
+            ..KaldiOptions::default()
+        };
+        let mfcc = mfcc(&x.view(), sr, 13, 22., &opts);
+        assert_eq!(mfcc.dim(), (98, 13));
+        let energy = f64::ln(1e8 / 2. * 400.);
+        assert!((mfcc[[50, 0]] - energy).abs() < 1e-3, "{}", mfcc[[50, 0]]);
+        assert_eq!(fbank(&x.view(), sr, &opts).column(0), mfcc.column(0));
+    }
+}
@@ -1,9 +1,25 @@
 use extendr_api::prelude::*;
 use std::path::Path;
 
+mod constantq;
+mod convert;
 mod decode_symphonia;
+mod effects;
+mod encode_flac;
+mod error;
+mod features;
+mod filters;
+mod kaldi;
+mod loudness;
 mod misc;
+mod pitch;
 mod play_audio;
+mod resample;
+mod segment;
+mod sequence;
+mod stream;
+mod synth;
+mod util;
 
 use play_audio::*;
 
@@ -15,95 +31,3981 @@ use ndarray::ArrayView2;
 pub fn test_in_R() {
     let fname = "./test_files/mono.wav";
     let path = Path::new(fname);
-    let filetype = Path::extension(path)
-        .expect("couldn't extract the file extension")
-        .to_str()
-        .expect("cannot convert from &OsStr to &str");
-    let decoded_arr = decode_symphonia::load(path, false, 0., NA_REAL, filetype);
+    let filetype = filetype_of(path, None);
+    let decoded_arr = decode_symphonia::load(
+        path,
+        false,
+        0.,
+        NA_REAL,
+        None,
+        false,
+        &decode_symphonia::Norm::FullScale,
+        filetype,
+    );
     let sr = decode_symphonia::get_samplerate(path, filetype);
     rprintln!("{:?}", decoded_arr);
     rprintln!("{:?}", sr);
-    play_audio::play(&decoded_arr.view(), sr);
+    warn_on_timeout(play_audio::play(&decoded_arr.view(), sr, None));
 }
 
+/// Load an audio file as a matrix of doubles.
+/// @param fname \[String\] The path to the input file.
+/// @param mono \[Logical\] Whether to average the channels.
+/// @param offset \[Double\] Start reading the file after the offset, in seconds. A negative offset counts from the end of the file, so `-2` loads the last two seconds.
+/// @param duration \[Double\] Duration to be loaded, in seconds, counting from the offset. `NA` loads the file till the end. If the file ends sooner, the samples up to its end are returned with a warning.
+/// @param track \[Integer\] 0-based index of the track to decode, as listed by `list_tracks`. Defaults to the default track.
+/// @param mmap \[Logical\] Whether to memory-map the file instead of reading it through buffered I/O.
+/// @param offset_samples \[Double\] Start as a whole number of samples per channel. Takes precedence over `offset`.
+/// @param duration_samples \[Double\] Length as a whole number of samples per channel. Takes precedence over `duration`.
+/// @param clamp_offset \[Logical\] Whether an offset at or past the end of the file returns a matrix with no rows instead of an error.
+/// @param on_error \[String\] What to do when a packet fails to decode, `"error"`, `"skip"` or `"truncate"`, see `read()`.
+/// @param with_sr \[Logical\] If `TRUE`, return a list with the audio as `y`, the sampling rate `sr`, and the `n_samples` and `duration` decoded.
+/// @param norm \[String\] How samples are scaled, `"fullscale"`, `"none"` or `"peak"`, see `read()`.
+/// @param filetype \[String\] Format of the file, as a file extension such as `"wav"` or `"flac"`. It is only a hint: `NA` takes the extension of `fname`, and files without one are recognized from their contents.
+/// @param return_peak \[Logical\] If `TRUE`, return a list with the audio as `y` and `peak`, the largest absolute sample of any channel, tracked while decoding. It is at full scale, where 1 is 0 dBFS, before `mono` and `norm`, so it flags clipping even when these hide it.
+/// @return a matrix of doubles (nsamples, channels), or a list with `with_sr = TRUE` or `return_peak = TRUE`.
+/// @section Errors:
+/// Failures to read the file are signalled as conditions of class `"audiotest_error"` and one of
+/// `"audiotest_io_error"` (the file cannot be opened, or its handle is closed), `"audiotest_format_error"`
+/// (no container or codec is registered for it, or it has no audio track), `"audiotest_decode_error"` (the
+/// header lacks the sample rate, channels or length, or a packet cannot be read or decoded) or
+/// `"audiotest_offset_error"` (the track, offset or duration lies outside of the file), so they can be handled
+/// separately with `tryCatch()`. The other functions reading files signal the same conditions.
+/// @examples
+/// fname <- tempfile()
+/// write_flac(fname, matrix(0, 800, 2), 8000L)
+/// load(fname, FALSE, 0, NA)
+/// tryCatch(load(tempfile()), audiotest_io_error = function(e) NULL)
 /// @export
 #[extendr]
+#[allow(clippy::too_many_arguments)]
 pub fn load(
     fname: &str,
     mono: bool,            // #[default = "TRUE"]
     offset: f64,           //#[default = "0."]
     duration: Option<f64>, // #[default = "NA_real_"]
+    #[default = "NA_integer_"] track: Option<i32>,
+    #[default = "FALSE"] mmap: bool,
+    #[default = "NA_real_"] offset_samples: Option<f64>,
+    #[default = "NA_real_"] duration_samples: Option<f64>,
+    #[default = "FALSE"] clamp_offset: bool,
+    #[default = "\"error\""] on_error: &str,
+    #[default = "FALSE"] with_sr: bool,
+    #[default = "\"fullscale\""] norm: &str,
+    #[default = "NA_character_"] filetype: Option<&str>,
+    #[default = "FALSE"] return_peak: bool,
 ) -> Robj {
-    let path = Path::new(fname);
-    let filetype = Path::extension(path)
-        .expect("couldn't extract the file extension")
-        .to_str()
-        .expect("cannot convert from &OsStr to &str");
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, filetype);
+
+        let track = track.map(|idx| usize::try_from(idx).expect("track must be non-negative"));
+        let mut reader = decode_symphonia::AudioReader::open(path, track, mmap, filetype);
+
+        let options = ReadOptions {
+            offset,
+            duration,
+            offset_samples,
+            duration_samples,
+            mono,
+            clamp_offset,
+            on_error: decode_symphonia::OnError::from_name(on_error),
+            norm: decode_symphonia::Norm::from_name(norm),
+        };
+        let (y, stats) = read_matrix(&mut reader, &options);
+        let sr = reader.sr;
+        if !with_sr && !return_peak {
+            return y;
+        }
+
+        let mut fields: Vec<(&str, Robj)> = vec![("y", y)];
+        if with_sr {
+            let n_decoded = stats.n_decoded as f64;
+            fields.push((
+                "sr",
+                i32::try_from(sr)
+                    .expect("cannot convert u32 to i32.")
+                    .into(),
+            ));
+            fields.push(("n_samples", n_decoded.into()));
+            fields.push(("duration", (n_decoded / sr as f64).into()));
+        }
+        if return_peak {
+            fields.push(("peak", stats.peak.into()));
+        }
+        List::from_pairs(fields).into_robj()
+    })
+}
+
+/// Decode a segment of a file into an existing matrix, without allocating a new one.
+/// The samples overwrite the first rows of `buffer` in place, so reusing one buffer across calls, e.g. for sliding windows, avoids an allocation per read. Rows past the segment are left untouched.
+/// As R does not copy `buffer`, every other variable referring to the same matrix sees the new samples too.
+/// @param fname \[String\] The path to the input file.
+/// @param buffer \[Matrix\] Double matrix (nrows, channels) with one column per channel of the file and at least as many rows as the segment has samples.
+/// @param offset \[Double\] Start of the segment in seconds, counted from the end of the file if negative.
+/// @param duration \[Double\] Length of the segment in seconds. `NA` reads to the end of the file. If the file ends sooner, the samples up to its end are returned with a warning.
+/// @param norm \[String\] How samples are scaled, `"fullscale"`, `"none"` or `"peak"`, see `read()`.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return the number of samples per channel written to `buffer`.
+/// @examples
+/// fname <- tempfile(fileext = ".flac")
+/// write_flac(fname, matrix(0.5, 8000, 1), 8000L)
+/// buffer <- matrix(0, 4000, 1)
+/// load_into(fname, buffer, 0.25, 0.5)
+/// @export
+#[extendr]
+pub fn load_into(
+    fname: &str,
+    buffer: Robj,
+    #[default = "0"] offset: f64,
+    #[default = "NA_real_"] duration: Option<f64>,
+    #[default = "\"fullscale\""] norm: &str,
+    #[default = "NA_character_"] filetype: Option<&str>,
+) -> Robj {
+    error::raise(|| {
+        let norm = decode_symphonia::Norm::from_name(norm);
+        if !buffer.is_matrix() || buffer.rtype() != Rtype::Doubles {
+            panic!("buffer must be a double matrix");
+        }
+        let (n_rows, n_cols) = (buffer.nrows(), buffer.ncols());
+
+        let path = Path::new(fname);
+        let mut reader =
+            decode_symphonia::AudioReader::open(path, None, false, filetype_of(path, filetype));
+        if n_cols != reader.channels {
+            panic!(
+                "buffer has {} columns but the file has {} channels",
+                n_cols, reader.channels
+            );
+        }
+        let (offset_samples, n_samples) = reader.segment(offset, duration);
+        warn_if_short(
+            duration.map(|d| (d * reader.sr as f64) as u64),
+            n_samples,
+            reader.sr,
+        );
+        if n_samples > n_rows {
+            panic!(
+                "buffer has {} rows but the segment has {} samples",
+                n_rows, n_samples
+            );
+        }
+
+        let mut buffer = buffer;
+        let data = buffer
+            .as_real_slice_mut()
+            .expect("cannot get the matrix data");
+        let mut arr =
+            ArrayViewMut2::from_shape((n_cols, n_rows), data).expect("cannot view the matrix data");
+        let mut segment = arr.slice_mut(s![.., ..n_samples]);
+        let stats = reader.read_into(
+            offset_samples,
+            &mut segment,
+            &decode_symphonia::OnError::Error,
+        );
+        norm.apply(&mut segment, stats.full_scale);
+
+        i32::try_from(n_samples).expect("cannot convert usize to i32.")
+    })
+}
+
+/// Format hint of a file: `filetype` if given, otherwise the extension of `path`, if any.
+/// Without either, symphonia probes the file contents alone.
+fn filetype_of<'a>(path: &'a Path, filetype: Option<&'a str>) -> Option<&'a str> {
+    filetype.or_else(|| Path::extension(path).and_then(|ext| ext.to_str()))
+}
+
+/// Warn when the file ends before the `requested` number of samples per channel, so a caller
+/// expecting segments of a fixed length learns that this one holds only `n_samples`.
+fn warn_if_short(requested: Option<u64>, n_samples: usize, sr: u32) {
+    if let Some(requested) = requested {
+        if requested > n_samples as u64 {
+            let msg = format!(
+                "the file ends {} s after the offset: returning {} of the {} samples requested",
+                n_samples as f64 / sr as f64,
+                n_samples,
+                requested
+            );
+            call!("warning", msg).expect("cannot signal the warning");
+        }
+    }
+}
+
+/// Convert a sample count passed from R as a double, which holds integers beyond the range
+/// of R's 32-bit integers exactly.
+fn to_samples(value: f64, name: &str) -> u64 {
+    if value < 0. || value.fract() != 0. {
+        panic!("{} must be a non-negative whole number", name);
+    }
+    value as u64
+}
+
+/// A segment of a file and how to decode it, as passed to `load` and `read`. The segment is
+/// given in seconds, unless `offset_samples` or `duration_samples` are set, which take
+/// precedence over `offset` and `duration` respectively.
+struct ReadOptions {
+    offset: f64,
+    duration: Option<f64>,
+    offset_samples: Option<f64>,
+    duration_samples: Option<f64>,
+    mono: bool,
+    clamp_offset: bool,
+    on_error: decode_symphonia::OnError,
+    norm: decode_symphonia::Norm,
+}
+
+/// Decode a segment into a (nsamples, channels) R matrix. An offset past the end of the file
+/// is an error, or gives a matrix without rows if `clamp_offset` is set. With `on_error = "skip"`
+/// the number of dropped packets is attached as the `skipped_packets` attribute.
+/// Also returns the statistics of the read, whose number of frames actually decoded is smaller
+/// than the number of rows if the file ends early.
+fn read_matrix(
+    reader: &mut decode_symphonia::AudioReader,
+    options: &ReadOptions,
+) -> (Robj, decode_symphonia::ReadStats) {
+    let start = options.offset_samples.map_or_else(
+        || reader.offset_frames(options.offset),
+        |n| to_samples(n, "offset_samples"),
+    );
+    if start >= reader.n_frames {
+        if !options.clamp_offset {
+            error::AudioError::Offset(String::from(
+                "offset bigger than or equal to total duration",
+            ))
+            .throw()
+        }
+        let channels = if options.mono { 1 } else { reader.channels };
+        let empty = Array2::<f64>::zeros((0, channels));
+        let stats = decode_symphonia::ReadStats {
+            n_decoded: 0,
+            skipped_packets: 0,
+            full_scale: 1.,
+            peak: 0.,
+        };
+        return (
+            Robj::try_from(&empty).expect("cannot convert ndarray to Robj"),
+            stats,
+        );
+    }
+
+    let sr = reader.sr as f64;
+    let requested = options
+        .duration_samples
+        .map(|n| to_samples(n, "duration_samples"))
+        .or_else(|| options.duration.map(|d| (d * sr) as u64));
+    let (offset_samples, n_samples) =
+        if options.offset_samples.is_none() && options.duration_samples.is_none() {
+            reader.segment(options.offset, options.duration)
+        } else {
+            reader.segment_samples(start, requested)
+        };
+    warn_if_short(requested, n_samples, reader.sr);
+
+    let (robj, stats) = if options.mono {
+        let mut decoded_arr = Array2::<f64>::zeros((reader.channels, n_samples));
+        let stats = reader.read_into(
+            offset_samples,
+            &mut decoded_arr.view_mut(),
+            &options.on_error,
+        );
+        let n_kept = match options.on_error {
+            decode_symphonia::OnError::Truncate => stats.n_decoded,
+            _ => n_samples,
+        };
+        let mut mono_arr = misc::to_mono_ndarray(&decoded_arr.slice(s![.., ..n_kept]));
+        options
+            .norm
+            .apply(&mut mono_arr.view_mut(), stats.full_scale);
+        (
+            Robj::try_from(&mono_arr.t()).expect("cannot convert ndarray to Robj"),
+            stats,
+        )
+    } else {
+        // Decode straight into the memory of the R matrix, so the samples are not held twice.
+        let mut robj = Robj::alloc_matrix(
+            extendr_api::rtype_to_sxp(Rtype::Doubles) as u32,
+            i32::try_from(n_samples).expect("too many samples for an R matrix"),
+            i32::try_from(reader.channels).expect("cannot convert usize to i32."),
+        );
+        let data = robj
+            .as_real_slice_mut()
+            .expect("cannot get the matrix data");
+        let mut arr = ArrayViewMut2::from_shape((reader.channels, n_samples), data)
+            .expect("cannot view the matrix data");
+        let stats = reader.read_into(offset_samples, &mut arr, &options.on_error);
+        options.norm.apply(&mut arr, stats.full_scale);
+
+        // A truncated read keeps only what was decoded, which takes a copy.
+        if matches!(options.on_error, decode_symphonia::OnError::Truncate)
+            && stats.n_decoded < n_samples
+        {
+            let decoded = arr.slice(s![.., ..stats.n_decoded]);
+            (
+                Robj::try_from(&decoded.t()).expect("cannot convert ndarray to Robj"),
+                stats,
+            )
+        } else {
+            (robj, stats)
+        }
+    };
+
+    if matches!(options.on_error, decode_symphonia::OnError::Skip) {
+        let skipped = i32::try_from(stats.skipped_packets).expect("cannot convert usize to i32.");
+        robj.set_attrib(sym!(skipped_packets), skipped)
+            .expect("cannot set attribute");
+    }
+
+    (robj, stats)
+}
+
+#[extendr]
+pub struct ArrayBaseR(pub ArrayBase<OwnedRepr<f64>, Dim<[usize; 2]>>);
+
+#[extendr]
+impl ArrayBaseR {
+    pub fn print(&self) {
+        rprintln!("imma arraaaayy argh \n {:?}", self.0);
+    }
+}
+
+/// @export
+#[extendr]
+pub fn load2(
+    fname: &str,
+    mono: bool,            // #[default = "TRUE"]
+    offset: f64,           //#[default = "0."]
+    duration: Option<f64>, // #[default = "NA_real_"]
+) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, None);
+
+        let decoded_arr = decode_symphonia::load(
+            path,
+            mono,
+            offset,
+            duration,
+            None,
+            false,
+            &decode_symphonia::Norm::FullScale,
+            filetype,
+        );
+
+        ArrayBaseR(decoded_arr)
+    })
+}
+
+/// Get the sampling rate of a file, reading only the container header.
+/// Files are decoded with symphonia, so this is the rate `load` returns the audio at.
+/// @param fname \[String\] The path to the input file.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return an integer, the sampling rate in Hz of the default track.
+/// @export
+#[extendr]
+pub fn get_samplerate(fname: &str, #[default = "NA_character_"] filetype: Option<&str>) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, filetype);
+
+        let sr = decode_symphonia::get_samplerate(path, filetype);
+
+        i32::try_from(sr).expect("cannot convert u32 to i32.")
+    })
+}
+
+/// Get the bit depth of a file, reading only the container header for uncompressed and lossless formats.
+/// With integer samples, `load(norm = "fullscale")` divides by `2^(bit_depth - 1)`.
+/// @param fname \[String\] The path to the input file.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return an integer, the bits per sample of the default track, or `NA` for lossy codecs decoding to floats, such as MP3.
+/// @export
+#[extendr]
+pub fn get_bit_depth(fname: &str, #[default = "NA_character_"] filetype: Option<&str>) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, filetype);
+
+        let mut reader = decode_symphonia::AudioReader::open(path, None, false, filetype);
+
+        reader
+            .bit_depth()
+            .map(|bits| i32::try_from(bits).expect("cannot convert u32 to i32."))
+    })
+}
+
+/// Get the sample format of a file.
+/// @param fname \[String\] The path to the input file.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return a string: `"f32"` or `"f64"` for float samples, otherwise `"s"` or `"u"` for signed or unsigned integers followed by their bits, e.g. `"s16"` or `"s24"`, or `"alaw"` and `"mulaw"`. Lossy codecs such as MP3 decode to `"f32"`. `NA` for a compressed file without any audio to decode.
+/// @export
+#[extendr]
+pub fn get_sample_format(fname: &str, #[default = "NA_character_"] filetype: Option<&str>) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, filetype);
+
+        let mut reader = decode_symphonia::AudioReader::open(path, None, false, filetype);
+
+        reader.sample_format().0
+    })
+}
+
+/// Write audio to a FLAC file, losslessly compressed.
+/// Samples are quantized from \[-1, 1\] to `bit_depth` bits, and samples outside of that range are clipped.
+/// @param fname \[String\] The path to the output file.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data, with at most 8 channels.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param bit_depth \[Integer\] Bits per sample, one of 8, 12, 16, 20 or 24.
+/// @param compression \[Integer\] From 0, the fastest, to 8, the smallest files. Every level decodes at the same speed.
+/// @export
+#[extendr]
+pub fn write_flac(
+    fname: &str,
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "16L"] bit_depth: i32,
+    #[default = "5L"] compression: i32,
+) {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    let bit_depth = u32::try_from(bit_depth).expect("bit_depth must be non-negative");
+    let compression = u32::try_from(compression).expect("compression must be non-negative");
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    encode_flac::write_flac(
+        Path::new(fname),
+        &arr.t(),
+        sr as u32,
+        bit_depth,
+        compression,
+    );
+}
+
+/// List the codecs and containers `load` can decode in this build.
+/// Codecs beyond the defaults are opt-in at install time, e.g. with the environment variable `AUDIOTEST_FEATURES="mp3 aac alac isomp4"` or `AUDIOTEST_FEATURES=all-codecs`.
+/// @return a list with `codecs` and `formats`, character vectors of short names.
+/// @export
+#[extendr]
+pub fn supported_formats() -> Robj {
+    list!(
+        codecs = decode_symphonia::supported_codecs(),
+        formats = decode_symphonia::supported_formats()
+    )
+    .into_robj()
+}
+
+/// List the file extensions of the containers `load` can read in this build, as declared by its container readers, e.g. to keep only loadable files of a directory.
+/// The extension is only a hint to the probe: a file with another extension, or none, is still recognized from its contents.
+/// @return a sorted character vector of lower-case extensions without the leading dot, e.g. `"flac"` and `"wav"`.
+/// @examples
+/// files <- list.files(tempdir(), full.names = TRUE)
+/// files[tolower(tools::file_ext(files)) %in% supported_extensions()]
+/// @export
+#[extendr]
+pub fn supported_extensions() -> Vec<&'static str> {
+    decode_symphonia::supported_extensions()
+}
+
+/// A file kept open by `open_audio`, so that `read` can extract segments without re-probing it.
+#[extendr]
+pub struct AudioHandle(Option<decode_symphonia::AudioReader>);
+
+impl AudioHandle {
+    /// The reader of the file, unless the handle has been closed.
+    fn reader(&mut self) -> &mut decode_symphonia::AudioReader {
+        self.0
+            .as_mut()
+            .unwrap_or_else(|| error::AudioError::Io("the handle is closed".to_string()).throw())
+    }
+}
+
+#[extendr]
+impl AudioHandle {
+    pub fn print(&self) {
+        match &self.0 {
+            Some(reader) => {
+                rprintln!(
+                    "AudioHandle: {} channels, {} Hz, {} frames",
+                    reader.channels,
+                    reader.sr,
+                    reader.n_frames
+                );
+            }
+            None => {
+                rprintln!("AudioHandle: closed");
+            }
+        }
+    }
+}
+
+/// Open a media file for repeated reads.
+/// @param fname \[String\] The path to the input file.
+/// @param track \[Integer\] 0-based index of the track to decode, as listed by `list_tracks`. Defaults to the default track.
+/// @param mmap \[Logical\] Whether to memory-map the file instead of reading it through buffered I/O. This speeds up many random-access reads of large files. Falls back to regular reads if the file cannot be mapped.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return an `AudioHandle` to pass to `read` and `close_audio`.
+/// The names leave `base::open()` and `base::close()`, the generics for connections, unmasked.
+/// @export
+#[extendr]
+pub fn open_audio(
+    fname: &str,
+    #[default = "NA_integer_"] track: Option<i32>,
+    #[default = "FALSE"] mmap: bool,
+    #[default = "NA_character_"] filetype: Option<&str>,
+) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, filetype);
+
+        let track = track.map(|idx| usize::try_from(idx).expect("track must be non-negative"));
+
+        AudioHandle(Some(decode_symphonia::AudioReader::open(
+            path, track, mmap, filetype,
+        )))
+    })
+}
+
+/// Decode a segment of a file opened with `open_audio`.
+/// @param handle \[AudioHandle\] A handle returned by `open_audio`.
+/// @param offset \[Double\] Start of the segment in seconds, counted from the end of the file if negative.
+/// @param duration \[Double\] Length of the segment in seconds. `NA` reads to the end of the file. If the file ends sooner, the samples up to its end are returned with a warning.
+/// @param mono \[Logical\] Whether to average the channels.
+/// @param offset_samples \[Double\] Start of the segment as a whole number of samples per channel. Takes precedence over `offset`, avoiding its rounding to a sample boundary.
+/// @param duration_samples \[Double\] Length of the segment as a whole number of samples per channel. Takes precedence over `duration`.
+/// @param clamp_offset \[Logical\] Whether an offset at or past the end of the file returns a matrix with no rows instead of an error.
+/// @param on_error \[String\] What to do when a packet fails to decode: `"error"` stops with an error, `"skip"` drops the packet and continues, recording the number of dropped packets in the `skipped_packets` attribute of the result, and `"truncate"` returns what was decoded so far.
+/// @param norm \[String\] How samples are scaled: `"fullscale"` divides integer PCM by its full scale, 2^(bits - 1), so samples lie in \[-1, 1\], `"none"` keeps the integer values of integer PCM for bit-exact comparisons, and `"peak"` divides by the largest absolute sample of the segment. Float formats are kept as decoded, except for `"peak"`.
+/// @return a matrix of doubles (nsamples, channels).
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn read(
+    handle: &mut AudioHandle,
+    #[default = "0"] offset: f64,
+    #[default = "NA_real_"] duration: Option<f64>,
+    #[default = "TRUE"] mono: bool,
+    #[default = "NA_real_"] offset_samples: Option<f64>,
+    #[default = "NA_real_"] duration_samples: Option<f64>,
+    #[default = "FALSE"] clamp_offset: bool,
+    #[default = "\"error\""] on_error: &str,
+    #[default = "\"fullscale\""] norm: &str,
+) -> Robj {
+    error::raise(|| {
+        let reader = handle.reader();
+        let options = ReadOptions {
+            offset,
+            duration,
+            offset_samples,
+            duration_samples,
+            mono,
+            clamp_offset,
+            on_error: decode_symphonia::OnError::from_name(on_error),
+            norm: decode_symphonia::Norm::from_name(norm),
+        };
+
+        read_matrix(reader, &options).0
+    })
+}
+
+/// Decode a fixed-length crop of a file opened with `open_audio`, e.g. to sample many random training crops from long files.
+/// The reader seeks straight to the crop, so the cost of each crop does not grow with its position in the file.
+/// @param handle \[AudioHandle\] A handle returned by `open_audio`.
+/// @param start_sec \[Double\] Start of the crop in seconds, rounded to the nearest sample.
+/// @param length_sec \[Double\] Length of the crop in seconds, rounded to the nearest sample.
+/// @param mono \[Logical\] Whether to average the channels.
+/// @param clamp \[Logical\] What to do with a crop running past the end of the file: `FALSE` is an error, and `TRUE` moves the crop back so it ends at the end of the file. A file shorter than `length_sec` is then returned whole.
+/// @return a matrix of doubles (nsamples, channels).
+/// @examples
+/// fname <- tempfile(fileext = ".flac")
+/// write_flac(fname, matrix(sin(1:80000 / 10), ncol = 1), 8000L)
+/// handle <- open_audio(fname)
+/// crops <- lapply(runif(100, 0, 9), function(start) read_segment(handle, start, 1))
+/// close_audio(handle)
+/// @export
+#[extendr]
+pub fn read_segment(
+    handle: &mut AudioHandle,
+    start_sec: f64,
+    length_sec: f64,
+    #[default = "TRUE"] mono: bool,
+    #[default = "FALSE"] clamp: bool,
+) -> Robj {
+    error::raise(|| {
+        if start_sec.is_nan() || start_sec < 0. {
+            panic!("start_sec must be non-negative");
+        }
+        if length_sec.is_nan() || length_sec <= 0. {
+            panic!("length_sec must be positive");
+        }
+        let reader = handle.reader();
+        let (start, length) = reader.crop(start_sec, length_sec, clamp);
+
+        let options = ReadOptions {
+            offset: 0.,
+            duration: None,
+            offset_samples: Some(start as f64),
+            duration_samples: Some(length as f64),
+            mono,
+            clamp_offset: false,
+            on_error: decode_symphonia::OnError::Error,
+            norm: decode_symphonia::Norm::FullScale,
+        };
+
+        read_matrix(reader, &options).0
+    })
+}
+
+/// Close a handle returned by `open_audio`, releasing the file. Reading from a closed handle is an error.
+/// @param handle \[AudioHandle\] A handle returned by `open_audio`.
+/// @export
+#[extendr]
+pub fn close_audio(handle: &mut AudioHandle) {
+    handle.0 = None;
+}
+
+/// Get the durations of many files in parallel, reading only the container headers.
+/// @param fnames \[Character\] The paths to the input files.
+/// @param filetype \[String\] Format of all the files, as a file extension. `NA` takes the extension of each file, if any.
+/// @param num_threads \[Integer\] Number of threads to process the files on. `NA` uses the global rayon pool, by default one thread per core; set it to limit the threads of each of many R processes sharing a machine.
+/// @return a numeric vector with the duration of each file in seconds. Files that cannot be probed, or whose header does not state the number of frames, are `NA`.
+/// @export
+#[extendr]
+pub fn get_durations(
+    fnames: Vec<String>,
+    #[default = "NA_character_"] filetype: Option<&str>,
+    #[default = "NA_integer_"] num_threads: Option<i32>,
+) -> Vec<Option<f64>> {
+    util::par_map_catch(&fnames, thread_count(num_threads), |fname| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, filetype);
+
+        decode_symphonia::get_duration(path, filetype)
+    })
+}
+
+/// Whether a file's audio is stored losslessly, e.g. to exclude lossy sources from a dataset.
+/// Only the container is probed, so the result is available even for codecs that cannot be decoded.
+/// @param fname \[String\] The path to the input file.
+/// @return `TRUE` for linear PCM, as in most WAV files, and lossless codecs (FLAC, ALAC, WavPack, Monkey's Audio, TTA) in the default track, `FALSE` for lossy ones such as MP3, AAC, Vorbis, Opus, A-law/mu-law and ADPCM.
+/// @export
+#[extendr]
+pub fn is_lossless(fname: &str) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, None);
+
+        decode_symphonia::is_lossless(path, filetype)
+    })
+}
+
+/// List the tracks of a media file.
+/// @param fname \[String\] The path to the input file.
+/// @return a data.frame with one row per track and the columns `track` (the 0-based index accepted by `load`), `codec`, `channels` and `sample_rate`. Fields the container does not provide are `NA`.
+/// @export
+#[extendr]
+pub fn list_tracks(fname: &str) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, None);
+
+        let tracks = decode_symphonia::list_tracks(path, filetype);
+
+        call!(
+            "data.frame",
+            track = (0..tracks.len() as i32).collect::<Vec<i32>>(),
+            codec = tracks
+                .iter()
+                .map(|t| t.codec)
+                .collect::<Vec<Option<&str>>>(),
+            channels = tracks
+                .iter()
+                .map(|t| t.channels.map(|c| c as i32))
+                .collect::<Vec<Option<i32>>>(),
+            sample_rate = tracks
+                .iter()
+                .map(|t| t.sample_rate.map(|sr| sr as i32))
+                .collect::<Vec<Option<i32>>>()
+        )
+        .expect("cannot create data.frame")
+    })
+}
+
+/// Read all the header information of a file in one probe, e.g. to build a media library, instead of calling `get_samplerate()`, `get_duration()` and the others, which each open the file again.
+/// Nothing is decoded, so `bit_depth` is only known when the header states it or the codec is PCM.
+/// @param fname \[String\] The path to the input file.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return a list with the `sr`, `channels`, `duration` in seconds, `codec` and `bit_depth` of the default track, each `NA` if the header does not state it, and `metadata`, a named list of the text tags of the file, such as `track_title` and `artist`.
+/// @examples
+/// fname <- tempfile(fileext = ".flac")
+/// write_flac(fname, matrix(0, 8000, 2), 8000L)
+/// str(probe(fname))
+/// @export
+#[extendr]
+pub fn probe(fname: &str, #[default = "NA_character_"] filetype: Option<&str>) -> Robj {
+    error::raise(|| {
+        let path = Path::new(fname);
+        let info = decode_symphonia::probe_file(path, filetype_of(path, filetype));
+
+        let (keys, values): (Vec<String>, Vec<String>) = info.tags.into_iter().unzip();
+        let metadata =
+            List::from_names_and_values(keys, values).expect("cannot create the metadata list");
+
+        list!(
+            sr = info.track.sample_rate.map(|sr| sr as i32),
+            channels = info.track.channels.map(|c| c as i32),
+            duration = info.track.duration(),
+            codec = info.track.codec,
+            bit_depth = info.bit_depth.map(|b| b as i32),
+            metadata = metadata
+        )
+        .into_robj()
+    })
+}
+
+/// Survey the audio files under a directory, e.g. to check that a dataset has a single sampling rate and channel layout before processing it.
+/// Files are probed in parallel, reading only their container headers, like `get_samplerate()`.
+/// @param dir \[String\] The directory to search, including its subdirectories.
+/// @param pattern \[String\] Optional regular expression the file names must match, as in `list.files()`, e.g. `"\\\\.wav$"`. `NA` probes every file.
+/// @param num_threads \[Integer\] Number of threads to process the files on, see `get_durations()`.
+/// @return a data.frame with one row per file and the columns `file`, `sr`, `channels`, `duration` in seconds and `codec`, describing the default track. Fields the header does not state are `NA`.
+/// Files that cannot be probed, such as files that are not audio, are left out with a warning.
+/// @export
+#[extendr]
+pub fn scan_directory(
+    dir: &str,
+    #[default = "NA_character_"] pattern: Option<&str>,
+    #[default = "NA_integer_"] num_threads: Option<i32>,
+) -> Robj {
+    let num_threads = thread_count(num_threads);
+    if !Path::new(dir).is_dir() {
+        panic!("{} is not a directory", dir);
+    }
+    let pattern = pattern.map_or(().into(), Robj::from);
+    // Positional, as `full.names` cannot be named here: path, pattern, all.files, full.names, recursive.
+    let files =
+        call!("list.files", dir, pattern, false, true, true).expect("cannot list the files of dir");
+    let files: Vec<String> = files
+        .as_str_vector()
+        .expect("cannot list the files of dir")
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+
+    let infos: Vec<Option<decode_symphonia::TrackInfo>> =
+        util::par_map_catch(&files, num_threads, |fname| {
+            let path = Path::new(fname);
+            decode_symphonia::default_track_info(path, filetype_of(path, None)).ok()
+        })
+        .into_iter()
+        .map(Option::flatten)
+        .collect();
+
+    let skipped: Vec<&str> = files
+        .iter()
+        .zip(infos.iter())
+        .filter(|(_, info)| info.is_none())
+        .map(|(f, _)| f.as_str())
+        .collect();
+    if !skipped.is_empty() {
+        call!(
+            "warning",
+            format!(
+                "skipped {} files that could not be probed: {}",
+                skipped.len(),
+                skipped.join(", ")
+            )
+        )
+        .expect("cannot raise a warning");
+    }
+
+    let (files, infos): (Vec<&String>, Vec<decode_symphonia::TrackInfo>) = files
+        .iter()
+        .zip(infos)
+        .filter_map(|(f, info)| info.map(|info| (f, info)))
+        .unzip();
+    call!(
+        "data.frame",
+        file = files.iter().map(|f| f.as_str()).collect::<Vec<&str>>(),
+        sr = infos
+            .iter()
+            .map(|i| i.sample_rate.map(|sr| sr as i32))
+            .collect::<Vec<Option<i32>>>(),
+        channels = infos
+            .iter()
+            .map(|i| i.channels.map(|c| c as i32))
+            .collect::<Vec<Option<i32>>>(),
+        duration = infos
+            .iter()
+            .map(|i| i.duration())
+            .collect::<Vec<Option<f64>>>(),
+        codec = infos.iter().map(|i| i.codec).collect::<Vec<Option<&str>>>(),
+        stringsAsFactors = false
+    )
+    .expect("cannot create data.frame")
+}
+
+/// The playback behind `play()`, defined in R/play.R, which returns its value invisibly.
+/// @noRd
+#[extendr]
+pub fn play_impl(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "FALSE"] dry_run: bool,
+    #[default = "NA_integer_"] buffer_frames: Option<i32>,
+) -> Robj {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    if dry_run {
+        let device_channels = play_audio::default_output_channels().unwrap_or(2);
+        let (frames, channels) = play_audio::dry_run(&arr.t(), device_channels);
+        return vec![frames as i32, channels as i32].into_robj();
+    }
+    warn_on_timeout(play_audio::play(
+        &arr.t(),
+        sr as u32,
+        to_buffer_frames(buffer_frames),
+    ));
+    ().into()
+}
+
+/// Raise an R warning when playback was cut off. Called on the main thread, where R can be called back.
+fn warn_on_timeout(played: std::result::Result<(), play_audio::Timeout>) {
+    if let Err(timeout) = played {
+        call!("warning", timeout.to_string()).expect("cannot signal the warning");
+    }
+}
+
+fn to_buffer_frames(buffer_frames: Option<i32>) -> Option<u32> {
+    buffer_frames.map(|n| {
+        if n <= 0 {
+            panic!("buffer_frames must be positive");
+        }
+        n as u32
+    })
+}
+
+/// Play audio on the default output device and wait until it has finished.
+/// Returns once every sample has drained from the device, rather than after a fixed delay.
+/// Stops with a warning a second after the audio should have ended, e.g. when the output stream stalls.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param buffer_frames \[Integer\] Frames per device buffer. Smaller buffers lower the latency at the cost of more callbacks. Clamped to the range the device supports; `NA`, or a device that does not report its range, uses the device default.
+/// @export
+#[extendr]
+pub fn play_blocking(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "NA_integer_"] buffer_frames: Option<i32>,
+) {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    warn_on_timeout(play_audio::play_blocking(
+        &arr.t(),
+        sr as u32,
+        to_buffer_frames(buffer_frames),
+    ))
+}
+
+/// @export
+#[extendr]
+pub fn play2(abar: &ArrayBaseR, sr: i32) {
+    let x = abar.0.clone();
+    warn_on_timeout(play_audio::play(&x.view(), sr as u32, None))
+}
+
+/// Resample audio to a new sampling rate.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param orig_sr \[Integer\] Sampling rate of `r_arr`.
+/// @param target_sr \[Integer\] Sampling rate to resample to.
+/// @param res_type \[String\] Resampling method, from slowest and most accurate to fastest: `"kaiser_best"` is a long Kaiser-windowed sinc filter keeping 95% of the band and suppressing aliasing the most, `"kaiser_fast"` a filter a quarter as long keeping 85% of the band, and `"linear"` plain linear interpolation with no anti-aliasing filter.
+/// @return a matrix of doubles (ceiling(nsamples * target_sr / orig_sr), channels).
+/// @export
+#[extendr]
+pub fn resample(
+    r_arr: RMatrix<f64>,
+    orig_sr: i32,
+    target_sr: i32,
+    #[default = "\"kaiser_best\""] res_type: &str,
+) -> Robj {
+    if orig_sr <= 0 {
+        panic!("orig_sr must be positive");
+    }
+    if target_sr <= 0 {
+        panic!("target_sr must be positive");
+    }
+    let res_type = resample::ResType::from_name(res_type);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = resample::resample(&arr.t(), orig_sr as u32, target_sr as u32, &res_type);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Resample audio to the sampling rate of another clip, e.g. before mixing or comparing the two.
+/// The same as `resample(r_arr, orig_sr, ref_sr)`, but naming the reference clip rather than the target rate avoids resampling to the rate of the wrong clip.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param orig_sr \[Integer\] Sampling rate of `r_arr`.
+/// @param ref_arr \[Matrix\] Matrix of doubles (nsamples, channels) of the reference clip. Only its layout is checked; the samples are not used.
+/// @param ref_sr \[Integer\] Sampling rate of `ref_arr`, which `r_arr` is resampled to.
+/// @param res_type \[String\] Resampling method, see `resample()`.
+/// @return a matrix of doubles (ceiling(nsamples * ref_sr / orig_sr), channels).
+/// @examples
+/// a <- matrix(sin(1:8000 / 10), ncol = 1)
+/// b <- matrix(0, 22050, 1)
+/// a2 <- resample_to(a, 8000L, b, 22050L)
+/// @export
+#[extendr]
+pub fn resample_to(
+    r_arr: RMatrix<f64>,
+    orig_sr: i32,
+    ref_arr: Robj,
+    ref_sr: i32,
+    #[default = "\"kaiser_best\""] res_type: &str,
+) -> Robj {
+    if !ref_arr.is_matrix() || !(ref_arr.is_real() || ref_arr.is_integer()) {
+        panic!("ref_arr must be a numeric matrix (nsamples, channels)");
+    }
+    resample(r_arr, orig_sr, ref_sr, res_type)
+}
+
+/// Set the dB floor of `convert` from the `audiotest.amin` option, for the functions taking logs.
+/// Read on the main thread, as R cannot be called from the rayon pool.
+fn sync_db_floor() {
+    let amin = call!("getOption", "audiotest.amin", convert::DEFAULT_AMIN)
+        .ok()
+        .and_then(|v| v.as_real())
+        .unwrap_or_else(|| panic!("option audiotest.amin must be a positive number"));
+    convert::set_amin(amin);
+}
+
+/// The floors applied before taking logs, so silence maps to a finite level instead of `-Inf`.
+/// Every function working in dB or on log-mel features (`vad()`, `specshow_data()`, `onset_strength()`, `onset_detect()` and `mfcc_batch()`) floors amplitudes at `amin` and powers at `amin^2`, the same level in dB, so features stay consistent when these are chained.
+/// The floor is the package option `audiotest.amin`, `1e-5` (-100 dB) by default; e.g. `options(audiotest.amin = 1e-8)` keeps quieter detail at -160 dB.
+/// Kaldi features (`kaldi_fbank()`, `kaldi_mfcc()`) keep Kaldi's own floor, the single-precision machine epsilon, to match Kaldi.
+/// @return a list with the amplitude floor `amin`, the power floor `pmin` and the floor in dB `db`.
+/// @examples
+/// db_floor()
+/// old <- options(audiotest.amin = 1e-8)
+/// db_floor()
+/// options(old)
+/// @export
+#[extendr]
+pub fn db_floor() -> Robj {
+    sync_db_floor();
+    list!(
+        amin = convert::amin(),
+        pmin = convert::pmin(),
+        db = convert::amplitude_to_db(0., 1.)
+    )
+    .into_robj()
+}
+
+/// Slice audio into overlapping frames centered on multiples of `hop_length`, the framing all the frame-based features share.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param frame_length \[Integer\] Number of samples per frame.
+/// @param hop_length \[Integer\] Number of samples between successive frames.
+/// @param pad_mode \[String\] Edge padding by `frame_length / 2` on both sides, see `stft()`.
+/// @return a 3D (t, frame_length, channels) array of doubles.
+/// @examples
+/// x <- matrix(1:10, ncol = 1)
+/// frame(x, 4L, 2L, pad_mode = "constant")[1, , 1] # 0 0 1 2
+/// @export
+#[extendr]
+pub fn frame(
+    r_arr: RMatrix<f64>,
+    #[default = "2048L"] frame_length: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+) -> Robj {
+    if frame_length <= 0 {
+        panic!("frame_length must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    let pad_mode = features::PadMode::from_name(pad_mode);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let channels: Vec<Array2<f64>> = arr
+        .t()
+        .outer_iter()
+        .map(|channel| {
+            features::frame(
+                &channel,
+                frame_length as usize,
+                hop_length as usize,
+                &pad_mode,
+            )
+        })
+        .collect();
+    let views: Vec<ArrayView2<f64>> = channels.iter().map(|c| c.view()).collect();
+    let out = ndarray::stack(Axis(2), &views).expect("cannot stack channels");
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Root-mean-square energy of each frame, per channel.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param frame_length \[Integer\] Number of samples per frame.
+/// @param hop_length \[Integer\] Number of samples between successive frames. Frames are centered on multiples of `hop_length`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`. Zero padding lowers the energy of the edge frames.
+/// @return a matrix of doubles (t, channels).
+/// @examples
+/// x <- matrix(sin(1:22050 / 10), ncol = 1)
+/// range(rms(x)) # about 1 / sqrt(2)
+/// @export
+#[extendr]
+pub fn rms(
+    r_arr: RMatrix<f64>,
+    #[default = "2048L"] frame_length: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+) -> Robj {
+    if frame_length <= 0 {
+        panic!("frame_length must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    let pad_mode = features::PadMode::from_name(pad_mode);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let energy = features::rms(
+        &arr.t(),
+        frame_length as usize,
+        hop_length as usize,
+        &pad_mode,
+    );
+    Robj::try_from(&energy.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Energy-based voice activity detection.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data. Channels are averaged before analysis.
+/// @param sr \[Integer\] Audio sampling rate.
+/// @param frame_length \[Integer\] Number of samples per analysis frame.
+/// @param hop_length \[Integer\] Number of samples between successive frames.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param energy_threshold_db \[Double\] Frames louder than this level, in dBFS, are marked as voiced.
+/// @param hangover \[Integer\] Number of frames that stay voiced after the energy drops below the threshold.
+/// @return a list with `voiced`, a logical vector with one entry per frame, `times`, the frame centers in seconds, and `energy_db`, the frame energy in dBFS, with `pad_mode = "constant"` corrected for the zero padding of the edge frames.
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn vad(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "2048L"] frame_length: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "-40"] energy_threshold_db: f64,
+    #[default = "5L"] hangover: i32,
+) -> Robj {
+    sync_db_floor();
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if frame_length <= 0 {
+        panic!("frame_length must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    if hangover < 0 {
+        panic!("hangover must be non-negative");
+    }
+
+    let pad_mode = features::PadMode::from_name(pad_mode);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let (voiced, energy_db) = features::vad(
+        &arr.t(),
+        frame_length as usize,
+        hop_length as usize,
+        &pad_mode,
+        energy_threshold_db,
+        hangover as usize,
+    );
+    let frames: Vec<usize> = (0..voiced.len()).collect();
+    let times = convert::frames_to_time(&frames, sr as u32, hop_length as usize);
+
+    list!(voiced = voiced, times = times, energy_db = energy_db).into_robj()
+}
+
+/// Apply a time-varying gain, e.g. for volume automation or ducking.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param times \[Double\] Sorted times, in seconds, of the envelope's control points. They must lie within the clip.
+/// @param gains \[Double\] Linear gain at each control point. Samples before the first or after the last point keep that point's gain.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param interp \[String\] Either `"linear"` to ramp between control points or `"step"` to hold each gain until the next point.
+/// @return a matrix of doubles (nsamples, channels), every channel multiplied by the envelope.
+/// @examples
+/// x <- matrix(1, 8, 2)
+/// apply_gain_envelope(x, c(0.25, 0.75), c(1, 0), 8L)
+/// @export
+#[extendr]
+pub fn apply_gain_envelope(
+    r_arr: RMatrix<f64>,
+    times: Vec<f64>,
+    gains: Vec<f64>,
+    sr: i32,
+    #[default = "\"linear\""] interp: &str,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    let interp = effects::Interp::from_name(interp);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let envelope = effects::gain_envelope(&times, &gains, sr as u32, arr.nrows(), &interp);
+    let out = effects::apply_gain(&arr.t(), &envelope);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Convert stereo audio from left/right to mid/side channels.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, 2) holding the left and right channels.
+/// @return a matrix of doubles (nsamples, 2) holding mid (L + R) / 2 and side (L - R) / 2.
+/// @export
+#[extendr]
+pub fn to_midside(r_arr: RMatrix<f64>) -> Robj {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::to_midside(&arr.t());
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Convert stereo audio from mid/side back to left/right channels, the inverse of `to_midside()`.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, 2) holding the mid and side channels.
+/// @return a matrix of doubles (nsamples, 2) holding left M + S and right M - S.
+/// @export
+#[extendr]
+pub fn from_midside(r_arr: RMatrix<f64>) -> Robj {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::from_midside(&arr.t());
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Widen or narrow the stereo image by scaling the side channel.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, 2) holding the left and right channels.
+/// @param width \[Double\] Factor for the side channel: 0 gives mono, 1 leaves the audio unchanged and values above 1 widen the image. Wider images can exceed \[-1, 1\].
+/// @return a matrix of doubles (nsamples, 2).
+/// @export
+#[extendr]
+pub fn adjust_width(r_arr: RMatrix<f64>, width: f64) -> Robj {
+    if width.is_nan() || width < 0. {
+        panic!("width must be non-negative");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::adjust_width(&arr.t(), width);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Pan a mono source to stereo with the equal-power pan law, which keeps its loudness constant as it moves: the left and right gains are `cos` and `sin` of `(position + 1) * pi / 4`.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, 1) holding the mono source.
+/// @param position \[Double\] Position from -1 (left) through 0 (center, both channels at -3 dB) to 1 (right), either one value or one per sample for a moving source.
+/// @return a matrix of doubles (nsamples, 2).
+/// @examples
+/// x <- matrix(sin(1:22050 / 10), ncol = 1)
+/// sweep <- pan(x, seq(-1, 1, length.out = nrow(x)))
+/// @export
+#[extendr]
+pub fn pan(r_arr: RMatrix<f64>, position: Vec<f64>) -> Robj {
+    if r_arr.ncols() != 1 {
+        panic!("r_arr must be mono, got {} channels", r_arr.ncols());
+    }
+    if position.len() != 1 && position.len() != r_arr.nrows() {
+        panic!("position must have length 1 or nrow(r_arr)");
+    }
+    if position.iter().any(|p| !(-1. ..=1.).contains(p)) {
+        panic!("position must be within [-1, 1]");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::pan(&arr.column(0), &position);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Reorder, drop or duplicate channels.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param mapping \[Integer\] 1-based source channel of each output channel, e.g. `c(2L, 1L)` swaps left and right and `c(1L, 1L)` turns mono into stereo.
+/// @return a matrix of doubles (nsamples, length(mapping)).
+/// @examples
+/// x <- matrix(c(1, 2, 3, 4), 2, 2)
+/// remap_channels(x, c(2L, 1L, 1L))
+/// @export
+#[extendr]
+pub fn remap_channels(r_arr: RMatrix<f64>, mapping: Vec<i32>) -> Robj {
+    let channels = r_arr.ncols();
+    if mapping.is_empty() {
+        panic!("mapping must not be empty");
+    }
+    let mapping: Vec<usize> = mapping
+        .iter()
+        .map(|c| match usize::try_from(*c) {
+            Ok(c) if (1..=channels).contains(&c) => c - 1,
+            _ => panic!(
+                "mapping must hold channel indices between 1 and {}",
+                channels
+            ),
+        })
+        .collect();
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::remap_channels(&arr.t(), &mapping);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Join two clips, fading from the end of the first into the start of the second.
+/// @param a \[Matrix\] Matrix of doubles (nsamples, channels), the first clip.
+/// @param b \[Matrix\] Matrix of doubles (nsamples, channels), the second clip, with the same number of channels and sampling rate as `a`.
+/// @param sr \[Integer\] Sampling rate of both clips.
+/// @param fade_duration \[Double\] Length in seconds of the overlap, at most the length of either clip. The fade uses equal-power curves.
+/// @return a matrix of doubles (nrow(a) + nrow(b) - fade samples, channels).
+/// @export
+#[extendr]
+pub fn crossfade(a: RMatrix<f64>, b: RMatrix<f64>, sr: i32, fade_duration: f64) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if fade_duration.is_nan() || fade_duration < 0. {
+        panic!("fade_duration must be non-negative");
+    }
+    let n_fade = (fade_duration * sr as f64).round() as usize;
+
+    let robj_a = RMatrix::into_robj(a);
+    let a: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj_a).expect("cannot convert Robj to ArrayView2");
+    let robj_b = RMatrix::into_robj(b);
+    let b: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj_b).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::crossfade(&a.t(), &b.t(), n_fade);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Smoothed amplitude envelope, e.g. to follow the dynamics of a recording or to drive a gate.
+/// The rectified signal goes through a one-pole filter with separate time constants for rising and falling levels.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param attack \[Double\] Time constant in seconds with which the envelope follows a rising level. `0` follows it instantly.
+/// @param release \[Double\] Time constant in seconds with which the envelope follows a falling level.
+/// @return a matrix of doubles (nsamples, channels), the envelope of each channel.
+/// @export
+#[extendr]
+pub fn amplitude_envelope(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "0.01"] attack: f64,
+    #[default = "0.1"] release: f64,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if attack.is_nan() || attack < 0. {
+        panic!("attack must be non-negative");
+    }
+    if release.is_nan() || release < 0. {
+        panic!("release must be non-negative");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::amplitude_envelope(&arr.t(), sr as u32, attack, release);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Dynamic-range compressor, e.g. to level speech before analysis or playback.
+/// A feed-forward design: the peak level over all channels drives a static gain curve whose output is smoothed, and one gain is applied to every channel.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param threshold_db \[Double\] Level in dBFS above which the gain is reduced.
+/// @param ratio \[Double\] Input level increase above the threshold, in dB, per dB of output level increase. `1` leaves the audio unchanged and large values approach a limiter.
+/// @param attack \[Double\] Time constant in seconds with which the gain reduction grows.
+/// @param release \[Double\] Time constant in seconds with which the gain reduction recovers.
+/// @param makeup_db \[Double\] Gain in dB added after compression, to make up for the reduced level.
+/// @return a matrix of doubles (nsamples, channels). With makeup gain, samples can go beyond \[-1, 1\].
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn compress(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "-20"] threshold_db: f64,
+    #[default = "4"] ratio: f64,
+    #[default = "0.005"] attack: f64,
+    #[default = "0.05"] release: f64,
+    #[default = "0"] makeup_db: f64,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if threshold_db.is_nan() {
+        panic!("threshold_db must not be NA");
+    }
+    if ratio.is_nan() || ratio < 1. {
+        panic!("ratio must be at least 1");
+    }
+    if attack.is_nan() || attack < 0. {
+        panic!("attack must be non-negative");
+    }
+    if release.is_nan() || release < 0. {
+        panic!("release must be non-negative");
+    }
+    if !makeup_db.is_finite() {
+        panic!("makeup_db must be finite");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::compress(
+        &arr.t(),
+        sr as u32,
+        threshold_db,
+        ratio,
+        attack,
+        release,
+        makeup_db,
+    );
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Soft clipping: saturate audio smoothly instead of cutting it off at full scale, for warmth and harmonic enhancement, or as a gentler alternative to hard clipping.
+/// Each sample is multiplied by `drive` and passed through a curve bounded by 1, so the output stays within \[-1, 1\] however loud the input.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param drive \[Double\] Gain applied before the curve. Larger values saturate more of the signal and add more harmonics.
+/// @param kind \[String\] Curve: `"tanh"`, the smoothest, `"atan"`, which approaches full scale more slowly, or `"cubic"`, `1.5 * (x - x^3 / 3)`, which reaches full scale at `drive * x = 1` and stays there.
+/// @return a matrix of doubles (nsamples, channels) within \[-1, 1\].
+/// @examples
+/// x <- matrix(sin(1:8000 / 10), ncol = 1)
+/// y <- soft_clip(x, drive = 4)
+/// @export
+#[extendr]
+pub fn soft_clip(
+    r_arr: RMatrix<f64>,
+    #[default = "1"] drive: f64,
+    #[default = "\"tanh\""] kind: &str,
+) -> Robj {
+    if !drive.is_finite() || drive <= 0. {
+        panic!("drive must be positive");
+    }
+    let kind = effects::Saturation::from_name(kind);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = effects::soft_clip(&arr, drive, &kind);
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Convolve audio with an impulse response, e.g. to add the reverb of a room or the coloration of a speaker.
+/// Uses FFT overlap-add convolution, so long impulse responses stay fast.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param ir \[Matrix\] Matrix of doubles (ir_length, ir_channels), the impulse response at the sampling rate of `r_arr`. Each channel of `r_arr` is convolved with the channel of `ir` of the same index; a single channel in either is used for every channel of the other, so a mono signal and a stereo response give stereo.
+/// @param sr \[Integer\] Sampling rate of `r_arr` and `ir`.
+/// @return a matrix of doubles (nsamples + ir_length - 1, max(channels, ir_channels)), the wet signal only. Mix it with `r_arr` for a dry/wet balance.
+/// @export
+#[extendr]
+pub fn convolve_reverb(r_arr: RMatrix<f64>, ir: RMatrix<f64>, sr: i32) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if r_arr.nrows() == 0 || ir.nrows() == 0 {
+        panic!("r_arr and ir must not be empty");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+    let robj_ir = RMatrix::into_robj(ir);
+    let ir: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj_ir).expect("cannot convert Robj to ArrayView2");
+
+    let out = filters::convolve_channels(&arr.t(), &ir.t());
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Remove mains hum with narrow band-stop filters at `freq` and, optionally, its harmonics.
+/// Each harmonic gets its own biquad notch, applied one after the other; harmonics at or above `sr / 2` are skipped.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param freq \[Double\] Frequency of the hum in Hz, usually 50 or 60 depending on the mains.
+/// @param q \[Double\] Quality factor, `freq` over the -3 dB bandwidth of each notch. Higher values remove a narrower band, but take longer to settle.
+/// @param harmonics \[Integer\] Number of harmonics to remove, counting `freq` itself: `3L` removes `freq`, `2 * freq` and `3 * freq`.
+/// @return a matrix of doubles (nsamples, channels).
+/// @examples
+/// t <- 1:44100 / 44100
+/// hum <- matrix(sin(2 * pi * 50 * t) + 0.5 * sin(2 * pi * 150 * t), ncol = 1)
+/// cleaned <- notch(hum, 44100L, harmonics = 3L)
+/// @export
+#[extendr]
+pub fn notch(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "50"] freq: f64,
+    #[default = "30"] q: f64,
+    #[default = "1L"] harmonics: i32,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if freq.is_nan() || freq <= 0. || freq >= sr as f64 / 2. {
+        panic!("freq must be between 0 and sr / 2");
+    }
+    if q.is_nan() || q <= 0. {
+        panic!("q must be positive");
+    }
+    if harmonics <= 0 {
+        panic!("harmonics must be positive");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = filters::notch(&arr.t(), sr as u32, freq, q, harmonics as usize);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Integrated loudness following ITU-R BS.1770-4, as used for broadcast and streaming loudness targets.
+/// The audio is K-weighted and its power measured over 400 ms blocks overlapping by 75%; blocks below -70 LUFS, and then blocks more than 10 LU below the loudness of the remaining ones, are gated out.
+/// Six channels are taken as 5.1 in the order L, R, C, LFE, Ls, Rs, with the LFE ignored; otherwise all channels are weighted equally.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @return the loudness in LUFS, `-Inf` for silence or audio shorter than 400 ms.
+/// @export
+#[extendr]
+pub fn integrated_loudness(r_arr: RMatrix<f64>, sr: i32) -> f64 {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    loudness::integrated_loudness(&arr.t(), sr as u32)
+}
+
+/// Scale audio to a target integrated loudness.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param target_lufs \[Double\] Loudness to reach, e.g. -23 for EBU R 128 or -16 for podcasts.
+/// @return a matrix of doubles (nsamples, channels). Raising the loudness can push samples beyond \[-1, 1\]; no limiting is applied.
+/// @export
+#[extendr]
+pub fn normalize_loudness(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "-23"] target_lufs: f64,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let lufs = loudness::integrated_loudness(&arr.t(), sr as u32);
+    if !lufs.is_finite() {
+        panic!("cannot normalize the loudness of silence or audio shorter than 400 ms");
+    }
+    let gain = 10_f64.powf((target_lufs - lufs) / 20.);
+    let out = arr.mapv(|v| v * gain);
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Scale audio so its largest absolute sample sits `headroom_db` below full scale, e.g. before writing it to a file.
+/// A little headroom keeps the peaks between samples, which a playback converter reconstructs, from clipping. All channels share one gain, so their balance is kept.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param headroom_db \[Double\] Distance of the peak below 1 in dB, `0` to normalize the peak to exactly 1.
+/// @return a matrix of doubles (nsamples, channels). Silence is returned unchanged.
+/// @examples
+/// x <- matrix(0.2 * sin(1:22050 / 10), ncol = 1)
+/// max(abs(peak_normalize(x, 1))) # 10^(-1 / 20)
+/// @export
+#[extendr]
+pub fn peak_normalize(r_arr: RMatrix<f64>, #[default = "0.1"] headroom_db: f64) -> Robj {
+    if headroom_db.is_nan() || headroom_db < 0. {
+        panic!("headroom_db must be non-negative");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let peak = arr.fold(0., |acc: f64, v| acc.max(v.abs()));
+    let out = match peak {
+        p if p > 0. => {
+            let gain = 10_f64.powf(-headroom_db / 20.) / p;
+            arr.mapv(|v| v * gain)
+        }
+        _ => arr.to_owned(),
+    };
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Convert to 1 channel taking the average across channels.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @return a (nsamples, 1) matrix of doubles.
+/// @examples
+/// x <- array(c(1,2,3,4), c(2, 2))
+/// to_mono(x)
+/// @export
+#[extendr]
+pub fn to_mono(r_arr: RMatrix<f64>) -> Robj {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+    let mono = misc::to_mono_ndarray(&arr.t());
+
+    Robj::try_from(&mono.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Reshape audio to a long data.frame with one row per sample and channel, e.g. for plotting with ggplot2.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param max_points \[Integer\] Largest number of samples to keep per channel, taking every `ceiling(nsamples / max_points)`-th sample. `NA` keeps them all. Every kept sample is a real one, but peaks between them can be missed.
+/// @return a data.frame with the columns `time`, in seconds, `channel`, 1-based, and `amplitude`, ordered by channel and then time.
+/// @examples
+/// x <- matrix(sin(1:8000 / 10), ncol = 1)
+/// df <- to_dataframe(x, 8000L, max_points = 1000L)
+/// # ggplot2::ggplot(df, ggplot2::aes(time, amplitude)) + ggplot2::geom_line() + ggplot2::facet_wrap(~channel)
+/// @export
+#[extendr]
+pub fn to_dataframe(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "NA_integer_"] max_points: Option<i32>,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    let n_samples = r_arr.nrows();
+    let step = match max_points {
+        None => 1,
+        Some(max_points) if max_points > 0 => {
+            usize::max(n_samples.div_ceil(max_points as usize), 1)
+        }
+        Some(_) => panic!("max_points must be positive"),
+    };
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let samples: Vec<usize> = (0..n_samples).step_by(step).collect();
+    let mut time = Vec::with_capacity(samples.len() * arr.ncols());
+    let mut channel = Vec::with_capacity(time.capacity());
+    let mut amplitude = Vec::with_capacity(time.capacity());
+    for (c, column) in arr.axis_iter(Axis(1)).enumerate() {
+        for i in &samples {
+            time.push(*i as f64 / sr as f64);
+            channel.push(c as i32 + 1);
+            amplitude.push(column[*i]);
+        }
+    }
+
+    call!(
+        "data.frame",
+        time = time,
+        channel = channel,
+        amplitude = amplitude
+    )
+    .expect("cannot create data.frame")
+}
+
+/// Reduce audio to about `n_points` samples per channel for drawing a waveform.
+/// The samples are split into `n_points / 2` equal buckets and each bucket is replaced by its minimum and maximum, in the order they occur, so short transients stay visible, unlike with `r_arr[seq(1, nrow(r_arr), by = k), ]`.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param n_points \[Integer\] Number of samples to keep per channel, rounded down to an even number.
+/// @return a matrix of doubles (n_points, channels). Rows `2 * b - 1` and `2 * b` hold the extremes of bucket `b`, covering samples `(b - 1) * nsamples / (n_points / 2)` to `b * nsamples / (n_points / 2)`. Audio with at most `n_points` samples is returned unchanged.
+/// @examples
+/// x <- matrix(sin(1:44100 / 10), ncol = 1)
+/// plot(downsample_plot(x, 2000L)[, 1], type = "l")
+/// @export
+#[extendr]
+pub fn downsample_plot(r_arr: RMatrix<f64>, n_points: i32) -> Robj {
+    if n_points < 2 {
+        panic!("n_points must be at least 2");
+    }
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = util::minmax_decimate(&arr.t(), n_points as usize);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Crop or pad an audio buffer to an exact length.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param size \[Integer\] Desired length along `axis`.
+/// @param axis \[String\] Either `"samples"` or `"channels"`.
+/// @param pad_value \[Double\] Value used for padding.
+/// @param center \[Bool\] If `TRUE`, pad (or crop) evenly on both sides instead of at the end.
+/// @return a 2D (nsamples, channels) array of doubles with exactly `size` entries along `axis`.
+/// @examples
+/// x <- array(c(1,2,3,4), c(2, 2))
+/// fix_length(x, 4L)
+/// @export
+#[extendr]
+pub fn fix_length(
+    r_arr: RMatrix<f64>,
+    size: i32,
+    #[default = "\"samples\""] axis: &str,
+    #[default = "0"] pad_value: f64,
+    #[default = "FALSE"] center: bool,
+) -> Robj {
+    if size < 0 {
+        panic!("size must be non-negative");
+    }
+    let axis = match axis {
+        "channels" => Axis(0),
+        "samples" => Axis(1),
+        _ => panic!("axis must be either \"samples\" or \"channels\""),
+    };
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let fixed = util::fix_length(&arr.t(), size as usize, axis, pad_value, center);
+
+    Robj::try_from(&fixed.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Repeat an audio buffer `n` times end to end, e.g. to build a loop or a longer test signal.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param n \[Integer\] Number of repetitions.
+/// @return a matrix of doubles (n * nsamples, channels).
+/// @examples
+/// beat <- click_track(c(0, 0.25), 22050L, length = 11025L)
+/// bar <- tile(beat, 4L)
+/// @export
+#[extendr]
+pub fn tile(r_arr: RMatrix<f64>, n: i32) -> Robj {
+    let n = usize::try_from(n).expect("n must be non-negative");
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let copies = vec![arr; n];
+    let out = match n {
+        0 => Array2::<f64>::zeros((0, arr.ncols())),
+        _ => ndarray::concatenate(Axis(0), &copies).expect("cannot concatenate copies"),
+    };
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Join audio buffers end to end.
+/// @param arrs \[List\] List of matrices of doubles (nsamples, channels), all with the same number of channels.
+/// @return a matrix of doubles (total nsamples, channels).
+/// @examples
+/// a <- matrix(sin(1:100 / 10), ncol = 2)
+/// concatenate(list(a, a[50:1, ]))
+/// @export
+#[extendr]
+pub fn concatenate(arrs: List) -> Robj {
+    if arrs.is_empty() {
+        panic!("arrs must contain at least one matrix");
+    }
+
+    let values: Vec<Robj> = arrs.values().collect();
+    let views: Vec<ArrayView2<f64>> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            ArrayView2::from_robj(v).unwrap_or_else(|_| {
+                panic!(
+                    "element {} of arrs must be a (nsamples, channels) matrix of doubles",
+                    i + 1
+                )
+            })
+        })
+        .collect();
+    let channels = views[0].ncols();
+    if let Some(i) = views.iter().position(|v| v.ncols() != channels) {
+        panic!(
+            "element {} of arrs has {} channels but element 1 has {}",
+            i + 1,
+            views[i].ncols(),
+            channels
+        );
+    }
+
+    let out = ndarray::concatenate(Axis(0), &views).expect("cannot concatenate arrays");
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Play an audio buffer backwards, reversing the order of the samples of every channel.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @return a matrix of doubles (nsamples, channels).
+/// @export
+#[extendr]
+pub fn reverse(r_arr: RMatrix<f64>) -> Robj {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    Robj::try_from(&arr.slice(s![..;-1, ..])).expect("cannot convert ndarray to Robj")
+}
+
+/// Invert the polarity of an audio buffer, negating every sample. Mixing a signal with its inverted copy cancels it out.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @return a matrix of doubles (nsamples, channels).
+/// @export
+#[extendr]
+pub fn invert_polarity(r_arr: RMatrix<f64>) -> Robj {
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    Robj::try_from(&arr.mapv(|v| -v)).expect("cannot convert ndarray to Robj")
+}
+
+/// Stack time-delayed copies of a feature array to give each frame temporal context.
+/// @param data \[Array\] a 3D (t, f, channels) array of doubles.
+/// @param n_steps \[Integer\] Number of copies to stack, including the undelayed one.
+/// @param delay \[Integer\] Number of frames between successive copies. Negative values stack future frames instead of past ones.
+/// @return a 3D (t, f * n_steps, channels) array of doubles. Frames delayed past the edges of `data` are filled with zeros.
+/// @export
+#[extendr]
+pub fn stack_memory(
+    data: misc::ArrayView3Wrapper<f64>,
+    #[default = "2L"] n_steps: i32,
+    #[default = "1L"] delay: i32,
+) -> Robj {
+    if n_steps <= 0 {
+        panic!("n_steps must be positive");
+    }
+    if delay == 0 {
+        panic!("delay must be non-zero");
+    }
+
+    let stacked = features::stack_memory(&data.0, n_steps as usize, delay as isize);
+
+    Robj::try_from(&stacked).expect("cannot convert ndarray to Robj")
+}
+
+/// Aggregate a feature array between boundary frames, e.g. to get beat-synchronous features.
+/// @param data \[Array\] a 3D (t, f, channels) array of doubles.
+/// @param idx \[Integer\] 0-based boundary frame indices. The first and last frame are added when missing, and indices past the end are clipped.
+/// @param aggregate \[String\] One of `"mean"`, `"median"` or `"max"`.
+/// @return a 3D (n_segments, f, channels) array of doubles, aggregating the frames of each segment.
+/// @export
+#[extendr]
+pub fn sync(
+    data: misc::ArrayView3Wrapper<f64>,
+    idx: Vec<i32>,
+    #[default = "\"mean\""] aggregate: &str,
+) -> Robj {
+    let aggregate = match aggregate {
+        "mean" => features::Aggregate::Mean,
+        "median" => features::Aggregate::Median,
+        "max" => features::Aggregate::Max,
+        _ => panic!("aggregate must be one of \"mean\", \"median\" or \"max\""),
+    };
+    let idx: Vec<usize> = idx
+        .iter()
+        .map(|i| usize::try_from(*i).expect("idx must be non-negative"))
+        .collect();
+
+    let synced = features::sync(&data.0, &idx, aggregate);
+
+    Robj::try_from(&synced).expect("cannot convert ndarray to Robj")
+}
+
+/// Dynamic time warping between two feature sequences.
+/// @param x \[Matrix\] Matrix of doubles (t, f) holding one feature vector per row.
+/// @param y \[Matrix\] Matrix of doubles (t, f) with the same number of columns as `x`.
+/// @param metric \[String\] Frame distance, one of `"euclidean"`, `"sqeuclidean"`, `"cityblock"` or `"cosine"`.
+/// @param band \[Integer\] Optional Sakoe-Chiba radius, in frames, around the diagonal. `NA` leaves the path unconstrained.
+/// @return a list with `cost`, the accumulated cost matrix (t_x, t_y), and `path`, an integer (k, 2) matrix of 0-based frame pairs from the start to the end of both sequences.
+/// @export
+#[extendr]
+pub fn dtw(
+    x: RMatrix<f64>,
+    y: RMatrix<f64>,
+    #[default = "\"euclidean\""] metric: &str,
+    #[default = "NA_integer_"] band: Option<i32>,
+) -> Robj {
+    let robj_x = RMatrix::into_robj(x);
+    let x: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj_x).expect("cannot convert Robj to ArrayView2");
+    let robj_y = RMatrix::into_robj(y);
+    let y: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj_y).expect("cannot convert Robj to ArrayView2");
+    let band = band.map(|b| usize::try_from(b).expect("band must be non-negative"));
+
+    let (cost, path) = sequence::dtw(&x, &y, &sequence::Metric::from_name(metric), band);
+
+    list!(
+        cost = Robj::try_from(&cost).expect("cannot convert ndarray to Robj"),
+        path = Robj::try_from(&path).expect("cannot convert ndarray to Robj")
+    )
+    .into_robj()
+}
+
+/// Recurrence (self-similarity) matrix of a feature sequence.
+/// @param data \[Matrix\] Matrix of doubles (t, f) holding one feature vector per row.
+/// @param k \[Integer\] Number of nearest neighbors linked to each frame. `NA` uses `2 * ceil(sqrt(t - 1))`; use `t - 1` for a full self-similarity matrix.
+/// @param metric \[String\] Frame distance, one of `"euclidean"`, `"sqeuclidean"`, `"cityblock"` or `"cosine"`.
+/// @param mode \[String\]
+/// \itemize{
+/// \item `"connectivity"`: 1 for linked frames.
+/// \item `"distance"`: the distance between linked frames.
+/// \item `"affinity"`: `exp(-distance / bandwidth)` for linked frames, the bandwidth being the median distance to the k-th neighbor.
+/// }
+/// @return a (t, t) matrix of doubles. Entry `[i, j]` is non-zero when frame `j` is one of the `k` nearest neighbors of frame `i`.
+/// @export
+#[extendr]
+pub fn recurrence_matrix(
+    data: RMatrix<f64>,
+    #[default = "NA_integer_"] k: Option<i32>,
+    #[default = "\"euclidean\""] metric: &str,
+    #[default = "\"connectivity\""] mode: &str,
+) -> Robj {
+    let mode = match mode {
+        "connectivity" => segment::RecurrenceMode::Connectivity,
+        "distance" => segment::RecurrenceMode::Distance,
+        "affinity" => segment::RecurrenceMode::Affinity,
+        _ => panic!("mode must be one of \"connectivity\", \"distance\" or \"affinity\""),
+    };
+    let k = k.map(|k| usize::try_from(k).expect("k must be non-negative"));
+
+    let robj = RMatrix::into_robj(data);
+    let data: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let rec = segment::recurrence_matrix(&data, k, &sequence::Metric::from_name(metric), mode);
+
+    Robj::try_from(&rec).expect("cannot convert ndarray to Robj")
+}
+
+/// Split a feature sequence into contiguous segments by agglomerative clustering.
+/// @param data \[Matrix\] Matrix of doubles (t, f) holding one feature vector per row.
+/// @param k \[Integer\] Number of segments.
+/// @return an integer vector with the 0-based start frame of each of the `k` segments. The first entry is always 0.
+/// @export
+#[extendr]
+pub fn segment(data: RMatrix<f64>, k: i32) -> Vec<i32> {
+    if k <= 0 {
+        panic!("k must be positive");
+    }
+
+    let robj = RMatrix::into_robj(data);
+    let data: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    segment::agglomerative(&data, k as usize)
+        .iter()
+        .map(|b| *b as i32)
+        .collect()
+}
+
+/// Novelty curve of a feature sequence, such as a spectrogram, peaking where its content changes. Peaks are candidate structure boundaries.
+/// @param s \[Matrix\] Matrix of doubles (t, f) holding one feature vector per row.
+/// @param kernel \[String\]
+/// \itemize{
+/// \item `"spectral"`: spectral flux, the summed increase of every feature since the previous frame.
+/// \item `"checkerboard"`: correlation of a Gaussian-tapered checkerboard kernel along the diagonal of the cosine self-similarity matrix. It compares the `kernel_size / 2` frames before and after each frame, so it targets section-level changes rather than onsets.
+/// }
+/// @param kernel_size \[Integer\] Width in frames of the checkerboard kernel. Ignored for `"spectral"`.
+/// @return a vector of doubles with one value per frame.
+/// @export
+#[extendr]
+pub fn novelty(
+    s: RMatrix<f64>,
+    #[default = "\"spectral\""] kernel: &str,
+    #[default = "64L"] kernel_size: i32,
+) -> Vec<f64> {
+    let kernel = match kernel {
+        "spectral" => segment::NoveltyKernel::Spectral,
+        "checkerboard" => segment::NoveltyKernel::Checkerboard,
+        _ => panic!("kernel must be either \"spectral\" or \"checkerboard\""),
+    };
+    let kernel_size = usize::try_from(kernel_size).expect("kernel_size must be non-negative");
+
+    let robj = RMatrix::into_robj(s);
+    let s: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    segment::novelty(&s, &kernel, kernel_size).to_vec()
+}
+
+/// Onset strength envelope, the mean half-wave rectified increase of a spectrogram over the frame `lag` frames earlier. Its peaks mark note onsets and percussive hits.
+/// By default it is computed from the log-power mel spectrogram of `r_arr`, clipped 80 dB below its peak. Pass a precomputed feature array as `s` instead to use another front-end, e.g. `log1p(Mod(stft(x)))`, `chroma_stft()` or a constant-Q transform computed elsewhere.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data. Ignored when `s` is given.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param s \[Array\] Optional real (t, f, channels) array of features, preferably on a log scale. `NULL` computes a mel spectrogram from `r_arr`.
+/// @param n_fft \[Integer\] Length of the FFT window of the mel spectrogram. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param n_mels \[Integer\] Number of mel bands spanning 0 Hz to `sr / 2`.
+/// @param lag \[Integer\] Number of frames to look back when computing the increase.
+/// @return a matrix of doubles (t, channels). The first `lag` frames are zero.
+/// @examples
+/// x <- matrix(rnorm(22050) * rep(c(0, 1), each = 11025), ncol = 1)
+/// env <- onset_strength(x, 22050L)
+/// which.max(env[, 1])
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn onset_strength(
+    #[default = "NULL"] r_arr: Robj,
+    #[default = "22050L"] sr: i32,
+    #[default = "NULL"] s: Robj,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "128L"] n_mels: i32,
+    #[default = "1L"] lag: i32,
+) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let env = onset_envelope(&r_arr, sr, &s, n_fft, hop_length, &pad_mode, n_mels, lag);
+
+    Robj::try_from(&env).expect("cannot convert ndarray to Robj")
+}
+
+/// Onset strength (t, channels) of `r_arr`, or of the features `s` if given, for `onset_strength` and `onset_detect`.
+#[allow(clippy::too_many_arguments)]
+fn onset_envelope(
+    r_arr: &Robj,
+    sr: i32,
+    s: &Robj,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &features::PadMode,
+    n_mels: i32,
+    lag: i32,
+) -> Array2<f64> {
+    let lag = match lag {
+        l if l > 0 => l as usize,
+        _ => panic!("lag must be positive"),
+    };
+    sync_db_floor();
+
+    if s.is_null() {
+        if r_arr.is_null() {
+            panic!("either r_arr or s must be given");
+        }
+        let n_mels = usize::try_from(n_mels).expect("n_mels must be non-negative");
+        let arr: ArrayView2<f64> =
+            ArrayView2::from_robj(r_arr).expect("r_arr must be a numeric matrix");
+
+        let mut db = features::melspectrogram(
+            &arr.t(),
+            sr as u32,
+            n_fft,
+            hop_length,
+            pad_mode,
+            n_mels,
+            None,
+            2.,
+        )
+        .mapv(|p| convert::power_to_db(p, 1.));
+        let floor = db.fold(f64::NEG_INFINITY, |acc, x| acc.max(*x)) - 80.;
+        db.mapv_inplace(|v| v.max(floor));
+        features::onset_strength(&db.view(), lag)
+    } else {
+        features::onset_strength(&misc::require_features(s, "s"), lag)
+    }
+}
+
+/// Detect note onsets and percussive hits as the peaks of the onset strength envelope, as in librosa's `onset_detect`.
+/// The envelope of `onset_strength()`, averaged over channels, is scaled to \[0, 1\]. A frame is an onset if it is the maximum of the 30 ms before it, exceeds the mean of the 100 ms around it by `delta`, and comes over 30 ms after the previous onset.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data. Ignored when `s` is given.
+/// @param sr \[Integer\] Sampling rate of `r_arr`, also setting the peak picking windows in frames.
+/// @param s \[Array\] Optional real (t, f, channels) array of features, see `onset_strength()`.
+/// @param n_fft \[Integer\] Length of the FFT window of the mel spectrogram. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames, also of `s` if given. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param n_mels \[Integer\] Number of mel bands spanning 0 Hz to `sr / 2`.
+/// @param lag \[Integer\] Number of frames to look back when computing the increase.
+/// @param delta \[Double\] Amount by which an onset must exceed the local mean of the scaled envelope.
+/// @param return_envelope \[Logical\] If `TRUE`, return a list with the scaled `envelope` and the `onsets` picked from it, e.g. to plot the peaks over the envelope.
+/// @return an integer vector of 0-based frame indices of the onsets, see `frames_to_time()`, or a list with `return_envelope = TRUE`.
+/// @examples
+/// x <- matrix(rnorm(22050) * rep(c(0, 1), each = 11025), ncol = 1)
+/// res <- onset_detect(x, 22050L, return_envelope = TRUE)
+/// plot(res$envelope, type = "l")
+/// abline(v = res$onsets + 1)
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn onset_detect(
+    #[default = "NULL"] r_arr: Robj,
+    #[default = "22050L"] sr: i32,
+    #[default = "NULL"] s: Robj,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "128L"] n_mels: i32,
+    #[default = "1L"] lag: i32,
+    #[default = "0.07"] delta: f64,
+    #[default = "FALSE"] return_envelope: bool,
+) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let env = onset_envelope(&r_arr, sr, &s, n_fft, hop_length, &pad_mode, n_mels, lag);
+
+    let mut env = env.mean_axis(Axis(1)).expect("cannot average channels");
+    let (lo, hi) = env
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        });
+    if hi > lo {
+        env.mapv_inplace(|v| (v - lo) / (hi - lo));
+    } else {
+        env.fill(0.);
+    }
+
+    let opts = features::PeakPick::for_onsets(sr as u32, hop_length, delta);
+    let onsets: Vec<i32> = features::peak_pick(&env.view(), &opts)
+        .iter()
+        .map(|i| *i as i32)
+        .collect();
+
+    if return_envelope {
+        list!(envelope = env.to_vec(), onsets = onsets).into_robj()
+    } else {
+        onsets.into()
+    }
+}
+
+/// Sinusoidal liftering of cepstral coefficients, as in HTK and Kaldi: coefficient `n`, counted from 0, is scaled by `1 + lifter / 2 * sin(pi * n / lifter)`.
+/// This raises the small higher coefficients to a range comparable with the lower ones, which suits models that assume features of similar variance.
+/// @param mfcc \[Array\] a real (t, n_mfcc, channels) array, e.g. from `mfcc_batch()`.
+/// @param lifter \[Double\] Liftering coefficient, 22 in HTK and Kaldi. `0` leaves the coefficients unchanged.
+/// @return an array of doubles of the same dimensions as `mfcc`.
+/// @export
+#[extendr]
+pub fn liftering(mfcc: Robj, #[default = "22"] lifter: f64) -> Robj {
+    if lifter.is_nan() || lifter < 0. {
+        panic!("lifter must be non-negative");
+    }
+    let mut out = misc::require_features(&mfcc, "mfcc").to_owned();
+    let weights = features::lifter_weights(out.dim().1, lifter);
+    for mut frame in out.outer_iter_mut() {
+        for mut coefficients in frame.axis_iter_mut(Axis(1)) {
+            coefficients *= &weights;
+        }
+    }
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Spectral whitening: divide every frequency bin by its average over time, per channel, so the long-term spectrum of the result is flat.
+/// This removes static colouration like that of a microphone or room, and lets the quieter high frequencies count as much as the low ones.
+/// @param s \[Array\] a real (t, f, channels) magnitude or power spectrogram, e.g. `Mod(stft(x))`, not on a dB scale.
+/// @return an array of doubles of the same dimensions as `s`, with an average of 1 in every bin. Bins that are zero throughout stay zero.
+/// @export
+#[extendr]
+pub fn whiten(s: Robj) -> Robj {
+    let s = misc::require_features(&s, "s");
+
+    Robj::try_from(&features::whiten(&s)).expect("cannot convert ndarray to Robj")
+}
+
+/// Scale a spectrogram to \[0, 1\] for display, so images of spectrograms with different ranges share one colour scale.
+/// @param s \[Array\] a real (t, f, channels) spectrogram, typically on a dB scale, e.g. `10 * log10(Mod(stft(x))^2 + 1e-10)`.
+/// @param method \[String\] `"minmax"` maps the smallest value of `s` to 0 and the largest to 1, and `"percentile"` maps the `pmin`th percentile to 0 and the `pmax`th to 1, clipping the values outside, so a few extreme bins do not compress the rest of the range.
+/// @param pmin \[Double\] Percentile mapped to 0 with `method = "percentile"`, within \[0, 100\].
+/// @param pmax \[Double\] Percentile mapped to 1 with `method = "percentile"`, within \[0, 100\].
+/// @return an array of doubles of the same dimensions as `s`, with values in \[0, 1\]. A constant `s` gives zeros.
+/// @export
+#[extendr]
+pub fn normalize_spectrogram(
+    s: Robj,
+    #[default = "\"minmax\""] method: &str,
+    #[default = "1"] pmin: f64,
+    #[default = "99"] pmax: f64,
+) -> Robj {
+    let s = misc::require_features(&s, "s");
+    if s.iter().any(|v| v.is_nan()) {
+        panic!("s must not contain NaN");
+    }
+
+    let (lo, hi) = match method {
+        "minmax" => s
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+                (lo.min(v), hi.max(v))
+            }),
+        "percentile" => {
+            if !(0. ..=100.).contains(&pmin) || !(0. ..=100.).contains(&pmax) || pmin >= pmax {
+                panic!("pmin and pmax must be within [0, 100] with pmin < pmax");
+            }
+            let values: Vec<f64> = s.iter().cloned().collect();
+            (
+                util::percentile(values.clone(), pmin),
+                util::percentile(values, pmax),
+            )
+        }
+        _ => panic!("method must be \"minmax\" or \"percentile\""),
+    };
+
+    let range = hi - lo;
+    let out = s.mapv(|v| {
+        if range > 0. {
+            ((v - lo) / range).clamp(0., 1.)
+        } else {
+            0.
+        }
+    });
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Convert frame indices to time in seconds.
+/// @param frames \[Integer\] 0-based frame indices.
+/// @param sr \[Integer\] Sampling rate.
+/// @param hop_length \[Integer\] Number of samples between successive frames, either one value for a constant hop, or one value per pair of successive frames for a variable hop, e.g. of an adaptive STFT or beat-synchronous features. Frame `t` then starts at `sum(hop_length[seq_len(t)])`, so the vector must have at least `max(frames)` values.
+/// @return a vector of doubles, the start of each frame in seconds (its center for centered frames such as `stft()`).
+/// @examples
+/// frames_to_time(0:3, 22050L, 512L)
+/// frames_to_time(0:3, 22050L, c(512L, 1024L, 256L))
+/// @export
+#[extendr]
+pub fn frames_to_time(
+    frames: Vec<i32>,
+    #[default = "22050L"] sr: i32,
+    #[default = "512L"] hop_length: Vec<i32>,
+) -> Vec<f64> {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    let frames: Vec<usize> = frames
+        .iter()
+        .map(|f| usize::try_from(*f).expect("frames must be non-negative"))
+        .collect();
+    let hops: Vec<usize> = hop_length
+        .iter()
+        .map(|h| match h {
+            h if *h > 0 => *h as usize,
+            _ => panic!("hop_length must be positive"),
+        })
+        .collect();
+
+    match hops[..] {
+        [] => panic!("hop_length must not be empty"),
+        [hop] => convert::frames_to_time(&frames, sr as u32, hop),
+        _ => convert::frames_to_time_variable(&frames, sr as u32, &hops),
+    }
+}
+
+/// Convert sample indices to time in seconds.
+/// @param samples \[Double\] 0-based sample indices, e.g. frame positions accumulated from a variable hop.
+/// @param sr \[Integer\] Sampling rate.
+/// @return a vector of doubles, in seconds.
+/// @export
+#[extendr]
+pub fn samples_to_time(samples: Vec<f64>, #[default = "22050L"] sr: i32) -> Vec<f64> {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    samples.iter().map(|n| n / sr as f64).collect()
+}
+
+/// Convert frequencies to Traunmüller's Bark scale.
+/// @param hz \[Double\] Frequencies in Hz.
+/// @return a vector of doubles, in Bark.
+/// @export
+#[extendr]
+pub fn hz_to_bark(hz: Vec<f64>) -> Vec<f64> {
+    hz.into_iter().map(convert::hz_to_bark).collect()
+}
+
+/// Convert frequencies on the Bark scale to Hz, the inverse of `hz_to_bark()`.
+/// @param bark \[Double\] Frequencies in Bark, below 26.28.
+/// @return a vector of doubles, in Hz.
+/// @export
+#[extendr]
+pub fn bark_to_hz(bark: Vec<f64>) -> Vec<f64> {
+    bark.into_iter().map(convert::bark_to_hz).collect()
+}
+
+/// Convert frequencies to Glasberg and Moore's ERB-rate scale, the number of equivalent rectangular bandwidths below each frequency.
+/// @param hz \[Double\] Frequencies in Hz.
+/// @return a vector of doubles, in ERBs.
+/// @export
+#[extendr]
+pub fn hz_to_erb(hz: Vec<f64>) -> Vec<f64> {
+    hz.into_iter().map(convert::hz_to_erb).collect()
+}
+
+/// Convert frequencies on the ERB-rate scale to Hz, the inverse of `hz_to_erb()`.
+/// @param erb \[Double\] Frequencies in ERBs.
+/// @return a vector of doubles, in Hz.
+/// @export
+#[extendr]
+pub fn erb_to_hz(erb: Vec<f64>) -> Vec<f64> {
+    erb.into_iter().map(convert::erb_to_hz).collect()
+}
+
+/// Band edges or centers evenly spaced on the Bark scale, e.g. to build a Bark filterbank for `melspectrogram_batch()`.
+/// @param n_bands \[Integer\] Number of frequencies.
+/// @param fmin \[Double\] Lowest frequency in Hz.
+/// @param fmax \[Double\] Highest frequency in Hz.
+/// @return a vector of `n_bands` doubles in Hz, from `fmin` to `fmax` included.
+/// @export
+#[extendr]
+pub fn bark_frequencies(
+    n_bands: i32,
+    #[default = "0"] fmin: f64,
+    #[default = "11025"] fmax: f64,
+) -> Vec<f64> {
+    let n_bands = usize::try_from(n_bands).expect("n_bands must be non-negative");
+    convert::bark_frequencies(n_bands, fmin, fmax)
+}
+
+/// Band edges or centers evenly spaced on the ERB-rate scale, e.g. to build a gammatone-like filterbank for `melspectrogram_batch()`.
+/// @param n_bands \[Integer\] Number of frequencies.
+/// @param fmin \[Double\] Lowest frequency in Hz.
+/// @param fmax \[Double\] Highest frequency in Hz.
+/// @return a vector of `n_bands` doubles in Hz, from `fmin` to `fmax` included.
+/// @export
+#[extendr]
+pub fn erb_frequencies(
+    n_bands: i32,
+    #[default = "0"] fmin: f64,
+    #[default = "11025"] fmax: f64,
+) -> Vec<f64> {
+    let n_bands = usize::try_from(n_bands).expect("n_bands must be non-negative");
+    convert::erb_frequencies(n_bands, fmin, fmax)
+}
+
+/// Default STFT frame parameters for a sampling rate, keeping the time resolution of `n_fft = 2048` and `hop_length = 512` at 22050 Hz.
+/// `n_fft` is `2048 * sr / 22050` rounded to the nearest power of two, so the window stays around 93 ms (between 66 and 131 ms), and `hop_length` is `n_fft / 4`. For example 44100 and 48000 Hz give 4096 and 1024.
+/// Functions taking `sr` use these when `n_fft` or `hop_length` are `NA`; an explicit `n_fft` with `hop_length = NA` gets `hop_length = n_fft / 4`.
+/// @param sr \[Integer\] Sampling rate.
+/// @return a list with the integers `n_fft` and `hop_length`.
+/// @export
+#[extendr]
+pub fn default_frame_params(sr: i32) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, None, None);
+
+    list!(n_fft = n_fft as i32, hop_length = hop_length as i32).into_robj()
+}
+
+/// `n_fft` and `hop_length` passed from R, `NA` taken from `features::default_frame_params`.
+fn frame_params(sr: i32, n_fft: Option<i32>, hop_length: Option<i32>) -> (usize, usize) {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    let n_fft = match n_fft {
+        Some(n) if n <= 0 => panic!("n_fft must be positive"),
+        Some(n) => Some(n as usize),
+        None => None,
+    };
+    let hop_length = match hop_length {
+        Some(h) if h <= 0 => panic!("hop_length must be positive"),
+        Some(h) => Some(h as usize),
+        None => None,
+    };
+    let (default_n_fft, _) = features::default_frame_params(sr as u32);
+    let n_fft = n_fft.unwrap_or(default_n_fft);
+
+    (n_fft, hop_length.unwrap_or(usize::max(n_fft / 4, 1)))
+}
+
+fn check_power(power: f64) {
+    if power.is_nan() || power <= 0. {
+        panic!("power must be positive");
+    }
+}
+
+/// Short-time Fourier transform with a Hann window, keeping the `n_fft / 2 + 1` non-negative frequency bins.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param n_fft \[Integer\] Length of the FFT window.
+/// @param hop_length \[Integer\] Number of samples between successive frames. Frames are centered on multiples of `hop_length`.
+/// @param pad_mode \[String\] How the signal is extended by `n_fft / 2` on both sides for the edge frames: `"constant"` with zeros, `"edge"` repeating the first and last samples, or `"reflect"` mirroring the signal around them, as in librosa.
+/// @param power \[Double\] `NA` for the complex STFT, otherwise the exponent its magnitude is raised to, e.g. `1` for magnitude and `2` for power.
+/// @param return_times \[Bool\] If `TRUE`, also return the time of each frame.
+/// @param sr \[Integer\] Sampling rate of `r_arr`, required when `return_times = TRUE` or with `fmin` or `fmax`.
+/// @param fmin \[Double\] Lowest frequency in Hz to keep. `NA` keeps the bins from 0 Hz.
+/// @param fmax \[Double\] Highest frequency in Hz to keep. `NA` keeps the bins up to `sr / 2`.
+/// @param aggregate \[String\] `NA` returns the features of every channel, `"mean"` averages them into a single channel.
+/// @return a complex (t, n_fft / 2 + 1, channels) array, or a double one when `power` is given. With `return_times = TRUE`, a list with the array as `spectrogram` and `frame_times`, the center of each frame in seconds, i.e. `(0:(t - 1)) * hop_length / sr`.
+/// The signal is padded on both sides, so frame 0 is centered on the first sample rather than starting there: use these times instead of `frame * hop_length + n_fft / 2`.
+/// With `fmin` or `fmax`, only the bins within `[fmin, fmax]` are kept and the result is a list with the array as `spectrogram` and the center frequency of each bin as `freqs`, plus `frame_times` with `return_times = TRUE`.
+/// @examples
+/// x <- matrix(sin(1:22050 / 10), ncol = 1)
+/// speech <- stft(x, sr = 22050L, fmin = 80, fmax = 8000)
+/// stopifnot(length(speech$freqs) == dim(speech$spectrogram)[2])
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn stft(
+    r_arr: RMatrix<f64>,
+    #[default = "2048L"] n_fft: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "NA_real_"] power: Option<f64>,
+    #[default = "FALSE"] return_times: bool,
+    #[default = "NA_integer_"] sr: Option<i32>,
+    #[default = "NA_real_"] fmin: Option<f64>,
+    #[default = "NA_real_"] fmax: Option<f64>,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    if let Some(power) = power {
+        check_power(power);
+    }
+    if n_fft <= 0 {
+        panic!("n_fft must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    let band = fmin.is_some() || fmax.is_some();
+    let sr = match (return_times || band, sr) {
+        (false, _) => None,
+        (true, Some(sr)) if sr > 0 => Some(sr as u32),
+        (true, Some(_)) => panic!("sr must be positive"),
+        (true, None) => panic!("sr is required when return_times = TRUE or with fmin or fmax"),
+    };
+
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let aggregate = features::Channels::from_name(aggregate);
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let bins = match sr {
+        Some(sr) if band => {
+            let fmin = fmin.unwrap_or(0.);
+            let fmax = fmax.unwrap_or(sr as f64 / 2.);
+            if fmin > fmax {
+                panic!("fmin must not be above fmax");
+            }
+            features::band_bins(sr, n_fft as usize, fmin, fmax)
+        }
+        _ => 0..n_fft as usize / 2 + 1,
+    };
+
+    let out = aggregate.apply(stft_channels(
+        &arr,
+        n_fft as usize,
+        hop_length as usize,
+        &pad_mode,
+        bins.clone(),
+    ));
+    let spectrogram = match power {
+        None => Robj::try_from(&out),
+        Some(power) => Robj::try_from(&features::spectrogram_power(&out.view(), power)),
+    }
+    .expect("cannot convert ndarray to Robj");
+
+    let sr = match sr {
+        None => return spectrogram,
+        Some(sr) => sr,
+    };
+    let mut pairs = vec![("spectrogram", spectrogram)];
+    if band {
+        let freqs = convert::fft_frequencies(sr, n_fft as usize)[bins].to_vec();
+        pairs.push(("freqs", freqs.into_robj()));
+    }
+    if return_times {
+        let frames: Vec<usize> = (0..out.shape()[0]).collect();
+        let frame_times = convert::frames_to_time(&frames, sr, hop_length as usize);
+        pairs.push(("frame_times", frame_times.into_robj()));
+    }
+    List::from_pairs(pairs).into_robj()
+}
+
+/// STFT of every channel of the (nsamples, channels) `arr`, stacked as a (t, f, channels) array of the `bins`.
+/// Only the band is kept from each channel, so the full spectrum is never stacked.
+fn stft_channels(
+    arr: &ArrayView2<f64>,
+    n_fft: usize,
+    hop_length: usize,
+    pad_mode: &features::PadMode,
+    bins: std::ops::Range<usize>,
+) -> Array3<c64> {
+    let channels: Vec<Array2<c64>> = arr
+        .t()
+        .outer_iter()
+        .map(|channel| {
+            features::stft(&channel, n_fft, hop_length, pad_mode).slice_move(s![.., bins.clone()])
+        })
+        .collect();
+    let views: Vec<ArrayView2<c64>> = channels.iter().map(|c| c.view()).collect();
+    ndarray::stack(Axis(2), &views).expect("cannot stack channels")
+}
+
+/// Short-time Fourier transform as separate magnitude and phase arrays, for working with the STFT without R's complex arrays.
+/// The STFT is the same as that of `stft()`: `magnitude * exp(1i * phase)` gives it back.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param n_fft \[Integer\] Length of the FFT window.
+/// @param hop_length \[Integer\] Number of samples between successive frames. Frames are centered on multiples of `hop_length`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param aggregate \[String\] `NA` returns the features of every channel, `"mean"` averages the complex STFTs into a single channel.
+/// @return a list with `magnitude` and `phase`, two double (t, n_fft / 2 + 1, channels) arrays. The phase is in radians, in `[-pi, pi]`.
+/// @examples
+/// x <- matrix(sin(1:22050 / 10), ncol = 1)
+/// s <- stft_mag_phase(x, 512L, 128L)
+/// stopifnot(all.equal(s$magnitude * exp(1i * s$phase), stft(x, 512L, 128L)))
+/// @export
+#[extendr]
+pub fn stft_mag_phase(
+    r_arr: RMatrix<f64>,
+    #[default = "2048L"] n_fft: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    if n_fft <= 0 {
+        panic!("n_fft must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let aggregate = features::Channels::from_name(aggregate);
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let out = aggregate.apply(stft_channels(
+        &arr,
+        n_fft as usize,
+        hop_length as usize,
+        &pad_mode,
+        0..n_fft as usize / 2 + 1,
+    ));
+
+    list!(
+        magnitude =
+            Robj::try_from(&out.mapv(|z| z.norm())).expect("cannot convert ndarray to Robj"),
+        phase = Robj::try_from(&out.mapv(|z| z.arg())).expect("cannot convert ndarray to Robj")
+    )
+    .into_robj()
+}
+
+/// Spectral entropy: the Shannon entropy of the power spectrum of each frame, taken as a probability distribution over the frequency bins.
+/// Tonal frames with a few strong partials have a low entropy and noisy frames a high one, so it complements spectral flatness as a measure of noisiness, e.g. to tell calls from background noise in field recordings.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param n_fft \[Integer\] Length of the FFT window.
+/// @param hop_length \[Integer\] Number of samples between successive frames. Frames are centered on multiples of `hop_length`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param normalize \[Bool\] If `TRUE`, divide by the entropy of a flat spectrum, `log2(n_fft / 2 + 1)`, to scale the result to \[0, 1\]. Otherwise the entropy is in bits.
+/// @param aggregate \[String\] `NA` or `"mean"`, see `stft()`.
+/// @return a 3D (t, 1, channels) array of doubles. Silent frames are 0.
+/// @examples
+/// x <- cbind(sin(1:22050 / 10), rnorm(22050))
+/// h <- spectral_entropy(x)
+/// colMeans(h[, 1, ])
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn spectral_entropy(
+    r_arr: RMatrix<f64>,
+    #[default = "2048L"] n_fft: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "TRUE"] normalize: bool,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    if n_fft <= 0 {
+        panic!("n_fft must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let aggregate = features::Channels::from_name(aggregate);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let entropy = aggregate.apply(features::spectral_entropy(
+        &arr.t(),
+        n_fft as usize,
+        hop_length as usize,
+        &pad_mode,
+        normalize,
+    ));
+    Robj::try_from(&entropy).expect("cannot convert ndarray to Robj")
+}
+
+/// Power spectral density by Welch's method: the average of the power spectra of overlapping windowed segments.
+/// Unlike a spectrogram, this is one spectrum summarising a stationary signal, e.g. to find the frequency of a hum or the level of a noise floor.
+/// Segments start every `hop_length` samples without centering or padding, as in `scipy.signal.welch`.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param n_fft \[Integer\] Length of the segments. Longer segments give a finer frequency resolution but fewer segments to average.
+/// @param hop_length \[Integer\] Number of samples between the starts of successive segments.
+/// @param window \[String\] Window applied to each segment, `"hann"`, `"hamming"` or `"rectangular"`.
+/// @return a list with `psd`, a (n_fft / 2 + 1, channels) matrix of the one-sided density in power per Hz, and `freqs`, the frequency of each row in Hz.
+/// @examples
+/// x <- matrix(sin(2 * pi * 50 * 1:22050 / 22050), ncol = 1)
+/// p <- psd(x, 22050L)
+/// p$freqs[which.max(p$psd[, 1])]
+/// @export
+#[extendr]
+pub fn psd(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "2048L"] n_fft: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"hann\""] window: &str,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if n_fft <= 0 {
+        panic!("n_fft must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+
+    let window = features::Window::from_name(window);
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let channels: Vec<Array1<f64>> = arr
+        .t()
+        .outer_iter()
+        .map(|channel| {
+            features::welch_psd(
+                &channel,
+                sr as u32,
+                n_fft as usize,
+                hop_length as usize,
+                &window,
+            )
+        })
+        .collect();
+    let views: Vec<ArrayView1<f64>> = channels.iter().map(|c| c.view()).collect();
+    let out = ndarray::stack(Axis(1), &views).expect("cannot stack channels");
+
+    list!(
+        psd = Robj::try_from(&out).expect("cannot convert ndarray to Robj"),
+        freqs = convert::fft_frequencies(sr as u32, n_fft as usize)
+    )
+    .into_robj()
+}
+
+/// Variable-Q transform, a spectrogram with `bins_per_octave` geometrically spaced bins whose filters are `sr / (f * alpha + gamma)` samples long, where `alpha = 2^(1 / bins_per_octave) - 1`.
+/// With `gamma = 0` it is the constant-Q transform: every bin spans the same musical interval, so bass notes are resolved in frequency at the cost of long, smeared filters. A positive `gamma` (Hz) widens the low bins and shortens their filters, trading some pitch resolution for time resolution in the bass.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. Frames are centered on multiples of `hop_length`, as in `stft()`.
+/// @param fmin \[Double\] Center frequency of the lowest bin in Hz. `NA` uses C1, about 32.7 Hz.
+/// @param n_bins \[Integer\] Number of bins. The highest one must stay below `sr / 2`.
+/// @param bins_per_octave \[Integer\] Number of bins per octave.
+/// @param gamma \[Double\] Bandwidth in Hz added to every bin.
+/// @param aggregate \[String\] `NA` returns the features of every channel, `"mean"` averages the complex transforms into a single channel.
+/// @return a complex array (t, n_bins, channels). A unit sinusoid at a bin frequency has a magnitude of about 0.5.
+/// @examples
+/// x <- matrix(sin(2 * pi * 440 * 1:22050 / 22050), ncol = 1)
+/// v <- vqt(x, 22050L, gamma = 10)
+/// which.max(Mod(v[20, , 1])) # A4, bin 58 from C1
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn vqt(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "NA_real_"] fmin: Option<f64>,
+    #[default = "84L"] n_bins: i32,
+    #[default = "12L"] bins_per_octave: i32,
+    #[default = "0"] gamma: f64,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    if n_bins <= 0 {
+        panic!("n_bins must be positive");
+    }
+    if bins_per_octave <= 0 {
+        panic!("bins_per_octave must be positive");
+    }
+    if gamma.is_nan() || gamma < 0. {
+        panic!("gamma must be non-negative");
+    }
+    let fmin = match fmin {
+        Some(f) if f > 0. => f,
+        Some(_) => panic!("fmin must be positive"),
+        None => constantq::C1,
+    };
+
+    let aggregate = features::Channels::from_name(aggregate);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let freqs = constantq::frequencies(fmin, n_bins as usize, bins_per_octave as usize);
+    let filters = constantq::vqt_filters(sr as u32, &freqs, bins_per_octave as usize, gamma);
+    let channels: Vec<Array2<c64>> = arr
+        .t()
+        .outer_iter()
+        .map(|channel| constantq::vqt(&channel, &filters, hop_length as usize))
+        .collect();
+    let views: Vec<ArrayView2<c64>> = channels.iter().map(|c| c.view()).collect();
+    let out = aggregate.apply(ndarray::stack(Axis(2), &views).expect("cannot stack channels"));
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Audio back from a constant- or variable-Q transform, by overlap-add of its filters weighted by a diagonal approximation of the pseudo-inverse of the filterbank.
+/// The parameters must be those `c` was computed with. The reconstruction is close to exact when `sr / hop_length` is above twice the widest bandwidth of the filters, `f * (2^(1 / bins_per_octave) - 1) + gamma` Hz for the highest bin `f`, so use a `hop_length` of 64 or 128 rather than the default to resynthesize a transform of the full range.
+/// Frequencies outside the band of the bins are lost.
+/// @param c \[Array\] a complex (t, n_bins, channels) array as returned by `vqt()`.
+/// @param sr \[Integer\] Sampling rate.
+/// @param hop_length \[Integer\] Number of samples between successive frames, as passed to `vqt()`.
+/// @param fmin \[Double\] Center frequency of the lowest bin in Hz, as passed to `vqt()`. `NA` uses C1, about 32.7 Hz.
+/// @param bins_per_octave \[Integer\] Number of bins per octave, as passed to `vqt()`.
+/// @param gamma \[Double\] Bandwidth in Hz added to every bin, as passed to `vqt()`.
+/// @param length \[Integer\] Number of samples to return. `NA` returns `hop_length * (t - 1)` samples.
+/// @return a matrix of doubles (nsamples, channels).
+/// @examples
+/// x <- matrix(sin(2 * pi * 440 * 1:22050 / 22050), ncol = 1)
+/// c <- vqt(x, 22050L, hop_length = 64L, fmin = 110, n_bins = 48L)
+/// y <- icqt(c, 22050L, hop_length = 64L, fmin = 110, length = nrow(x))
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn icqt(
+    c: Robj,
+    sr: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "NA_real_"] fmin: Option<f64>,
+    #[default = "12L"] bins_per_octave: i32,
+    #[default = "0"] gamma: f64,
+    #[default = "NA_integer_"] length: Option<i32>,
+) -> Robj {
+    let c = misc::require_complex(
+        &c,
+        "c must be a complex (t, n_bins, channels) transform as returned by vqt()",
+    );
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    if bins_per_octave <= 0 {
+        panic!("bins_per_octave must be positive");
+    }
+    if gamma.is_nan() || gamma < 0. {
+        panic!("gamma must be non-negative");
+    }
+    let fmin = match fmin {
+        Some(f) if f > 0. => f,
+        Some(_) => panic!("fmin must be positive"),
+        None => constantq::C1,
+    };
+    let (n_frames, n_bins, _) = c.dim();
+    let length = match length {
+        Some(n) => usize::try_from(n).expect("length must be non-negative"),
+        None => hop_length as usize * n_frames.saturating_sub(1),
+    };
 
-    let decoded_arr = decode_symphonia::load(path, mono, offset, duration, filetype);
+    // Bins above the Nyquist frequency mean `c` was computed with other parameters.
+    let freqs = constantq::frequencies(fmin, n_bins, bins_per_octave as usize);
+    let filters = constantq::vqt_filters(sr as u32, &freqs, bins_per_octave as usize, gamma);
+    let channels: Vec<Array1<f64>> = (0..c.dim().2)
+        .map(|ch| {
+            constantq::icqt(
+                &c.slice(s![.., .., ch]),
+                sr as u32,
+                &freqs,
+                &filters,
+                hop_length as usize,
+                length,
+            )
+        })
+        .collect();
+    let views: Vec<ArrayView1<f64>> = channels.iter().map(|c| c.view()).collect();
+    let out = ndarray::stack(Axis(1), &views).expect("cannot stack channels");
 
-    Robj::try_from(&decoded_arr.t()).expect("cannot convert ndarray to Robj") // try to return a matrix or Rarr instead of Robj
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
 }
 
+/// Fundamental frequency of monophonic audio with the YIN estimator, working in the time domain.
+/// Each frame searches for the shortest period at which the signal repeats, from the cumulative mean normalized difference of the frame with shifted copies of itself.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param fmin \[Double\] Lowest frequency in Hz to search, C2 by default. The period of `fmin` must fit in half a frame.
+/// @param fmax \[Double\] Highest frequency in Hz to search, C7 by default.
+/// @param frame_length \[Integer\] Length of the frames in samples. Frames are centered on multiples of `hop_length`.
+/// @param hop_length \[Integer\] Number of samples between successive frames.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param threshold \[Double\] Largest normalized difference accepted as a period. Lower values reject more noisy frames as unvoiced.
+/// @return a (t, channels) matrix of the estimated frequency in Hz of each frame, `NA` for unvoiced frames.
+/// @examples
+/// x <- matrix(sin(2 * pi * 220 * 1:22050 / 22050), ncol = 1)
+/// f0 <- yin(x, 22050L)
+/// @export
 #[extendr]
-pub struct ArrayBaseR(pub ArrayBase<OwnedRepr<f64>, Dim<[usize; 2]>>);
+#[allow(clippy::too_many_arguments)]
+pub fn yin(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "65"] fmin: f64,
+    #[default = "2093"] fmax: f64,
+    #[default = "2048L"] frame_length: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "0.1"] threshold: f64,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if frame_length <= 0 {
+        panic!("frame_length must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    if fmin.is_nan() || fmax.is_nan() || fmax > sr as f64 / 2. {
+        panic!("fmin and fmax must be given, with fmax at most sr / 2");
+    }
+    if threshold.is_nan() || threshold <= 0. {
+        panic!("threshold must be positive");
+    }
+
+    let pad_mode = features::PadMode::from_name(pad_mode);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
 
+    let channels: Vec<Array1<f64>> = arr
+        .t()
+        .outer_iter()
+        .map(|channel| {
+            pitch::yin(
+                &channel,
+                sr as u32,
+                fmin,
+                fmax,
+                frame_length as usize,
+                hop_length as usize,
+                &pad_mode,
+                threshold,
+            )
+            .into_iter()
+            .map(|f0| f0.unwrap_or_else(f64::na))
+            .collect()
+        })
+        .collect();
+    let views: Vec<ArrayView1<f64>> = channels.iter().map(|c| c.view()).collect();
+    let out = ndarray::stack(Axis(1), &views).expect("cannot stack channels");
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Fundamental frequency and voicing of monophonic audio with probabilistic YIN (pYIN), as in librosa.
+/// Every trough of the YIN difference function is a pitch candidate, weighted over a range of thresholds, and a hidden Markov model picks a smooth pitch track and decides which frames are voiced.
+/// Slower than `yin()`, but far less prone to octave errors and spurious pitches in unvoiced frames.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param fmin \[Double\] Lowest frequency in Hz to search, C2 by default. The period of `fmin` must fit in half a frame.
+/// @param fmax \[Double\] Highest frequency in Hz to search, C7 by default.
+/// @param frame_length \[Integer\] Length of the frames in samples. Frames are centered on multiples of `hop_length`.
+/// @param hop_length \[Integer\] Number of samples between successive frames.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param switch_prob \[Double\] Probability of switching between voiced and unvoiced from one frame to the next.
+/// @param no_trough_prob \[Double\] Weight given to the lowest trough of frames where none passes a threshold.
+/// @return a list of (t, channels) matrices: `f0`, the frequency in Hz of each frame, quantized to a tenth of a semitone and `NA` where unvoiced, `voiced_flag`, whether the frame is voiced, and `voiced_prob`, the probability that it is.
+/// @examples
+/// x <- matrix(sin(2 * pi * 220 * 1:22050 / 22050), ncol = 1)
+/// p <- pyin(x, 22050L)
+/// median(p$f0, na.rm = TRUE)
+/// @export
 #[extendr]
-impl ArrayBaseR {
-    pub fn print(&self) {
-        rprintln!("imma arraaaayy argh \n {:?}", self.0);
+#[allow(clippy::too_many_arguments)]
+pub fn pyin(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "65"] fmin: f64,
+    #[default = "2093"] fmax: f64,
+    #[default = "2048L"] frame_length: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "0.01"] switch_prob: f64,
+    #[default = "0.01"] no_trough_prob: f64,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
     }
+    if frame_length <= 0 {
+        panic!("frame_length must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    if fmin.is_nan() || fmax.is_nan() || fmax > sr as f64 / 2. {
+        panic!("fmin and fmax must be given, with fmax at most sr / 2");
+    }
+    if switch_prob.is_nan() || switch_prob <= 0. || switch_prob >= 1. {
+        panic!("switch_prob must be between 0 and 1");
+    }
+    if no_trough_prob.is_nan() || !(0. ..=1.).contains(&no_trough_prob) {
+        panic!("no_trough_prob must be between 0 and 1");
+    }
+    let params = pitch::PyinParams {
+        switch_prob,
+        no_trough_prob,
+        ..Default::default()
+    };
+
+    let pad_mode = features::PadMode::from_name(pad_mode);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let channels: Vec<(Vec<Option<f64>>, Vec<f64>)> = arr
+        .t()
+        .outer_iter()
+        .map(|channel| {
+            pitch::pyin(
+                &channel,
+                sr as u32,
+                fmin,
+                fmax,
+                frame_length as usize,
+                hop_length as usize,
+                &pad_mode,
+                &params,
+            )
+        })
+        .collect();
+    let n_frames = channels.first().map_or(0, |c| c.1.len());
+    let shape = (n_frames, channels.len());
+    let f0 = Array2::from_shape_fn(shape, |(t, c)| channels[c].0[t].unwrap_or_else(f64::na));
+    let voiced_prob = Array2::from_shape_fn(shape, |(t, c)| channels[c].1[t]);
+    let voiced_flag = RMatrix::<Rbool>::new_matrix(n_frames, channels.len(), |t, c| {
+        Rbool::from(channels[c].0[t].is_some())
+    });
+
+    list!(
+        f0 = Robj::try_from(&f0).expect("cannot convert ndarray to Robj"),
+        voiced_flag = voiced_flag,
+        voiced_prob = Robj::try_from(&voiced_prob).expect("cannot convert ndarray to Robj")
+    )
+    .into_robj()
 }
 
+/// Chromagram: the energy of each of the 12 pitch classes per frame, from a power spectrogram.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param n_fft \[Integer\] Length of the FFT window. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param filterbank \[Matrix\] Optional (n_bands, n_fft / 2 + 1) matrix of doubles replacing the chroma filters. `NULL` uses the 12 pitch class filters.
+/// @param power \[Double\] Exponent of the STFT magnitude the filters are applied to, `2` for power and `1` for magnitude.
+/// @param aggregate \[String\] `NA` or `"mean"`, see `stft()`.
+/// @return a 3D (t, 12, channels) array of doubles, pitch classes starting at C, or (t, n_bands, channels) with `filterbank`. Each frame is scaled so its strongest band is 1.
 /// @export
 #[extendr]
-pub fn load2(
-    fname: &str,
-    mono: bool,            // #[default = "TRUE"]
-    offset: f64,           //#[default = "0."]
-    duration: Option<f64>, // #[default = "NA_real_"]
-) -> ArrayBaseR {
-    let path = Path::new(fname);
-    let filetype = Path::extension(path)
-        .expect("couldn't extract the file extension")
-        .to_str()
-        .expect("cannot convert from &OsStr to &str");
+#[allow(clippy::too_many_arguments)]
+pub fn chroma_stft(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "NULL"] filterbank: Robj,
+    #[default = "2"] power: f64,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    check_power(power);
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let aggregate = features::Channels::from_name(aggregate);
+
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
 
-    let decoded_arr = decode_symphonia::load(path, mono, offset, duration, filetype);
+    let filterbank = filterbank_from_robj(&filterbank);
+    let chroma = features::chroma_stft(
+        &arr.t(),
+        sr as u32,
+        n_fft,
+        hop_length,
+        &pad_mode,
+        filterbank.as_ref().map(|f| f.view()).as_ref(),
+        power,
+    );
 
-    ArrayBaseR(decoded_arr)
+    Robj::try_from(&aggregate.apply(chroma)).expect("cannot convert ndarray to Robj")
 }
 
+/// Estimate the musical key by correlating the time-averaged chromagram with the Krumhansl-Schmuckler major and minor key profiles.
+/// Channels are averaged before computing the chromagram.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param n_fft \[Integer\] Length of the FFT window. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param return_correlations \[Bool\] If `TRUE`, also return the correlations with all 24 keys.
+/// @return a list with `key`, the tonic such as `"F#"`, `mode`, either `"major"` or `"minor"`, and `confidence`, the correlation of the best key in \[-1, 1\]. With `return_correlations`, `correlations` is a named vector of the 24 correlations, `"C major"` to `"B major"` then `"C minor"` to `"B minor"`.
 /// @export
 #[extendr]
-pub fn get_samplerate(fname: &str) -> i32 {
-    let path = Path::new(fname);
-    let filetype = Path::extension(path)
-        .expect("couldn't extract the file extension")
-        .to_str()
-        .expect("cannot convert from &OsStr to &str");
+pub fn estimate_key(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "FALSE"] return_correlations: bool,
+) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    let pad_mode = features::PadMode::from_name(pad_mode);
 
-    let sr = decode_symphonia::get_samplerate(path, filetype);
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let mono = misc::to_mono_ndarray(&arr.t());
+    let chroma = features::chroma_stft(
+        &mono.view(),
+        sr as u32,
+        n_fft,
+        hop_length,
+        &pad_mode,
+        None,
+        2.,
+    );
+    let mean = chroma
+        .mean_axis(Axis(0))
+        .expect("audio must have at least one frame");
+    let correlations = features::key_correlations(&mean.column(0).to_vec());
 
-    i32::try_from(sr).expect("cannot convert u32 to i32.")
+    let best = (0..24)
+        .max_by(|a, b| {
+            correlations[*a]
+                .partial_cmp(&correlations[*b])
+                .expect("cannot compare NaN")
+        })
+        .expect("there are 24 keys");
+    let key = features::PITCH_CLASSES[best % 12];
+    let mode = if best < 12 { "major" } else { "minor" };
+
+    if return_correlations {
+        let names: Vec<String> = ["major", "minor"]
+            .iter()
+            .flat_map(|mode| {
+                features::PITCH_CLASSES
+                    .iter()
+                    .map(move |key| format!("{} {}", key, mode))
+            })
+            .collect();
+        let named = Robj::from(correlations.clone());
+        named
+            .set_attrib(sym!(names), names)
+            .expect("cannot set names");
+        list!(
+            key = key,
+            mode = mode,
+            confidence = correlations[best],
+            correlations = named
+        )
+        .into_robj()
+    } else {
+        list!(key = key, mode = mode, confidence = correlations[best]).into_robj()
+    }
 }
 
+/// Instantaneous-frequency spectrogram: a refined frequency estimate for every STFT bin, along with the magnitude.
+/// The frequency comes from the phase advance over one sample, computed from a second STFT of the signal advanced by one sample. For a stable sinusoid the bins around its peak report its frequency well below the bin spacing, which is the basis for pitch tracking and tuning estimation.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param n_fft \[Integer\] Length of the FFT window. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param aggregate \[String\] `NA` returns the features of every channel, `"mean"` averages both arrays into a single channel.
+/// @return a list with `freqs`, the (t, n_fft / 2 + 1, channels) instantaneous frequencies in Hz, bins without energy getting their center frequency, and `mag`, the magnitude STFT of the same shape.
 /// @export
 #[extendr]
-pub fn play(r_arr: RMatrix<f64>, sr: i32) {
+pub fn if_gram(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let aggregate = features::Channels::from_name(aggregate);
+
     let robj = RMatrix::into_robj(r_arr);
     let arr: ArrayView2<f64> =
         ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
 
-    println!("{:?}", arr);
-    play_audio::play(&arr, sr as u32)
+    let (freqs, mags): (Vec<Array2<f64>>, Vec<Array2<f64>>) = arr
+        .t()
+        .outer_iter()
+        .map(|channel| features::if_gram(&channel, sr as u32, n_fft, hop_length, &pad_mode))
+        .unzip();
+    let stack = |arrays: &[Array2<f64>]| {
+        let views: Vec<ArrayView2<f64>> = arrays.iter().map(|a| a.view()).collect();
+        let out = aggregate.apply(ndarray::stack(Axis(2), &views).expect("cannot stack channels"));
+        Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+    };
+
+    list!(freqs = stack(&freqs), mag = stack(&mags)).into_robj()
+}
+
+/// A file whose STFT is computed block by block, returned by `stft_stream`.
+#[extendr]
+pub struct StftStream(stream::StftBlocks);
+
+#[extendr]
+impl StftStream {
+    /// The next complex (t, n_fft / 2 + 1, channels) block of frames, or `NULL` once the whole file has been returned.
+    pub fn next_block(&mut self) -> Robj {
+        error::raise(|| match self.0.next_block() {
+            Some(block) => Robj::try_from(&block).expect("cannot convert ndarray to Robj"),
+            None => ().into(),
+        })
+    }
+
+    /// Total number of frames over all blocks.
+    pub fn n_frames(&self) -> i32 {
+        i32::try_from(self.0.n_frames()).expect("cannot convert usize to i32.")
+    }
 }
 
+/// Short-time Fourier transform of a file, read and computed one block of frames at a time.
+/// Binding the blocks along the first dimension gives exactly `stft(x, pad_mode = "constant")` of
+/// the whole file, without holding it in memory.
+/// @param fname \[String\] The path to the input file.
+/// @param n_fft \[Integer\] Length of the FFT window.
+/// @param hop_length \[Integer\] Number of samples between successive frames.
+/// @param block_frames \[Integer\] Number of frames per block.
+/// @return a `StftStream`. Call `$next_block()` until it returns `NULL`.
+/// @examples
+/// fname <- tempfile(fileext = ".flac")
+/// write_flac(fname, matrix(sin(1:80000 / 10), ncol = 1), 8000L)
+/// blocks <- stft_stream(fname)
+/// while (!is.null(block <- blocks$next_block())) {
+///   # process block
+/// }
 /// @export
 #[extendr]
-pub fn play2(abar: &ArrayBaseR, sr: i32) {
-    let x = abar.0.clone();
-    play_audio::play(&x.view(), sr as u32)
+pub fn stft_stream(
+    fname: &str,
+    #[default = "2048L"] n_fft: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "1024L"] block_frames: i32,
+) -> Robj {
+    error::raise(|| {
+        if n_fft <= 0 {
+            panic!("n_fft must be positive");
+        }
+        if hop_length <= 0 {
+            panic!("hop_length must be positive");
+        }
+        if block_frames <= 0 {
+            panic!("block_frames must be positive");
+        }
+
+        let path = Path::new(fname);
+        let filetype = filetype_of(path, None);
+        let reader = decode_symphonia::AudioReader::open(path, None, false, filetype);
+
+        StftStream(stream::StftBlocks::new(
+            reader,
+            n_fft as usize,
+            hop_length as usize,
+            block_frames as usize,
+        ))
+    })
+}
+
+/// A segment of a file decoded on demand, returned by `pcm_stream`.
+#[extendr]
+pub struct PcmStream(stream::PcmBlocks, stream::PcmFormat);
+
+#[extendr]
+impl PcmStream {
+    /// The next `n` frames, or fewer at the end of the segment, as a raw vector of interleaved little-endian samples in the format of the stream, to be parsed with `readBin` or passed on to any consumer of raw PCM. Returns `raw(0)` once the segment has been read, like `readBin` at the end of a connection.
+    pub fn read(&mut self, n: i32) -> Robj {
+        error::raise(|| match self.0.next_frames(to_frames(n)) {
+            Some(block) => Raw::from_bytes(&self.1.encode(&block.view())),
+            None => Raw::from_bytes(&[]),
+        })
+    }
+
+    /// The next `n` frames, or fewer at the end of the segment, as a matrix of doubles (nsamples, channels) at full scale. Returns `NULL` once the segment has been read.
+    pub fn read_frames(&mut self, n: i32) -> Robj {
+        error::raise(|| match self.0.next_frames(to_frames(n)) {
+            Some(block) => Robj::try_from(&block.t()).expect("cannot convert ndarray to Robj"),
+            None => ().into(),
+        })
+    }
+
+    /// Number of frames left to read.
+    pub fn remaining(&self) -> f64 {
+        self.0.remaining() as f64
+    }
+
+    pub fn channels(&self) -> i32 {
+        self.0.channels() as i32
+    }
+
+    pub fn sr(&self) -> i32 {
+        self.0.sr() as i32
+    }
+
+    /// Bytes per frame of the raw vectors returned by `$read()`.
+    pub fn frame_bytes(&self) -> i32 {
+        (self.0.channels() * self.1.bytes_per_sample()) as i32
+    }
+}
+
+fn to_frames(n: i32) -> usize {
+    if n <= 0 {
+        panic!("n must be positive");
+    }
+    n as usize
+}
+
+/// Decode a file lazily, pulling a number of frames at a time, so a long file can be fed to streaming R code without being loaded whole.
+/// Each `$read(n)` decodes only the next `n` frames and returns them as raw PCM bytes, which `readBin` parses and which can be written to any connection, e.g. a pipe to an external encoder; `$read_frames(n)` returns them as a matrix instead.
+/// R offers no API for packages to implement connections, so the stream is pulled explicitly rather than through `readBin(con)`.
+/// @param fname \[String\] The path to the input file.
+/// @param format \[String\] Sample encoding of `$read()`: `"s16le"` or `"s32le"` for signed integers, scaled by 2^(bits - 1) and saturated, or `"f32le"` or `"f64le"` for floats at full scale.
+/// @param offset \[Double\] Start of the segment in seconds, counted from the end of the file if negative.
+/// @param duration \[Double\] Length of the segment in seconds. `NA` reads to the end of the file. If the file ends sooner, the samples up to its end are returned with a warning.
+/// @param filetype \[String\] Format of the file, as a file extension. `NA` takes the extension of `fname`, if any.
+/// @return a `PcmStream`, with methods `$read(n)`, `$read_frames(n)`, `$remaining()`, `$channels()`, `$sr()` and `$frame_bytes()`.
+/// @examples
+/// fname <- tempfile(fileext = ".flac")
+/// write_flac(fname, matrix(sin(1:8000 / 10), ncol = 1), 8000L)
+/// pcm <- pcm_stream(fname)
+/// while (length(bytes <- pcm$read(1024L)) > 0) {
+///   samples <- readBin(bytes, "integer", n = length(bytes) / 2, size = 2, endian = "little")
+/// }
+/// @export
+#[extendr]
+pub fn pcm_stream(
+    fname: &str,
+    #[default = "\"s16le\""] format: &str,
+    #[default = "0"] offset: f64,
+    #[default = "NA_real_"] duration: Option<f64>,
+    #[default = "NA_character_"] filetype: Option<&str>,
+) -> Robj {
+    error::raise(|| {
+        let format = stream::PcmFormat::from_name(format);
+        let path = Path::new(fname);
+        let reader =
+            decode_symphonia::AudioReader::open(path, None, false, filetype_of(path, filetype));
+        let (offset_samples, n_samples) = reader.segment(offset, duration);
+        warn_if_short(
+            duration.map(|d| (d * reader.sr as f64) as u64),
+            n_samples,
+            reader.sr,
+        );
+        PcmStream(
+            stream::PcmBlocks::new(reader, offset_samples, n_samples),
+            format,
+        )
+    })
+}
+
+/// Inverse short-time Fourier transform by windowed overlap-add.
+/// @param s \[Array\] a complex (t, f, channels) array as returned by `stft`, or a (t, f) matrix of one of its channels.
+/// @param hop_length \[Integer\] Number of samples between successive frames.
+/// @param length \[Integer\] Number of samples to return, cropping or zero-padding the end. `NA` returns `hop_length * (t - 1)` samples.
+/// @param n_fft \[Integer\] FFT length passed to `stft`, `2 * (f - 1)` or `2 * f - 1`. `NA` assumes an even length, `2 * (f - 1)`.
+/// @return a matrix of doubles (nsamples, channels).
+/// @export
+#[extendr]
+pub fn istft(
+    s: Robj,
+    #[default = "512L"] hop_length: i32,
+    #[default = "NA_integer_"] length: Option<i32>,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+) -> Robj {
+    let out = istft_channels(&misc::require_stft(&s), hop_length, length, n_fft);
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Audio back from a STFT, to check what an analysis keeps of a signal or to resynthesize a modified spectrogram.
+/// Like `istft()`, but also accepts the list returned by `stft(return_times = TRUE)`.
+/// `reconstruct(stft(x, n_fft, hop_length), hop_length, nrow(x))` gives back `x` up to rounding errors.
+/// @param s \[Array\] a complex (t, f, channels) array as returned by `stft`, a (t, f) matrix of one of its channels, or a list with the array as `spectrogram`.
+/// @param hop_length \[Integer\] Number of samples between successive frames, as passed to `stft`.
+/// @param length \[Integer\] Number of samples to return, cropping or zero-padding the end. `NA` returns `hop_length * (t - 1)` samples.
+/// @param n_fft \[Integer\] FFT length passed to `stft`, `2 * (f - 1)` or `2 * f - 1`. `NA` assumes an even length, `2 * (f - 1)`.
+/// @return a matrix of doubles (nsamples, channels).
+/// @examples
+/// x <- matrix(sin(1:4096 / 10), ncol = 1)
+/// y <- reconstruct(stft(x, 512L, 128L), 128L, nrow(x))
+/// stopifnot(max(abs(x - y)) < 1e-9)
+/// @export
+#[extendr]
+pub fn reconstruct(
+    s: Robj,
+    #[default = "512L"] hop_length: i32,
+    #[default = "NA_integer_"] length: Option<i32>,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+) -> Robj {
+    let s = if s.is_list() {
+        s.dollar("spectrogram")
+            .expect("s must be a STFT array or a list with a spectrogram")
+    } else {
+        s
+    };
+    let out = istft_channels(&misc::require_stft(&s), hop_length, length, n_fft);
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Inverse STFT of every channel of a complex (t, f, channels) array, as a (nsamples, channels) matrix.
+/// Without `n_fft`, the FFT length is the even `2 * (f - 1)`.
+fn istft_channels(
+    s: &ndarray::ArrayView3<c64>,
+    hop_length: i32,
+    length: Option<i32>,
+    n_fft: Option<i32>,
+) -> Array2<f64> {
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+    let length = length.map(|n| usize::try_from(n).expect("length must be non-negative"));
+    let n_fft = n_fft.map_or(2 * (s.dim().1 - 1), |n| {
+        usize::try_from(n).expect("n_fft must be positive")
+    });
+
+    let channels: Vec<Array1<f64>> = (0..s.dim().2)
+        .map(|c| features::istft(&s.slice(s![.., .., c]), n_fft, hop_length as usize, length))
+        .collect();
+    let views: Vec<ArrayView1<f64>> = channels.iter().map(|c| c.view()).collect();
+    ndarray::stack(Axis(1), &views).expect("cannot stack channels")
+}
+
+/// Real FFT of a whole signal, without framing or windowing. Uses the same cached FFT plans as `stft()`.
+/// @param x \[Double\] A numeric vector.
+/// @return a complex vector of the `length(x) %/% 2 + 1` non-negative frequency bins, unnormalised like R's `fft()`.
+/// @examples
+/// spectrum <- rfft(sin(2 * pi * 1:64 / 8))
+/// @export
+#[extendr]
+pub fn rfft(x: Vec<f64>) -> Robj {
+    if x.is_empty() {
+        panic!("x must not be empty");
+    }
+
+    features::rfft(&x).into_iter().collect_robj()
+}
+
+/// Inverse of `rfft()`.
+/// @param x \[Complex\] The non-negative frequency bins of a real signal.
+/// @param n \[Integer\] Length of the signal, `2 * (length(x) - 1)` or `2 * length(x) - 1`. `NA` assumes an even length, `2 * (length(x) - 1)`.
+/// @return a numeric vector of length `n`, scaled by `1 / n` so `irfft(rfft(x), length(x))` returns `x`.
+/// @export
+#[extendr]
+pub fn irfft(x: Robj, #[default = "NA_integer_"] n: Option<i32>) -> Vec<f64> {
+    let spectrum: &[c64] =
+        extendr_api::AsTypedSlice::as_typed_slice(&x).expect("x must be a complex vector");
+    if spectrum.is_empty() {
+        panic!("x must not be empty");
+    }
+    let n = match n {
+        Some(n) => usize::try_from(n).expect("n must be non-negative"),
+        None => 2 * (spectrum.len() - 1),
+    };
+    if n == 0 {
+        panic!("n must be positive");
+    }
+
+    features::irfft(spectrum, n)
+}
+
+/// Prepare a spectrogram for plotting with `image()`.
+/// @param s \[Array\] a 3D (t, f, channels) array, either a complex STFT or a real magnitude spectrogram such as a mel spectrogram.
+/// @param sr \[Integer\] Audio sampling rate.
+/// @param hop_length \[Integer\] Number of audio samples between columns of `s`.
+/// @param y_axis \[String\] Frequency axis of `s`. `"linear"` or `"log"` for FFT bins, assuming `n_fft = 2 * (f - 1)`, or `"mel"` for mel bands spanning 0 Hz to `sr / 2`.
+/// `"log"` drops the 0 Hz bin, so that every frequency is positive and `image(..., log = "y")` spaces the bins logarithmically.
+/// @return a list with `db`, the (t, f, channels) magnitude in dB relative to 1.0 and floored at -100 dB, `times`, the frame times in seconds, and `freqs`, the frequency of each bin in Hz.
+/// With `y_axis = "log"`, `db` and `freqs` have one bin less than `s`.
+/// @examples
+/// s <- stft(matrix(rnorm(22050), ncol = 1))
+/// x <- specshow_data(s, 22050L)
+/// image(x$times, x$freqs, x$db[, , 1])
+/// x <- specshow_data(s, 22050L, y_axis = "log")
+/// image(x$times, x$freqs, x$db[, , 1], log = "y")
+/// @export
+#[extendr]
+pub fn specshow_data(
+    s: Robj,
+    sr: i32,
+    #[default = "512L"] hop_length: i32,
+    #[default = "\"linear\""] y_axis: &str,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+
+    sync_db_floor();
+    let db: Array3<f64> = if s.is_complex() {
+        misc::require_stft(&s).mapv(|v| convert::amplitude_to_db(v.norm(), 1.))
+    } else {
+        let s = <misc::ArrayView3Wrapper<f64>>::from_robj(&s).expect("s must be a 3D array");
+        s.0.mapv(|v| convert::amplitude_to_db(v.abs(), 1.))
+    };
+    let (n_frames, n_bins, _) = db.dim();
+
+    let frames: Vec<usize> = (0..n_frames).collect();
+    let times = convert::frames_to_time(&frames, sr as u32, hop_length as usize);
+    let (freqs, first_bin) = specshow_freqs(y_axis, sr as u32, n_bins);
+    let db = db.slice(s![.., first_bin.., ..]);
+
+    list!(
+        db = Robj::try_from(&db).expect("cannot convert ndarray to Robj"),
+        times = times,
+        freqs = freqs
+    )
+    .into_robj()
+}
+
+// The frequencies of the bins `specshow_data` keeps, and the first of them: on a log axis the 0 Hz bin has no place.
+fn specshow_freqs(y_axis: &str, sr: u32, n_bins: usize) -> (Vec<f64>, usize) {
+    match y_axis {
+        "linear" | "log" => {
+            if n_bins < 2 {
+                panic!("s must have at least two frequency bins");
+            }
+            let freqs = convert::fft_frequencies(sr, 2 * (n_bins - 1));
+            let first_bin = usize::from(y_axis == "log");
+            (freqs[first_bin..].to_vec(), first_bin)
+        }
+        "mel" => (convert::mel_frequencies(n_bins, 0., sr as f64 / 2.), 0),
+        _ => panic!("y_axis must be one of \"linear\", \"log\" or \"mel\""),
+    }
+}
+
+/// Sonify a chromagram by playing each pitch class with a weight following the chroma, for auditioning harmonic content.
+/// Every pitch class is rendered as a sum of its octaves centered around A4 (440 Hz).
+/// @param chroma \[Array\] a 3D (t, 12, channels) array of doubles, pitch classes starting at C.
+/// @param sr \[Integer\] Sampling rate of the output.
+/// @param hop_length \[Integer\] Number of samples between successive chroma frames.
+/// @return a matrix of doubles (t * hop_length, channels) within \[-1, 1\].
+/// @export
+#[extendr]
+pub fn chroma_to_audio(
+    chroma: misc::ArrayView3Wrapper<f64>,
+    sr: i32,
+    #[default = "512L"] hop_length: i32,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if hop_length <= 0 {
+        panic!("hop_length must be positive");
+    }
+
+    let out = synth::chroma_to_audio(&chroma.0, sr as u32, hop_length as usize);
+
+    Robj::try_from(&out.t()).expect("cannot convert ndarray to Robj")
+}
+
+/// Metronome clicks at the given times, e.g. to overlay on a track and check beat times by ear.
+/// @param times \[Double\] Click times in seconds.
+/// @param sr \[Integer\] Sampling rate of the output.
+/// @param length \[Integer\] Number of samples of the output. `NA` makes it long enough for the last click.
+/// @param click_freq \[Double\] Frequency (Hz) of the decaying sine making up each 100 ms click.
+/// @return a matrix of doubles (length, 1). Overlapping clicks add up.
+/// @examples
+/// y <- clicks(c(0, 0.5, 1), 22050L)
+/// @export
+#[extendr]
+pub fn clicks(
+    times: Vec<f64>,
+    sr: i32,
+    #[default = "NA_integer_"] length: Option<i32>,
+    #[default = "1000"] click_freq: f64,
+) -> Robj {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    let length = match length {
+        Some(length) => usize::try_from(length).expect("length must be non-negative"),
+        None => {
+            let last = times.iter().cloned().fold(0., f64::max);
+            ((last + synth::CLICK_DURATION) * sr as f64).round() as usize
+        }
+    };
+
+    let out = synth::clicks(&times, sr as u32, length, click_freq).insert_axis(Axis(1));
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Generate a sine tone, e.g. as a test signal.
+/// @param freq \[Double\] Frequency in Hz.
+/// @param sr \[Integer\] Sampling rate of the output.
+/// @param duration \[Double\] Duration in seconds.
+/// @param amplitude \[Double\] Peak amplitude, within \[-1, 1\].
+/// @return a matrix of doubles (nsamples, 1).
+/// @examples
+/// a4 <- tone(440, 22050L, 1)
+/// @export
+#[extendr]
+pub fn tone(freq: f64, sr: i32, duration: f64, #[default = "0.5"] amplitude: f64) -> Robj {
+    let n = generator_samples(sr, duration, amplitude);
+
+    let out = synth::tone(freq, sr as u32, n, amplitude).insert_axis(Axis(1));
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Generate a sine sweep, e.g. to probe the frequency response of a processing step.
+/// @param fmin \[Double\] Start frequency in Hz.
+/// @param fmax \[Double\] End frequency in Hz.
+/// @param sr \[Integer\] Sampling rate of the output.
+/// @param duration \[Double\] Duration in seconds.
+/// @param linear \[Bool\] If `TRUE` the frequency rises linearly in time, otherwise exponentially, i.e. at a constant number of octaves per second.
+/// @param amplitude \[Double\] Peak amplitude, within \[-1, 1\].
+/// @return a matrix of doubles (nsamples, 1).
+/// @export
+#[extendr]
+pub fn chirp(
+    fmin: f64,
+    fmax: f64,
+    sr: i32,
+    duration: f64,
+    #[default = "FALSE"] linear: bool,
+    #[default = "0.5"] amplitude: f64,
+) -> Robj {
+    if fmin <= 0. || fmax <= 0. {
+        panic!("fmin and fmax must be positive");
+    }
+    let n = generator_samples(sr, duration, amplitude);
+
+    let out = synth::chirp(fmin, fmax, sr as u32, n, linear, amplitude).insert_axis(Axis(1));
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Generate white or pink noise, e.g. for augmentation or as a test signal.
+/// @param kind \[String\] `"white"` for a flat spectrum or `"pink"` for power falling 3 dB per octave.
+/// @param sr \[Integer\] Sampling rate of the output.
+/// @param duration \[Double\] Duration in seconds.
+/// @param seed \[Double\] Optional seed, a non-negative whole number. The same seed gives the same noise on every run and platform. `NA` seeds from the clock.
+/// @param amplitude \[Double\] Peak amplitude, within \[-1, 1\]. Pink noise is scaled to reach it exactly.
+/// @return a matrix of doubles (nsamples, 1).
+/// @examples
+/// n <- noise("pink", 22050L, 1, seed = 1)
+/// @export
+#[extendr]
+pub fn noise(
+    #[default = "\"white\""] kind: &str,
+    sr: i32,
+    duration: f64,
+    #[default = "NA_real_"] seed: Option<f64>,
+    #[default = "0.5"] amplitude: f64,
+) -> Robj {
+    let kind = synth::NoiseKind::from_name(kind);
+    let n = generator_samples(sr, duration, amplitude);
+    let mut rng = match seed {
+        Some(seed) => util::SplitMix64::new(to_samples(seed, "seed")),
+        None => util::SplitMix64::from_time(),
+    };
+
+    let out = synth::noise(&kind, n, amplitude, &mut rng).insert_axis(Axis(1));
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
+}
+
+/// Generate a click track of a given length, e.g. as a reference for beat tracking. Like `clicks()` with 1 kHz clicks.
+/// @param times \[Double\] Click times in seconds.
+/// @param sr \[Integer\] Sampling rate of the output.
+/// @param length \[Integer\] Number of samples of the output.
+/// @return a matrix of doubles (length, 1).
+/// @export
+#[extendr]
+pub fn click_track(times: Vec<f64>, sr: i32, length: i32) -> Robj {
+    clicks(times, sr, Some(length), 1000.)
+}
+
+/// Number of samples of a generated signal, validating the common generator arguments.
+fn generator_samples(sr: i32, duration: f64, amplitude: f64) -> usize {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if duration.is_nan() || duration < 0. {
+        panic!("duration must be non-negative");
+    }
+    if amplitude.is_nan() || amplitude.abs() > 1. {
+        panic!("amplitude must be within [-1, 1]");
+    }
+    (duration * sr as f64).round() as usize
+}
+
+/// A user-supplied (n_bands, n_bins) filterbank matrix, or `None` for `NULL`.
+fn filterbank_from_robj(filterbank: &Robj) -> Option<Array2<f64>> {
+    if filterbank.is_null() {
+        return None;
+    }
+    let filterbank: ArrayView2<f64> =
+        ArrayView2::from_robj(filterbank).expect("filterbank must be a numeric matrix");
+    Some(filterbank.to_owned())
+}
+
+/// `num_threads` passed from R, `None` for `NA` to use the global rayon pool.
+fn thread_count(num_threads: Option<i32>) -> Option<usize> {
+    num_threads.map(|n| {
+        if n <= 0 {
+            panic!("num_threads must be positive");
+        }
+        n as usize
+    })
+}
+
+/// Load each file as a (channels, nsamples) array, resampled to `sr` with `res_type` if it has another
+/// sampling rate, and compute a feature array from it on the rayon thread pool. Files that fail to load give `NULL`.
+fn features_batch<F>(
+    fnames: &[String],
+    sr: i32,
+    res_type: &resample::ResType,
+    num_threads: Option<i32>,
+    features: F,
+) -> Robj
+where
+    F: Fn(&ArrayView2<f64>, u32) -> Array3<f64> + Sync,
+{
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+
+    let results = util::par_map_catch(fnames, thread_count(num_threads), |fname| {
+        let path = Path::new(fname);
+        let mut reader =
+            decode_symphonia::AudioReader::open(path, None, false, filetype_of(path, None));
+        let decoded_arr = reader.read(0., None, false, &decode_symphonia::Norm::FullScale);
+        if reader.sr == sr as u32 {
+            return features(&decoded_arr.view(), sr as u32);
+        }
+        let resampled = resample::resample(&decoded_arr.view(), reader.sr, sr as u32, res_type);
+
+        features(&resampled.view(), sr as u32)
+    });
+
+    // R objects can only be created on the main thread.
+    List::from_values(results.iter().map(|out| match out {
+        Some(out) => Robj::try_from(out).expect("cannot convert ndarray to Robj"),
+        None => ().into(),
+    }))
+    .into_robj()
+}
+
+/// Compute mel spectrograms for many files in parallel.
+/// @param fnames \[Character\] The paths to the input files.
+/// @param sr \[Integer\] Sampling rate of the features. Files with another sampling rate are resampled to `sr`.
+/// @param n_fft \[Integer\] Length of the FFT window. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param n_mels \[Integer\] Number of mel bands spanning 0 Hz to `sr / 2`.
+/// @param filterbank \[Matrix\] Optional (n_bands, n_fft / 2 + 1) matrix of doubles replacing the mel filters, e.g. to use a Bark or ERB scale. `n_mels` is then ignored.
+/// @param power \[Double\] Exponent of the STFT magnitude the filters are applied to, `2` for a power and `1` for a magnitude spectrogram.
+/// @param res_type \[String\] Resampling method for files whose sampling rate differs from `sr`, see `resample()`.
+/// @param aggregate \[String\] `NA` or `"mean"`, see `stft()`.
+/// @param num_threads \[Integer\] Number of threads to process the files on, see `get_durations()`.
+/// @return a list with one (t, n_mels, channels) spectrogram per file, or (t, n_bands, channels) with `filterbank`, in the order of `fnames`. Files that cannot be loaded are `NULL`.
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn melspectrogram_batch(
+    fnames: Vec<String>,
+    sr: i32,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "128L"] n_mels: i32,
+    #[default = "NULL"] filterbank: Robj,
+    #[default = "2"] power: f64,
+    #[default = "\"kaiser_best\""] res_type: &str,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+    #[default = "NA_integer_"] num_threads: Option<i32>,
+) -> Robj {
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    check_power(power);
+    let res_type = resample::ResType::from_name(res_type);
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let aggregate = features::Channels::from_name(aggregate);
+    let n_mels = usize::try_from(n_mels).expect("n_mels must be non-negative");
+    let filterbank = filterbank_from_robj(&filterbank);
+    if let Some(filterbank) = &filterbank {
+        // Check once here rather than failing every file of the batch.
+        features::check_filterbank(&filterbank.view(), n_fft);
+    }
+
+    features_batch(&fnames, sr, &res_type, num_threads, |arr, sr| {
+        aggregate.apply(features::melspectrogram(
+            arr,
+            sr,
+            n_fft,
+            hop_length,
+            &pad_mode,
+            n_mels,
+            filterbank.as_ref().map(|f| f.view()).as_ref(),
+            power,
+        ))
+    })
+}
+
+/// Mel spectrogram of a segment of a file, decoding only the segment, e.g. for random access to features of long files.
+/// The reader seeks to `start_sec` and decodes `length_sec` seconds, which are resampled to `sr` if the file has another sampling rate, so neither the rest of the file nor its full-length resampled audio is ever held in memory.
+/// @param fname \[String\] The path to the input file.
+/// @param sr \[Integer\] Sampling rate the segment is resampled to before the spectrogram.
+/// @param start_sec \[Double\] Start of the segment in seconds, counted from the end of the file if negative.
+/// @param length_sec \[Double\] Length of the segment in seconds. `NA` reads to the end of the file. If the file ends sooner, the segment runs to its end, with a warning.
+/// @param mono \[Logical\] Whether to average the channels.
+/// @param n_fft \[Integer\] Length of the FFT window. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param n_mels \[Integer\] Number of mel bands spanning 0 Hz to `sr / 2`.
+/// @param power \[Double\] Exponent of the STFT magnitude the filters are applied to, `2` for a power and `1` for a magnitude spectrogram.
+/// @param res_type \[String\] Resampling method, see `resample()`.
+/// @param aggregate \[String\] `NA` or `"mean"`, see `stft()`.
+/// @return a (t, n_mels, channels) array of doubles.
+/// @examples
+/// fname <- tempfile(fileext = ".flac")
+/// write_flac(fname, matrix(sin(1:80000 / 10), ncol = 1), 8000L)
+/// melspectrogram_segment(fname, 16000L, 2, 0.5)
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn melspectrogram_segment(
+    fname: &str,
+    sr: i32,
+    #[default = "0"] start_sec: f64,
+    #[default = "NA_real_"] length_sec: Option<f64>,
+    #[default = "TRUE"] mono: bool,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "128L"] n_mels: i32,
+    #[default = "2"] power: f64,
+    #[default = "\"kaiser_best\""] res_type: &str,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    error::raise(|| {
+        let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+        check_power(power);
+        let n_mels = usize::try_from(n_mels).expect("n_mels must be non-negative");
+        let res_type = resample::ResType::from_name(res_type);
+        let pad_mode = features::PadMode::from_name(pad_mode);
+        let aggregate = features::Channels::from_name(aggregate);
+
+        let path = Path::new(fname);
+        let mut reader =
+            decode_symphonia::AudioReader::open(path, None, false, filetype_of(path, None));
+        let (_, n_samples) = reader.segment(start_sec, length_sec);
+        warn_if_short(
+            length_sec.map(|d| (d * reader.sr as f64) as u64),
+            n_samples,
+            reader.sr,
+        );
+        let segment = reader.read(
+            start_sec,
+            length_sec,
+            mono,
+            &decode_symphonia::Norm::FullScale,
+        );
+        let segment = resample::resample(&segment.view(), reader.sr, sr as u32, &res_type);
+
+        let out = features::melspectrogram(
+            &segment.view(),
+            sr as u32,
+            n_fft,
+            hop_length,
+            &pad_mode,
+            n_mels,
+            None,
+            power,
+        );
+        Robj::try_from(&aggregate.apply(out)).expect("cannot convert ndarray to Robj")
+    })
+}
+
+/// Compute MFCCs for many files in parallel.
+/// @param fnames \[Character\] The paths to the input files.
+/// @param sr \[Integer\] Sampling rate of the features. Files with another sampling rate are resampled to `sr`.
+/// @param n_mfcc \[Integer\] Number of coefficients to return.
+/// @param n_fft \[Integer\] Length of the FFT window. `NA` scales it to `sr`, see `default_frame_params()`.
+/// @param hop_length \[Integer\] Number of samples between successive frames. `NA` uses `n_fft / 4`, see `default_frame_params()`.
+/// @param pad_mode \[String\] Edge padding, see `stft()`.
+/// @param n_mels \[Integer\] Number of mel bands spanning 0 Hz to `sr / 2`.
+/// @param res_type \[String\] Resampling method for files whose sampling rate differs from `sr`, see `resample()`.
+/// @param aggregate \[String\] `NA` or `"mean"`, see `stft()`.
+/// @param num_threads \[Integer\] Number of threads to process the files on, see `get_durations()`.
+/// @return a list with one (t, n_mfcc, channels) array per file, in the order of `fnames`. Files that cannot be loaded are `NULL`.
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn mfcc_batch(
+    fnames: Vec<String>,
+    sr: i32,
+    #[default = "20L"] n_mfcc: i32,
+    #[default = "NA_integer_"] n_fft: Option<i32>,
+    #[default = "NA_integer_"] hop_length: Option<i32>,
+    #[default = "\"reflect\""] pad_mode: &str,
+    #[default = "128L"] n_mels: i32,
+    #[default = "\"kaiser_best\""] res_type: &str,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+    #[default = "NA_integer_"] num_threads: Option<i32>,
+) -> Robj {
+    let n_mfcc = usize::try_from(n_mfcc).expect("n_mfcc must be non-negative");
+    let res_type = resample::ResType::from_name(res_type);
+    let (n_fft, hop_length) = frame_params(sr, n_fft, hop_length);
+    let pad_mode = features::PadMode::from_name(pad_mode);
+    let n_mels = usize::try_from(n_mels).expect("n_mels must be non-negative");
+    let aggregate = features::Channels::from_name(aggregate);
+    sync_db_floor();
+
+    features_batch(&fnames, sr, &res_type, num_threads, |arr, sr| {
+        aggregate.apply(features::mfcc(
+            arr, sr, n_mfcc, n_fft, hop_length, &pad_mode, n_mels,
+        ))
+    })
+}
+
+/// Log mel filterbank energies computed as by Kaldi's `compute-fbank-feats`, for models trained on Kaldi or ESPnet features.
+/// Frames are preprocessed in Kaldi's order: dither, DC offset removal, energy, pre-emphasis and the Povey window. The mel filters and log floor are Kaldi's too, so the features match up to single-precision rounding.
+/// The defaults are those of `compute-fbank-feats` except `dither`; compare against Kaldi run with `--dither=0`.
+/// These are not a mode of `melspectrogram_batch()` and `mfcc_batch()`, which follow librosa: Kaldi frames in milliseconds from the first sample rather than centered on multiples of `hop_length`, preprocesses every frame, and has its own window, mel scale, filter normalization and log floor, so none of their parameters but `n_mels` would carry over. Unlike those, `kaldi_fbank()` and `kaldi_mfcc()` take audio in memory, scaled as Kaldi reads it, rather than files.
+/// @param r_arr \[Matrix\] Matrix of doubles (nsamples, channels) representing the audio data. Kaldi reads 16-bit samples as integers, so scale audio loaded in \[-1, 1\] by 32768 to get the same values.
+/// @param sr \[Integer\] Sampling rate of `r_arr`.
+/// @param n_mels \[Integer\] Number of mel bins, from 20 Hz to `sr / 2` (`--num-mel-bins`).
+/// @param frame_length \[Double\] Frame length in milliseconds (`--frame-length`). The FFT length is the next power of two.
+/// @param frame_shift \[Double\] Frame shift in milliseconds (`--frame-shift`).
+/// @param use_energy \[Logical\] Add the log energy of each frame (`--use-energy`).
+/// @param dither \[Double\] Standard deviation of the Gaussian noise added to every sample (`--dither`). Kaldi's default is 1, which makes the features random.
+/// @param remove_dc_offset \[Logical\] Subtract the mean of each frame (`--remove-dc-offset`).
+/// @param preemph_coeff \[Double\] Pre-emphasis coefficient (`--preemphasis-coefficient`).
+/// @param snip_edges \[Logical\] Only keep the frames that fit in the signal (`--snip-edges`). `FALSE` gives one frame per `frame_shift`, with the signal mirrored at the ends.
+/// @param raw_energy \[Logical\] Take the energy before pre-emphasis and windowing (`--raw-energy`).
+/// @param aggregate \[String\] `NA` or `"mean"`, see `stft()`.
+/// @return an array of doubles (t, n_mels, channels), or (t, n_mels + 1, channels) with the log energy first when `use_energy = TRUE`.
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn kaldi_fbank(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "23L"] n_mels: i32,
+    #[default = "25"] frame_length: f64,
+    #[default = "10"] frame_shift: f64,
+    #[default = "FALSE"] use_energy: bool,
+    #[default = "0"] dither: f64,
+    #[default = "TRUE"] remove_dc_offset: bool,
+    #[default = "0.97"] preemph_coeff: f64,
+    #[default = "TRUE"] snip_edges: bool,
+    #[default = "TRUE"] raw_energy: bool,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    let aggregate = features::Channels::from_name(aggregate);
+    let opts = kaldi_options(
+        sr,
+        n_mels,
+        frame_length,
+        frame_shift,
+        use_energy,
+        dither,
+        remove_dc_offset,
+        preemph_coeff,
+        snip_edges,
+        raw_energy,
+    );
+
+    kaldi_channels(r_arr, aggregate, |x| kaldi::fbank(x, sr as u32, &opts))
+}
+
+/// MFCCs computed as by Kaldi's `compute-mfcc-feats`: the orthonormal DCT of the log mel energies of `kaldi_fbank()`, liftered, with C0 replaced by the log energy of the frame when `use_energy = TRUE`.
+/// The defaults are those of `compute-mfcc-feats` except `dither`; compare against Kaldi run with `--dither=0`.
+/// @inheritParams kaldi_fbank
+/// @param n_mfcc \[Integer\] Number of coefficients (`--num-ceps`).
+/// @param lifter \[Double\] Sinusoidal liftering coefficient (`--cepstral-lifter`), see `liftering()`.
+/// @param use_energy \[Logical\] Replace C0 by the log energy of each frame (`--use-energy`).
+/// @return an array of doubles (t, n_mfcc, channels).
+/// @examples
+/// x <- matrix(rnorm(16000), ncol = 1) * 1000
+/// feats <- kaldi_mfcc(x, 16000L)
+/// dim(feats) # 98 frames of 13 coefficients
+/// @export
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+pub fn kaldi_mfcc(
+    r_arr: RMatrix<f64>,
+    sr: i32,
+    #[default = "13L"] n_mfcc: i32,
+    #[default = "22"] lifter: f64,
+    #[default = "23L"] n_mels: i32,
+    #[default = "25"] frame_length: f64,
+    #[default = "10"] frame_shift: f64,
+    #[default = "TRUE"] use_energy: bool,
+    #[default = "0"] dither: f64,
+    #[default = "TRUE"] remove_dc_offset: bool,
+    #[default = "0.97"] preemph_coeff: f64,
+    #[default = "TRUE"] snip_edges: bool,
+    #[default = "TRUE"] raw_energy: bool,
+    #[default = "NA_character_"] aggregate: Option<&str>,
+) -> Robj {
+    let n_mfcc = usize::try_from(n_mfcc).expect("n_mfcc must be non-negative");
+    if lifter.is_nan() || lifter < 0. {
+        panic!("lifter must be non-negative");
+    }
+    let aggregate = features::Channels::from_name(aggregate);
+    let opts = kaldi_options(
+        sr,
+        n_mels,
+        frame_length,
+        frame_shift,
+        use_energy,
+        dither,
+        remove_dc_offset,
+        preemph_coeff,
+        snip_edges,
+        raw_energy,
+    );
+
+    kaldi_channels(r_arr, aggregate, |x| {
+        kaldi::mfcc(x, sr as u32, n_mfcc, lifter, &opts)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn kaldi_options(
+    sr: i32,
+    n_mels: i32,
+    frame_length: f64,
+    frame_shift: f64,
+    use_energy: bool,
+    dither: f64,
+    remove_dc_offset: bool,
+    preemph_coeff: f64,
+    snip_edges: bool,
+    raw_energy: bool,
+) -> kaldi::KaldiOptions {
+    if sr <= 0 {
+        panic!("sr must be positive");
+    }
+    if n_mels <= 0 {
+        panic!("n_mels must be positive");
+    }
+    if frame_length.is_nan() || frame_length <= 0. || frame_shift.is_nan() || frame_shift <= 0. {
+        panic!("frame_length and frame_shift must be positive");
+    }
+    if dither.is_nan() || dither < 0. {
+        panic!("dither must be non-negative");
+    }
+    if !(0. ..=1.).contains(&preemph_coeff) {
+        panic!("preemph_coeff must be between 0 and 1");
+    }
+
+    kaldi::KaldiOptions {
+        frame_length,
+        frame_shift,
+        n_mels: n_mels as usize,
+        use_energy,
+        dither,
+        remove_dc_offset,
+        preemph_coeff,
+        snip_edges,
+        raw_energy,
+    }
+}
+
+/// Apply a Kaldi feature to every channel, stacking the (t, n) results into a (t, n, channels) array, averaged over the channels with `Channels::Mean`.
+fn kaldi_channels<F>(r_arr: RMatrix<f64>, aggregate: features::Channels, feature: F) -> Robj
+where
+    F: Fn(&ArrayView1<f64>) -> Array2<f64>,
+{
+    let robj = RMatrix::into_robj(r_arr);
+    let arr: ArrayView2<f64> =
+        ArrayView2::from_robj(&robj).expect("cannot convert Robj to ArrayView2");
+
+    let channels: Vec<Array2<f64>> = arr.t().outer_iter().map(|c| feature(&c)).collect();
+    let views: Vec<ArrayView2<f64>> = channels.iter().map(|c| c.view()).collect();
+    let out = aggregate.apply(ndarray::stack(Axis(2), &views).expect("cannot stack channels"));
+
+    Robj::try_from(&out).expect("cannot convert ndarray to Robj")
 }
 
 // Macro to generate exports.
@@ -112,12 +4014,168 @@ pub fn play2(abar: &ArrayBaseR, sr: i32) {
 extendr_module! {
     mod audiotest;
     fn load;
-    // fn to_mono;
+    fn load_into;
+    fn to_mono;
+    fn to_dataframe;
+    fn downsample_plot;
     // fn get_duration;
     fn get_samplerate;
-    fn play;
+    fn get_bit_depth;
+    fn get_sample_format;
+    fn get_durations;
+    fn list_tracks;
+    fn probe;
+    fn scan_directory;
+    fn is_lossless;
+    fn write_flac;
+    fn supported_formats;
+    fn supported_extensions;
+    impl AudioHandle;
+    fn open_audio;
+    fn read;
+    fn read_segment;
+    fn close_audio;
+    fn play_impl;
+    fn play_blocking;
     fn test_in_R;
     impl ArrayBaseR;
     fn load2;
     fn play2;
+    fn resample;
+    fn resample_to;
+    fn db_floor;
+    fn frame;
+    fn rms;
+    fn vad;
+    fn apply_gain_envelope;
+    fn to_midside;
+    fn from_midside;
+    fn adjust_width;
+    fn pan;
+    fn remap_channels;
+    fn crossfade;
+    fn amplitude_envelope;
+    fn compress;
+    fn soft_clip;
+    fn notch;
+    fn convolve_reverb;
+    fn integrated_loudness;
+    fn normalize_loudness;
+    fn peak_normalize;
+    fn fix_length;
+    fn tile;
+    fn concatenate;
+    fn reverse;
+    fn invert_polarity;
+    fn stack_memory;
+    fn sync;
+    fn dtw;
+    fn recurrence_matrix;
+    fn segment;
+    fn novelty;
+    fn onset_strength;
+    fn onset_detect;
+    fn liftering;
+    fn whiten;
+    fn normalize_spectrogram;
+    fn frames_to_time;
+    fn samples_to_time;
+    fn hz_to_bark;
+    fn bark_to_hz;
+    fn hz_to_erb;
+    fn erb_to_hz;
+    fn bark_frequencies;
+    fn erb_frequencies;
+    fn default_frame_params;
+    fn stft;
+    fn stft_mag_phase;
+    fn spectral_entropy;
+    fn psd;
+    fn vqt;
+    fn icqt;
+    fn yin;
+    fn pyin;
+    fn chroma_stft;
+    fn estimate_key;
+    fn if_gram;
+    fn istft;
+    fn reconstruct;
+    fn rfft;
+    fn irfft;
+    impl StftStream;
+    fn stft_stream;
+    impl PcmStream;
+    fn pcm_stream;
+    fn specshow_data;
+    fn chroma_to_audio;
+    fn clicks;
+    fn tone;
+    fn chirp;
+    fn noise;
+    fn click_track;
+    fn melspectrogram_batch;
+    fn melspectrogram_segment;
+    fn mfcc_batch;
+    fn kaldi_fbank;
+    fn kaldi_mfcc;
+}
+
+#[cfg(test)]
+mod test_lib {
+    use super::*;
+
+    #[test]
+    fn test_istft_channels_layout() {
+        // Two channels in the (nsamples, channels) layout of an R matrix, as `stft` and `istft` see them.
+        let x = Array2::from_shape_fn((3000, 2), |(i, c)| {
+            (i as f64 * 0.05 * (c + 1) as f64).sin() + 0.3 * (i as f64 * 0.71).cos()
+        });
+        for n_fft in [512, 511] {
+            let s = stft_channels(
+                &x.view(),
+                n_fft,
+                128,
+                &features::PadMode::Reflect,
+                0..n_fft / 2 + 1,
+            );
+            assert_eq!(s.dim(), (24, n_fft / 2 + 1, 2));
+
+            let y = istft_channels(&s.view(), 128, Some(3000), Some(n_fft as i32));
+            assert_eq!(y.dim(), x.dim());
+            assert!(Zip::from(&x).and(&y).all(|a, b| (a - b).abs() < 1e-9));
+
+            let cropped = istft_channels(&s.view(), 128, Some(1000), Some(n_fft as i32));
+            assert_eq!(cropped, y.slice(s![..1000, ..]));
+            // Past the last frame, the end is zero-padded.
+            let padded = istft_channels(&s.view(), 128, Some(4000), Some(n_fft as i32));
+            assert_eq!(padded.slice(s![..3000, ..]), y);
+            assert!(padded.slice(s![3500.., ..]).iter().all(|v| *v == 0.));
+            assert_eq!(
+                istft_channels(&s.view(), 128, None, Some(n_fft as i32)).dim(),
+                (128 * 23, 2)
+            );
+        }
+        // Without n_fft, the even length is assumed.
+        let s = stft_channels(&x.view(), 512, 128, &features::PadMode::Reflect, 0..257);
+        assert_eq!(
+            istft_channels(&s.view(), 128, Some(3000), None),
+            istft_channels(&s.view(), 128, Some(3000), Some(512))
+        );
+    }
+
+    #[test]
+    fn test_specshow_freqs() {
+        let (linear, first_bin) = specshow_freqs("linear", 22050, 1025);
+        assert_eq!((linear.len(), first_bin), (1025, 0));
+        assert_eq!((linear[0], linear[1024]), (0., 11025.));
+
+        // The log axis starts at the first bin above 0 Hz.
+        let (log, first_bin) = specshow_freqs("log", 22050, 1025);
+        assert_eq!((log.len(), first_bin), (1024, 1));
+        assert_eq!(log[..], linear[1..]);
+        assert!(log.iter().all(|f| *f > 0.));
+
+        let (mel, first_bin) = specshow_freqs("mel", 22050, 128);
+        assert_eq!((mel.len(), first_bin), (128, 0));
+    }
 }
@@ -1,15 +1,36 @@
-use extendr_api::{FromRobj, Robj, Rinternals, AsTypedSlice};
-use ndarray::{Array2, ArrayView2, Axis, ShapeBuilder};
+use extendr_api::{FromRobj, Robj, Rinternals, AsTypedSlice, Attributes};
+use ndarray::{Array2, ArrayView2, ArrayView3, Axis, ShapeBuilder, Zip};
 use num_complex::Complex;
 
 /// Convert to 1 channel taking the average across channels.
-pub fn to_mono_ndarray(arr: &ArrayView2<f64>) -> Array2<f64> { // into_shape doesn't create a copy but mean_axis does
-    arr.mean_axis(Axis(0))
-        .expect("cannot mean_axis") 
-        .into_shape((1, arr.ncols()))
-        .expect("cannot reshape")
+/// Each channel is a contiguous row, so the elementwise `Zip` loops below are auto-vectorized.
+/// The additions happen in the same order as in `mean_axis(Axis(0))`, so the result is identical.
+pub fn to_mono_ndarray(arr: &ArrayView2<f64>) -> Array2<f64> {
+    let channels = arr.nrows();
+    let mut mono = Array2::<f64>::zeros((1, arr.ncols()));
+    let mut out = mono.row_mut(0);
+
+    match channels {
+        0 => panic!("cannot downmix an array without channels"),
+        1 => out.assign(&arr.row(0)),
+        // Stereo, the common case, in a single pass.
+        2 => Zip::from(&mut out)
+            .and(arr.row(0))
+            .and(arr.row(1))
+            .for_each(|o, &l, &r| *o = (l + r) / 2.),
+        _ => {
+            out.assign(&arr.row(0));
+            for row in arr.outer_iter().skip(1) {
+                Zip::from(&mut out).and(&row).for_each(|o, &x| *o += x);
+            }
+            out.mapv_inplace(|v| v / channels as f64);
+        }
+    }
+
+    mono
 }
 
+/// View of a complex R matrix, such as a single channel `stft(x)[, , 1]` that R dropped to (t, f).
 pub struct ArrayView2Wrapper<'a>(pub ArrayView2<'a, Complex<f64>>);
 
 impl<'a> FromRobj<'a> for ArrayView2Wrapper<'a> {
@@ -27,3 +48,129 @@ impl<'a> FromRobj<'a> for ArrayView2Wrapper<'a> {
         return Err("cannot convert Robj to ArrayView2Wrapper");
     }
 }
+
+/// View of a 3D R array, e.g. a (t, f, channels) feature array or a complex STFT.
+pub struct ArrayView3Wrapper<'a, T>(pub ArrayView3<'a, T>);
+
+impl<'a, T> FromRobj<'a> for ArrayView3Wrapper<'a, T>
+where
+    Robj: AsTypedSlice<'a, T>,
+{
+    fn from_robj(robj: &'a Robj) -> std::result::Result<Self, &'static str> {
+        if let Some(dim) = robj.dim() {
+            let dim: Vec<usize> = dim.iter().map(|d| d.0 as usize).collect();
+            if dim.len() == 3 {
+                if let Some(v) = robj.as_typed_slice() {
+                    let shape = (dim[0], dim[1], dim[2]).into_shape().f();
+                    if let Ok(res) = ArrayView3::from_shape(shape, v) {
+                        return Ok(ArrayView3Wrapper(res));
+                    }
+                }
+            }
+        }
+        Err("cannot convert Robj to ArrayView3Wrapper")
+    }
+}
+
+/// View of a complex 3D array, such as a STFT or CQT. `layout` describes the expected array in the panic message
+/// when `s` is real, such as a magnitude spectrogram, or not 3D.
+pub fn require_complex<'a>(s: &'a Robj, layout: &str) -> ArrayView3<'a, Complex<f64>> {
+    if !s.is_complex() {
+        panic!(
+            "{}, got a {} array: a magnitude or power spectrogram cannot be inverted",
+            layout,
+            if s.is_real() { "real" } else { "non-complex" }
+        );
+    }
+    let n_dim = s.dim().map_or(1, |dim| dim.len());
+    if n_dim != 3 {
+        panic!("{}, got {} dimensions", layout, n_dim);
+    }
+    ArrayView3Wrapper::<Complex<f64>>::from_robj(s).expect(layout).0
+}
+
+/// View of a complex STFT, a (t, f, channels) array as returned by `stft()`, or a (t, f) matrix of a single channel
+/// as left by subsetting one channel in R. Panics with a message naming that layout when `s` is real, such as a
+/// magnitude spectrogram, or neither 2D nor 3D.
+pub fn require_stft(s: &Robj) -> ArrayView3<'_, Complex<f64>> {
+    const LAYOUT: &str = "s must be a complex (t, f, channels) STFT as returned by stft()";
+    let s = if s.is_complex() && s.is_matrix() {
+        ArrayView2Wrapper::from_robj(s).expect(LAYOUT).0.insert_axis(Axis(2))
+    } else {
+        require_complex(s, LAYOUT)
+    };
+    if s.dim().1 < 2 {
+        panic!("{}, got fewer than two frequency bins", LAYOUT);
+    }
+    s
+}
+
+/// View of a real (t, f, channels) feature array, such as a spectrogram from `stft()` or `melspectrogram_batch()`.
+/// Panics with a message naming that layout when `s` is complex, not 3D or empty.
+pub fn require_features<'a>(s: &'a Robj, name: &str) -> ArrayView3<'a, f64> {
+    if !s.is_real() {
+        panic!(
+            "{} must be a real (t, f, channels) array, take Mod() of a complex STFT first",
+            name
+        );
+    }
+    let n_dim = s.dim().map_or(1, |dim| dim.len());
+    if n_dim != 3 {
+        panic!(
+            "{} must be a real (t, f, channels) array, got {} dimensions",
+            name, n_dim
+        );
+    }
+    let s = ArrayView3Wrapper::<f64>::from_robj(s)
+        .expect("cannot convert Robj to ArrayView3Wrapper")
+        .0;
+    if s.is_empty() {
+        panic!("{} must not be empty", name);
+    }
+    s
+}
+
+#[cfg(test)]
+mod test_misc {
+    use super::*;
+    use ndarray::{Array1, Axis};
+
+    fn mean_axis_mono(arr: &ArrayView2<f64>) -> Array2<f64> {
+        arr.mean_axis(Axis(0))
+            .expect("cannot mean_axis")
+            .into_shape((1, arr.ncols()))
+            .expect("cannot reshape")
+    }
+
+    #[test]
+    fn test_to_mono_matches_mean_axis() {
+        for channels in 1..=5 {
+            let arr = Array2::from_shape_fn((channels, 1001), |(c, i)| ((c * 1001 + i) as f64 * 0.37).sin());
+            let mono = to_mono_ndarray(&arr.view());
+            let expected = mean_axis_mono(&arr.view());
+            assert!(Zip::from(&mono).and(&expected).all(|a, b| (a - b).abs() <= 1e-12));
+        }
+    }
+
+    // Benchmark against `mean_axis` on a 10-minute stereo file at 44.1 kHz.
+    // Run with `cargo test --release -- --ignored --nocapture bench_to_mono`.
+    #[test]
+    #[ignore]
+    fn bench_to_mono() {
+        let n = 10 * 60 * 44100;
+        let t = Array1::<f64>::linspace(0., 600., n);
+        let mut arr = Array2::<f64>::zeros((2, n));
+        arr.row_mut(0).assign(&t.mapv(|v| (v * 2764.6).sin()));
+        arr.row_mut(1).assign(&t.mapv(|v| (v * 3110.2).sin()));
+
+        let start = std::time::Instant::now();
+        let expected = mean_axis_mono(&arr.view());
+        println!("mean_axis:       {:?}", start.elapsed());
+
+        let start = std::time::Instant::now();
+        let mono = to_mono_ndarray(&arr.view());
+        println!("to_mono_ndarray: {:?}", start.elapsed());
+
+        assert_eq!(mono, expected);
+    }
+}
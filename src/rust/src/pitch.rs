@@ -0,0 +1,438 @@
+use ndarray::{s, Array2, ArrayView1, ArrayView2};
+
+use crate::features::{self, PadMode};
+
+/// Range of lags, in samples, searched for periods between `sr / fmax` and `sr / fmin`.
+/// A frame of `frame_length` samples compares windows of `frame_length / 2` samples, so the
+/// longest lag is capped at `frame_length - frame_length / 2 - 1`.
+pub fn period_range(sr: u32, fmin: f64, fmax: f64, frame_length: usize) -> (usize, usize) {
+    if fmin <= 0. || fmin >= fmax {
+        panic!("fmin must be positive and below fmax");
+    }
+    let win_length = frame_length / 2;
+    let min_period = usize::max((sr as f64 / fmax).floor() as usize, 1);
+    let max_period = usize::min(
+        (sr as f64 / fmin).ceil() as usize,
+        (frame_length - win_length).saturating_sub(1),
+    );
+    if min_period >= max_period {
+        panic!("frame_length is too short for fmin, or fmax too close to fmin");
+    }
+    (min_period, max_period)
+}
+
+/// YIN difference function of a frame: the squared difference between its first
+/// `frame.len() / 2` samples and the same window shifted by each lag up to `max_period`.
+/// The cross term is an FFT correlation and the energies running sums, so a frame costs
+/// O(n log n) rather than O(n * max_period).
+fn difference(frame: &ArrayView1<f64>, max_period: usize) -> Vec<f64> {
+    let win_length = frame.len() / 2;
+    let n_fft = frame.len().next_power_of_two();
+    let mut window = vec![0.; n_fft];
+    let mut signal = vec![0.; n_fft];
+    for (i, v) in frame.iter().enumerate() {
+        signal[i] = *v;
+        if i < win_length {
+            window[i] = *v;
+        }
+    }
+    let spectrum: Vec<_> = features::rfft(&window)
+        .iter()
+        .zip(features::rfft(&signal))
+        .map(|(w, s)| w.conj() * s)
+        .collect();
+    let correlation = features::irfft(&spectrum, n_fft);
+
+    let mut cumulative = Vec::with_capacity(frame.len() + 1);
+    cumulative.push(0.);
+    for v in frame.iter() {
+        cumulative.push(cumulative[cumulative.len() - 1] + v * v);
+    }
+    let energy = |lag: usize| cumulative[lag + win_length] - cumulative[lag];
+
+    (0..=max_period)
+        .map(|lag| f64::max(energy(0) + energy(lag) - 2. * correlation[lag], 0.))
+        .collect()
+}
+
+/// Cumulative mean normalized difference of each lag: the difference divided by its mean over
+/// the shorter lags, 1 at lag 0. Frames without energy are 1 everywhere.
+pub fn cumulative_mean_normalized_difference(
+    frame: &ArrayView1<f64>,
+    max_period: usize,
+) -> Vec<f64> {
+    let difference = difference(frame, max_period);
+    let mut running = 0.;
+    difference
+        .iter()
+        .enumerate()
+        .map(|(lag, d)| {
+            running += d;
+            if lag == 0 || running <= 0. {
+                1.
+            } else {
+                d * lag as f64 / running
+            }
+        })
+        .collect()
+}
+
+/// Lag of the minimum at `lag` of `values`, refined by fitting a parabola through its neighbors.
+pub fn parabolic_lag(values: &[f64], lag: usize) -> f64 {
+    if lag == 0 || lag + 1 >= values.len() {
+        return lag as f64;
+    }
+    let (before, at, after) = (values[lag - 1], values[lag], values[lag + 1]);
+    let curvature = before - 2. * at + after;
+    if curvature <= 0. {
+        return lag as f64;
+    }
+    lag as f64 + 0.5 * (before - after) / curvature
+}
+
+/// YIN fundamental frequency estimate (de Cheveigné and Kawahara, 2002) of each frame of `x`.
+/// Frames are centered on multiples of `hop_length` and padded with `pad_mode` as in
+/// `features::frame`. The period of a frame is the first local minimum of the cumulative mean
+/// normalized difference below `threshold` between the lags of `fmax` and `fmin`, refined by
+/// parabolic interpolation.
+/// Frames without such a minimum are unvoiced, `None`.
+#[allow(clippy::too_many_arguments)]
+pub fn yin(
+    x: &ArrayView1<f64>,
+    sr: u32,
+    fmin: f64,
+    fmax: f64,
+    frame_length: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    threshold: f64,
+) -> Vec<Option<f64>> {
+    let (min_period, max_period) = period_range(sr, fmin, fmax, frame_length);
+    let frames = features::frame(x, frame_length, hop_length, pad_mode);
+
+    frames
+        .outer_iter()
+        .map(|frame| {
+            let cmndf = cumulative_mean_normalized_difference(&frame, max_period);
+            let is_minimum = |lag: usize| {
+                cmndf[lag] < threshold
+                    && cmndf[lag] <= cmndf[lag - 1]
+                    && (lag == max_period || cmndf[lag] <= cmndf[lag + 1])
+            };
+            (min_period..=max_period)
+                .find(|lag| is_minimum(*lag))
+                .map(|lag| sr as f64 / parabolic_lag(&cmndf, lag))
+        })
+        .collect()
+}
+
+/// CDF of the beta distribution with integer parameters `a` and `b` at `x`, a binomial tail.
+fn beta_cdf(x: f64, a: usize, b: usize) -> f64 {
+    let n = a + b - 1;
+    let mut binomial = 1.;
+    let mut cdf = 0.;
+    for j in 0..=n {
+        if j > 0 {
+            binomial *= (n + 1 - j) as f64 / j as f64;
+        }
+        if j >= a {
+            cdf += binomial * x.powi(j as i32) * (1. - x).powi((n - j) as i32);
+        }
+    }
+    cdf
+}
+
+/// Settings of `pyin` with the defaults of librosa.
+pub struct PyinParams {
+    /// Number of YIN thresholds the probabilities are averaged over.
+    pub n_thresholds: usize,
+    /// Parameters of the beta prior over the thresholds.
+    pub beta: (usize, usize),
+    /// Parameter of the Boltzmann prior favouring the shortest periods below a threshold.
+    pub boltzmann: f64,
+    /// Probability given to the global minimum when no trough is below a threshold.
+    pub no_trough_prob: f64,
+    /// Width of the pitch states in semitones.
+    pub resolution: f64,
+    /// Fastest pitch change the HMM allows, in semitones per second.
+    pub max_transition_rate: f64,
+    /// Probability of switching between voiced and unvoiced from one frame to the next.
+    pub switch_prob: f64,
+}
+
+impl Default for PyinParams {
+    fn default() -> Self {
+        PyinParams {
+            n_thresholds: 100,
+            beta: (2, 18),
+            boltzmann: 2.,
+            no_trough_prob: 0.01,
+            resolution: 0.1,
+            max_transition_rate: 35.92,
+            switch_prob: 0.01,
+        }
+    }
+}
+
+/// Probabilistic YIN (Mauch and Dixon, 2014), as in librosa. The troughs of the cumulative mean
+/// normalized difference of each frame are pitch candidates, weighted by how many of the YIN
+/// thresholds drawn from a beta prior they pass. The candidates are binned into pitch states of
+/// `resolution` semitones from `fmin`, each with an unvoiced twin, and the most likely sequence
+/// of states is decoded with an HMM whose pitch can move by `max_transition_rate` semitones per
+/// second and which switches between voiced and unvoiced with `switch_prob`.
+/// Returns the f0 of each frame, `None` where unvoiced, and its voiced probability.
+#[allow(clippy::too_many_arguments)]
+pub fn pyin(
+    x: &ArrayView1<f64>,
+    sr: u32,
+    fmin: f64,
+    fmax: f64,
+    frame_length: usize,
+    hop_length: usize,
+    pad_mode: &PadMode,
+    params: &PyinParams,
+) -> (Vec<Option<f64>>, Vec<f64>) {
+    let (min_period, max_period) = period_range(sr, fmin, fmax, frame_length);
+    let frames = features::frame(x, frame_length, hop_length, pad_mode);
+
+    let n_thresholds = params.n_thresholds;
+    let thresholds: Vec<f64> = (0..=n_thresholds)
+        .map(|k| k as f64 / n_thresholds as f64)
+        .collect();
+    let beta_probs: Vec<f64> = thresholds
+        .windows(2)
+        .map(|t| {
+            beta_cdf(t[1], params.beta.0, params.beta.1)
+                - beta_cdf(t[0], params.beta.0, params.beta.1)
+        })
+        .collect();
+    let boltzmann = |position: usize, n: usize| {
+        let lambda = params.boltzmann;
+        (1. - (-lambda).exp()) * (-lambda * position as f64).exp()
+            / (1. - (-lambda * n as f64).exp())
+    };
+
+    let bins_per_octave = 12. / params.resolution;
+    let n_pitch = (bins_per_octave * (fmax / fmin).log2()).floor() as usize + 1;
+    let freqs: Vec<f64> = (0..n_pitch)
+        .map(|b| fmin * 2_f64.powf(b as f64 / bins_per_octave))
+        .collect();
+
+    // Observation probabilities of the voiced states followed by the unvoiced ones.
+    let mut observations = Array2::<f64>::zeros((frames.nrows(), 2 * n_pitch));
+    let mut voiced_prob = Vec::with_capacity(frames.nrows());
+    for (frame, mut observation) in frames.outer_iter().zip(observations.outer_iter_mut()) {
+        let cmndf = cumulative_mean_normalized_difference(&frame, max_period);
+        let y = &cmndf[min_period..=max_period];
+        let troughs: Vec<usize> = (0..y.len() - 1)
+            .filter(|k| {
+                if *k == 0 {
+                    y[0] < y[1]
+                } else {
+                    y[*k] < y[k - 1] && y[*k] <= y[k + 1]
+                }
+            })
+            .collect();
+
+        let mut probs = vec![0.; troughs.len()];
+        for (threshold, beta_prob) in thresholds[1..].iter().zip(&beta_probs) {
+            let below: Vec<usize> = (0..troughs.len())
+                .filter(|i| y[troughs[*i]] < *threshold)
+                .collect();
+            for (position, i) in below.iter().enumerate() {
+                probs[*i] += boltzmann(position, below.len()) * beta_prob;
+            }
+        }
+        if let Some(global_min) = (0..troughs.len()).min_by(|a, b| {
+            y[troughs[*a]]
+                .partial_cmp(&y[troughs[*b]])
+                .expect("cannot compare NaN")
+        }) {
+            let n_above = thresholds[1..]
+                .iter()
+                .filter(|t| y[troughs[global_min]] >= **t)
+                .count();
+            probs[global_min] += params.no_trough_prob * beta_probs[..n_above].iter().sum::<f64>();
+        }
+
+        for (trough, prob) in troughs.iter().zip(probs) {
+            let f0 = sr as f64 / parabolic_lag(&cmndf, min_period + trough);
+            let bin = (bins_per_octave * (f0 / fmin).log2())
+                .round()
+                .clamp(0., (n_pitch - 1) as f64);
+            observation[bin as usize] += prob;
+        }
+        let voiced = observation.slice(s![..n_pitch]).sum().clamp(0., 1.);
+        observation
+            .slice_mut(s![n_pitch..])
+            .fill((1. - voiced) / n_pitch as f64);
+        voiced_prob.push(voiced);
+    }
+
+    let max_semitones = (params.max_transition_rate * 12. * hop_length as f64 / sr as f64).round();
+    let half_width = (max_semitones / params.resolution).round() as usize / 2;
+    let states = viterbi_pitch(
+        &observations.view(),
+        n_pitch,
+        half_width,
+        params.switch_prob,
+    );
+
+    let f0 = states
+        .iter()
+        .map(|state| {
+            if *state < n_pitch {
+                Some(freqs[*state])
+            } else {
+                None
+            }
+        })
+        .collect();
+    (f0, voiced_prob)
+}
+
+/// Most likely state sequence of the pYIN HMM given the (n_frames, 2 * n_pitch) observation
+/// probabilities, with voiced pitch states followed by unvoiced ones. Between frames the pitch
+/// moves by at most `half_width` states with triangular weights, in either voicing, and the
+/// voicing switches with `switch_prob`. The band structure keeps the decoding linear in the
+/// number of states, where a dense transition matrix would be quadratic.
+fn viterbi_pitch(
+    observations: &ArrayView2<f64>,
+    n_pitch: usize,
+    half_width: usize,
+    switch_prob: f64,
+) -> Vec<usize> {
+    let (n_frames, n_states) = observations.dim();
+    if n_frames == 0 {
+        return vec![];
+    }
+    let log_obs = observations.mapv(|p| (p + f64::MIN_POSITIVE).ln());
+    let log_switch = [
+        [(1. - switch_prob).ln(), switch_prob.ln()],
+        [switch_prob.ln(), (1. - switch_prob).ln()],
+    ];
+
+    // Triangular band of each source pitch, normalised over the pitches it can reach.
+    let triangle = |offset: usize| (half_width + 1 - offset) as f64;
+    let log_norm: Vec<f64> = (0..n_pitch)
+        .map(|i| {
+            let (lo, hi) = (
+                i.saturating_sub(half_width),
+                usize::min(i + half_width, n_pitch - 1),
+            );
+            (lo..=hi).map(|j| triangle(i.abs_diff(j))).sum::<f64>().ln()
+        })
+        .collect();
+    let log_local = |i: usize, j: usize| triangle(i.abs_diff(j)).ln() - log_norm[i];
+
+    let mut delta: Vec<f64> = log_obs
+        .row(0)
+        .iter()
+        .map(|p| p - (n_states as f64).ln())
+        .collect();
+    let mut backpointers = Array2::<u32>::zeros((n_frames, n_states));
+    for t in 1..n_frames {
+        let mut next = vec![f64::NEG_INFINITY; n_states];
+        for to in 0..n_states {
+            let (to_voicing, j) = (to / n_pitch, to % n_pitch);
+            let (lo, hi) = (
+                j.saturating_sub(half_width),
+                usize::min(j + half_width, n_pitch - 1),
+            );
+            let mut best = (f64::NEG_INFINITY, 0);
+            for (from_voicing, switch) in log_switch.iter().enumerate() {
+                for i in lo..=hi {
+                    let from = from_voicing * n_pitch + i;
+                    let score = delta[from] + switch[to_voicing] + log_local(i, j);
+                    if score > best.0 {
+                        best = (score, from);
+                    }
+                }
+            }
+            next[to] = best.0 + log_obs[[t, to]];
+            backpointers[[t, to]] = best.1 as u32;
+        }
+        delta = next;
+    }
+
+    let mut state = (0..n_states)
+        .max_by(|a, b| {
+            delta[*a]
+                .partial_cmp(&delta[*b])
+                .expect("cannot compare NaN")
+        })
+        .expect("cannot decode without states");
+    let mut states = vec![state; n_frames];
+    for t in (1..n_frames).rev() {
+        state = backpointers[[t, state]] as usize;
+        states[t - 1] = state;
+    }
+    states
+}
+
+#[cfg(test)]
+mod test_pitch {
+    use super::*;
+    use ndarray::Array1;
+
+    #[test]
+    fn test_yin_sine() {
+        let sr = 22050;
+        let x = crate::synth::tone(220., sr, sr as usize, 0.5);
+        let f0 = yin(
+            &x.view(),
+            sr,
+            65.,
+            2093.,
+            2048,
+            512,
+            &PadMode::Constant,
+            0.1,
+        );
+        // skip the frames overlapping the zero padding at both ends
+        for f in &f0[2..f0.len() - 2] {
+            let f = f.expect("a sine is voiced");
+            assert!((f - 220.).abs() < 0.5, "{}", f);
+        }
+
+        let silence = Array1::<f64>::zeros(4096);
+        assert!(yin(
+            &silence.view(),
+            sr,
+            65.,
+            2093.,
+            2048,
+            512,
+            &PadMode::Constant,
+            0.1
+        )
+        .iter()
+        .all(|f| f.is_none()));
+    }
+
+    #[test]
+    fn test_pyin_voicing() {
+        let sr = 22050;
+        let mut x = Array1::<f64>::zeros(sr as usize);
+        x.slice_mut(s![..11025])
+            .assign(&crate::synth::tone(220., sr, 11025, 0.5));
+        let (f0, voiced_prob) = pyin(
+            &x.view(),
+            sr,
+            65.,
+            2093.,
+            2048,
+            512,
+            &PadMode::Constant,
+            &PyinParams::default(),
+        );
+
+        for t in 2..18 {
+            let f = f0[t].expect("a sine is voiced");
+            // within a pitch state of a tenth of a semitone
+            assert!((12. * (f / 220.).log2()).abs() < 0.1, "{}", f);
+            assert!(voiced_prob[t] > 0.5);
+        }
+        assert!(f0[26..].iter().all(|f| f.is_none()));
+    }
+}
@@ -1,12 +1,19 @@
 pub use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-pub use cpal::{BufferSize, Sample, SampleRate, StreamConfig};
+pub use cpal::{BufferSize, Sample, SampleRate, StreamConfig, SupportedBufferSize};
 pub use ndarray::ArrayView2;
 
-pub fn play(arr: &ArrayView2<f64>, sr: u32) {
+use ndarray::{Array2, CowArray, Ix2};
+
+use crate::misc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Interleave the (channels, n) `arr` frame by frame, the sample layout output devices expect.
+pub fn interleave(arr: &ArrayView2<f64>) -> Vec<f32> {
     let channels = arr.nrows();
     let samples = arr.ncols();
 
-    // convert to interleaved
     let mut data_interleaved = Vec::with_capacity(channels * samples);
     for i in 0..samples {
         for ch in 0..channels {
@@ -14,10 +21,136 @@ pub fn play(arr: &ArrayView2<f64>, sr: u32) {
         }
     }
 
+    data_interleaved
+}
+
+/// Fill a device buffer from `next_sample`, with silence once it runs out.
+/// Returns the number of samples taken from `next_sample`.
+fn write_data<T: Sample>(output: &mut [T], next_sample: &mut dyn FnMut() -> Option<f32>) -> usize {
+    let mut written = 0;
+    for sample in output.iter_mut() {
+        *sample = match next_sample() {
+            Some(value) => {
+                written += 1;
+                Sample::from(&value)
+            }
+            None => Sample::from(&0_f32),
+        };
+    }
+    written
+}
+
+/// Downmix the (channels, n) `arr` for a device with `device_channels` outputs: to mono by averaging,
+/// or 5.1 (L, R, C, LFE, Ls, Rs) to stereo with the ITU-R BS.775 coefficients, dropping the LFE.
+/// Audio with no more channels than the device is kept as is; other layouts are an error.
+pub fn fit_channels<'a>(
+    arr: &ArrayView2<'a, f64>,
+    device_channels: usize,
+) -> CowArray<'a, f64, Ix2> {
+    let channels = arr.nrows();
+    if channels <= device_channels {
+        return CowArray::from(*arr);
+    }
+    match (channels, device_channels) {
+        (_, 1) => CowArray::from(misc::to_mono_ndarray(arr)),
+        (6, 2) => {
+            let mix = std::f64::consts::FRAC_1_SQRT_2;
+            let mut stereo = Array2::<f64>::zeros((2, arr.ncols()));
+            for (out, front, surround) in [(0, 0, 4), (1, 1, 5)] {
+                let mut row = stereo.row_mut(out);
+                row.assign(&arr.row(front));
+                row.scaled_add(mix, &arr.row(2));
+                row.scaled_add(mix, &arr.row(surround));
+            }
+            CowArray::from(stereo)
+        }
+        _ => panic!(
+            "cannot play {} channels on an output device with {}, downmix the audio first",
+            channels, device_channels
+        ),
+    }
+}
+
+/// The samples of the (channels, n) `arr` as they are sent to a device with `device_channels`
+/// outputs: downmixed by `fit_channels`, then interleaved. Returns them with the number of channels played.
+pub fn prepare(arr: &ArrayView2<f64>, device_channels: usize) -> (Vec<f32>, usize) {
+    let arr = fit_channels(arr, device_channels);
+    (interleave(&arr.view()), arr.nrows())
+}
+
+/// Number of output channels of the default output device, without opening a stream.
+/// `None` without an output device, e.g. on a headless machine.
+pub fn default_output_channels() -> Option<usize> {
+    let device = cpal::default_host().default_output_device()?;
+    let config = device.default_output_config().ok()?;
+    Some(config.channels() as usize)
+}
+
+/// Buffer size of the output stream: `Fixed` with the `requested` number of frames clamped to the
+/// range the device supports, or the device default without a request, or when the device does
+/// not report a range.
+pub fn buffer_size(requested: Option<u32>, supported: &SupportedBufferSize) -> BufferSize {
+    match (requested, supported) {
+        (Some(frames), SupportedBufferSize::Range { min, max }) => {
+            BufferSize::Fixed(frames.clamp(*min, *max))
+        }
+        _ => BufferSize::Default,
+    }
+}
+
+/// Playback progress shared with the output callback.
+struct Progress {
+    /// Samples handed to the device so far.
+    written: AtomicUsize,
+    /// Delay until the end of the last buffer is heard: its duration plus the device latency.
+    drain_nanos: AtomicU64,
+}
+
+/// Playback stopped before every sample was handed to the device, e.g. when the stream stalled.
+#[derive(Debug, PartialEq)]
+pub struct Timeout {
+    /// Samples handed to the device before the stream was stopped.
+    pub written: usize,
+    /// Samples to play.
+    pub total: usize,
+}
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "playback did not finish in time, stopped the output stream after {} of {} samples",
+            self.written, self.total
+        )
+    }
+}
+
+/// `Err` with the progress when fewer than `total` samples were handed to the device.
+fn check_finished(written: usize, total: usize) -> Result<(), Timeout> {
+    if written < total {
+        Err(Timeout { written, total })
+    } else {
+        Ok(())
+    }
+}
+
+/// Open a stream on the default output device playing the (channels, n) `arr`, with buffers of
+/// `buffer_frames` frames if given, see `buffer_size`.
+/// Returns the stream, which stops when dropped, with the number of samples to play and the progress.
+fn start_stream(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    buffer_frames: Option<u32>,
+) -> (cpal::Stream, usize, Arc<Progress>) {
     let host = cpal::default_host();
     let device = host
         .default_output_device()
         .expect("no output device available");
+    let device_config = device
+        .default_output_config()
+        .expect("cannot get the output device configuration");
+    let (data_interleaved, channels) = prepare(arr, device_config.channels() as usize);
+    let total = data_interleaved.len();
     //let mut supported_configs_range = device.supported_output_configs()
     //    .expect("error while querying configs");
     //let supported_config = supported_configs_range.next()
@@ -27,43 +160,91 @@ pub fn play(arr: &ArrayView2<f64>, sr: u32) {
     let config = StreamConfig {
         channels: channels as u16,
         sample_rate: SampleRate(sr), // Audio device default sample rate is set to 192000
-        buffer_size: BufferSize::Default,
+        buffer_size: buffer_size(buffer_frames, device_config.buffer_size()),
     };
 
     let err_fn = |err| eprintln!("an error occurred on the output audio stream: {}", err);
 
     let mut data_interleaved_iter = data_interleaved.into_iter();
-    let mut next_value = move || {
-        data_interleaved_iter
-            .next()
-            .expect("cannot get next iter value")
-    };
+    let mut next_value = move || data_interleaved_iter.next();
+
+    let progress = Arc::new(Progress {
+        written: AtomicUsize::new(0),
+        drain_nanos: AtomicU64::new(0),
+    });
+    let callback_progress = Arc::clone(&progress);
 
     let stream = device
         .build_output_stream(
             &config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                write_data(data, channels, &mut next_value)
+            move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                let written = write_data(data, &mut next_value);
+                let timestamp = info.timestamp();
+                let latency = timestamp
+                    .playback
+                    .duration_since(&timestamp.callback)
+                    .unwrap_or_default();
+                let buffer =
+                    Duration::from_secs_f64(data.len() as f64 / (channels as f64 * sr as f64));
+                callback_progress
+                    .drain_nanos
+                    .store((latency + buffer).as_nanos() as u64, Ordering::Relaxed);
+                callback_progress
+                    .written
+                    .fetch_add(written, Ordering::Release);
             },
             err_fn,
         )
         .unwrap();
 
-    fn write_data<T: Sample>(
-        output: &mut [T],
-        channels: usize,
-        next_sample: &mut dyn FnMut() -> f32,
-    ) {
-        for frame in output.chunks_mut(channels) {
-            for sample in frame.iter_mut() {
-                let value: T = Sample::from(&next_sample());
-                *sample = value
-            }
+    stream.play().unwrap();
+    (stream, total, progress)
+}
+
+/// Prepare the (channels, n) `arr` as `play` would for a device with `device_channels` outputs,
+/// without opening a stream, to check the input where no audio hardware is available.
+/// Returns the (frames, channels) shape of the samples that would be played.
+pub fn dry_run(arr: &ArrayView2<f64>, device_channels: usize) -> (usize, usize) {
+    let (data_interleaved, channels) = prepare(arr, device_channels);
+    (data_interleaved.len() / channels.max(1), channels)
+}
+
+/// Play the (channels, n) `arr` on the default output device for five seconds.
+/// Returns `Err` when the audio was cut off, because it is longer or the stream stalled.
+pub fn play(arr: &ArrayView2<f64>, sr: u32, buffer_frames: Option<u32>) -> Result<(), Timeout> {
+    let (_stream, total, progress) = start_stream(arr, sr, buffer_frames);
+    std::thread::sleep(Duration::from_millis(5000));
+    check_finished(progress.written.load(Ordering::Acquire), total)
+}
+
+/// Play the (channels, n) `arr` on the default output device and return once it has been heard.
+/// Waits until the output callback has taken every sample, then for the last buffer to drain
+/// through the device latency. Gives up a second after the audio should have ended, e.g. when
+/// the stream stalls, with the progress as `Err`.
+pub fn play_blocking(
+    arr: &ArrayView2<f64>,
+    sr: u32,
+    buffer_frames: Option<u32>,
+) -> Result<(), Timeout> {
+    let duration = Duration::from_secs_f64(arr.ncols() as f64 / sr as f64);
+    let deadline = Instant::now() + duration + Duration::from_secs(1);
+
+    let (stream, total, progress) = start_stream(arr, sr, buffer_frames);
+    loop {
+        let written = progress.written.load(Ordering::Acquire);
+        if written >= total {
+            break;
         }
+        if Instant::now() >= deadline {
+            return check_finished(written, total);
+        }
+        std::thread::sleep(Duration::from_millis(5));
     }
-
-    stream.play().unwrap();
-    std::thread::sleep(std::time::Duration::from_millis(5000));
+    std::thread::sleep(Duration::from_nanos(
+        progress.drain_nanos.load(Ordering::Relaxed),
+    ));
+    drop(stream);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -77,14 +258,71 @@ mod test_play {
     fn test_play() {
         let fname = "../../test_files/mono.wav";
         let path = Path::new(fname);
-        let filetype = Path::extension(path)
-            .expect("couldn't extract the file extension")
-            .to_str()
-            .expect("cannot convert from &OsStr to &str");
-        let decoded_arr = decode_symphonia::load(path, false, 0., NA_REAL, filetype);
+        let filetype = Path::extension(path).and_then(|ext| ext.to_str());
+        let decoded_arr = decode_symphonia::load(
+            path,
+            false,
+            0.,
+            NA_REAL,
+            None,
+            false,
+            &decode_symphonia::Norm::FullScale,
+            filetype,
+        );
         let sr = decode_symphonia::get_samplerate(path, filetype);
         println!("{:?}", decoded_arr);
         println!("{:?}", sr);
-        play(&decoded_arr.view(), sr);
+        assert_eq!(dry_run(&decoded_arr.view(), 2), (decoded_arr.ncols(), 1));
+        // Downmixed to the channels of the device, as in playback.
+        let stereo = ndarray::stack![ndarray::Axis(0), decoded_arr.row(0), decoded_arr.row(0)];
+        assert_eq!(dry_run(&stereo.view(), 1), (decoded_arr.ncols(), 1));
+    }
+
+    #[test]
+    fn test_check_finished() {
+        assert_eq!(check_finished(6, 6), Ok(()));
+        let timeout = check_finished(4, 6).unwrap_err();
+        assert_eq!(
+            timeout,
+            Timeout {
+                written: 4,
+                total: 6
+            }
+        );
+        assert!(timeout.to_string().contains("after 4 of 6 samples"));
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let range = SupportedBufferSize::Range { min: 64, max: 4096 };
+        assert_eq!(buffer_size(Some(256), &range), BufferSize::Fixed(256));
+        assert_eq!(buffer_size(Some(16), &range), BufferSize::Fixed(64));
+        assert_eq!(buffer_size(Some(8192), &range), BufferSize::Fixed(4096));
+        assert_eq!(buffer_size(None, &range), BufferSize::Default);
+        assert_eq!(
+            buffer_size(Some(256), &SupportedBufferSize::Unknown),
+            BufferSize::Default
+        );
+    }
+
+    #[test]
+    fn test_interleave() {
+        let arr = ndarray::array![[1., 2., 3.], [-1., -2., -3.]];
+        assert_eq!(interleave(&arr.view()), vec![1., -1., 2., -2., 3., -3.]);
+
+        let mut samples = interleave(&arr.view()).into_iter();
+        let mut output = [9_f32; 8];
+        assert_eq!(write_data(&mut output, &mut || samples.next()), 6);
+        assert_eq!(output, [1., -1., 2., -2., 3., -3., 0., 0.]);
+    }
+
+    #[test]
+    fn test_fit_channels() {
+        let surround = ndarray::array![[1.], [2.], [3.], [4.], [5.], [6.]];
+        assert_eq!(fit_channels(&surround.view(), 8), surround);
+        assert_eq!(fit_channels(&surround.view(), 1), ndarray::array![[3.5]]);
+        let stereo = fit_channels(&surround.view(), 2);
+        let mix = std::f64::consts::FRAC_1_SQRT_2;
+        assert_eq!(stereo, ndarray::array![[1. + 8. * mix], [2. + 9. * mix]]);
     }
 }
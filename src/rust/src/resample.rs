@@ -0,0 +1,191 @@
+use std::f64::consts::PI;
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis};
+
+/// Resampling method, trading quality for speed.
+/// The windowed-sinc filters follow resampy's Kaiser presets: a longer filter with a narrower
+/// transition band keeps more of the passband and suppresses more aliasing, at a cost
+/// proportional to its length.
+pub enum ResType {
+    /// 64 zero crossings, Kaiser beta 14.77, passband up to 94.8% of the Nyquist frequency.
+    KaiserBest,
+    /// 16 zero crossings, Kaiser beta 8.56, passband up to 85% of the Nyquist frequency.
+    /// About four times faster than `KaiserBest`.
+    KaiserFast,
+    /// Linear interpolation between neighbouring samples. Fastest, but without any low-pass
+    /// filtering, so downsampling aliases.
+    Linear,
+}
+
+impl ResType {
+    pub fn from_name(name: &str) -> ResType {
+        match name {
+            "kaiser_best" => ResType::KaiserBest,
+            "kaiser_fast" => ResType::KaiserFast,
+            "linear" => ResType::Linear,
+            _ => panic!("res_type must be one of \"kaiser_best\", \"kaiser_fast\" or \"linear\""),
+        }
+    }
+}
+
+// Number of filter table entries per zero crossing; the filter is linearly interpolated in between.
+const PRECISION: usize = 512;
+
+/// Zeroth-order modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let mut k = 1.;
+    while term > sum * 1e-17 {
+        term *= (x / (2. * k)).powi(2);
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+/// Right half of a Kaiser-windowed sinc low-pass filter, sampled `PRECISION` times per zero crossing.
+fn sinc_table(num_zeros: usize, beta: f64, rolloff: f64) -> Vec<f64> {
+    let n = num_zeros * PRECISION;
+    (0..=n + 1)
+        .map(|i| {
+            let u = i as f64 / PRECISION as f64;
+            let sinc = if i == 0 {
+                1.
+            } else {
+                (PI * rolloff * u).sin() / (PI * rolloff * u)
+            };
+            let r = f64::min(i as f64 / n as f64, 1.);
+            let window = bessel_i0(beta * (1. - r * r).sqrt()) / bessel_i0(beta);
+            rolloff * sinc * window
+        })
+        .collect()
+}
+
+/// Band-limited interpolation of `x` at `ratio` output samples per input sample.
+/// When downsampling, the filter is stretched so its cutoff falls below the new Nyquist frequency.
+fn resample_sinc(x: &ArrayView1<f64>, ratio: f64, table: &[f64], num_zeros: usize) -> Array1<f64> {
+    let n_out = (x.len() as f64 * ratio).ceil() as usize;
+    let scale = f64::min(1., ratio);
+    let half_width = num_zeros as f64 / scale;
+
+    Array1::from_shape_fn(n_out, |n| {
+        let t = n as f64 / ratio;
+        let lo = f64::max((t - half_width).ceil(), 0.) as usize;
+        let hi = usize::min((t + half_width).floor() as usize, x.len() - 1);
+
+        let mut acc = 0.;
+        for k in lo..=hi {
+            let pos = (t - k as f64).abs() * scale * PRECISION as f64;
+            let idx = pos as usize;
+            if idx + 1 >= table.len() {
+                continue;
+            }
+            let frac = pos - idx as f64;
+            acc += x[k] * (table[idx] * (1. - frac) + table[idx + 1] * frac);
+        }
+        acc * scale
+    })
+}
+
+/// Linear interpolation of `x` at `ratio` output samples per input sample.
+fn resample_linear(x: &ArrayView1<f64>, ratio: f64) -> Array1<f64> {
+    let n_out = (x.len() as f64 * ratio).ceil() as usize;
+    let last = x.len() - 1;
+
+    Array1::from_shape_fn(n_out, |n| {
+        let t = n as f64 / ratio;
+        let k = usize::min(t.floor() as usize, last);
+        let frac = t - k as f64;
+        if k == last {
+            x[last]
+        } else {
+            x[k] * (1. - frac) + x[k + 1] * frac
+        }
+    })
+}
+
+/// Resample a (channels, nsamples) array from `orig_sr` to `target_sr`.
+/// Returns a (channels, ceil(nsamples * target_sr / orig_sr)) array.
+/// The sinc methods low-pass filter at the lower of the two Nyquist frequencies, so content above
+/// the new Nyquist frequency is attenuated rather than aliased when downsampling.
+pub fn resample(
+    arr: &ArrayView2<f64>,
+    orig_sr: u32,
+    target_sr: u32,
+    res_type: &ResType,
+) -> Array2<f64> {
+    if arr.ncols() == 0 || orig_sr == target_sr {
+        return arr.to_owned();
+    }
+    let ratio = target_sr as f64 / orig_sr as f64;
+
+    let table = match res_type {
+        ResType::KaiserBest => Some((64, sinc_table(64, 14.769656459379492, 0.9475937167399596))),
+        ResType::KaiserFast => Some((16, sinc_table(16, 8.555504641634386, 0.85))),
+        ResType::Linear => None,
+    };
+
+    let channels: Vec<Array1<f64>> = arr
+        .outer_iter()
+        .map(|channel| match &table {
+            Some((num_zeros, table)) => resample_sinc(&channel, ratio, table, *num_zeros),
+            None => resample_linear(&channel, ratio),
+        })
+        .collect();
+    let views: Vec<ArrayView1<f64>> = channels.iter().map(|c| c.view()).collect();
+
+    ndarray::stack(Axis(0), &views).expect("cannot stack channels")
+}
+
+#[cfg(test)]
+mod test_resample {
+    use super::*;
+
+    #[test]
+    fn test_resample_sweep() {
+        // Upsample a 100 Hz to 3 kHz sweep from 8 kHz to 22.05 kHz and compare it with the
+        // analytic sweep, away from the edges.
+        let sweep = |t: f64| (2. * PI * (100. * t + 2900. * t * t / 2.)).sin();
+        let (orig_sr, target_sr) = (8000, 22050);
+        let x = Array2::from_shape_fn((1, orig_sr), |(_, i)| sweep(i as f64 / orig_sr as f64));
+
+        let max_error = |res_type: ResType| {
+            let y = resample(&x.view(), orig_sr as u32, target_sr, &res_type);
+            assert_eq!(y.ncols(), target_sr as usize);
+            (2000..target_sr as usize - 2000)
+                .map(|n| (y[[0, n]] - sweep(n as f64 / target_sr as f64)).abs())
+                .fold(0., f64::max)
+        };
+        let (best, fast, linear) = (
+            max_error(ResType::KaiserBest),
+            max_error(ResType::KaiserFast),
+            max_error(ResType::Linear),
+        );
+
+        assert!(best < 1e-3, "kaiser_best error {}", best);
+        assert!(fast < 2e-2, "kaiser_fast error {}", fast);
+        assert!(best < fast && fast < linear);
+    }
+
+    #[test]
+    fn test_resample_anti_aliasing() {
+        // A 6 kHz sine is above the 4 kHz Nyquist frequency of 8 kHz audio. Without a low-pass
+        // filter it would alias to 2 kHz; the sinc filters must remove it instead.
+        let (orig_sr, target_sr) = (16000, 8000);
+        let x = crate::synth::tone(6000., orig_sr as u32, orig_sr, 1.).insert_axis(Axis(0));
+
+        let rms = |res_type: ResType| {
+            let y = resample(&x.view(), orig_sr as u32, target_sr, &res_type);
+            let interior = y.slice(ndarray::s![0, 1000..target_sr as usize - 1000]);
+            (interior.mapv(|v| v * v).sum() / interior.len() as f64).sqrt()
+        };
+
+        let best = rms(ResType::KaiserBest);
+        let fast = rms(ResType::KaiserFast);
+        assert!(best < 1e-4, "kaiser_best leaks {}", best);
+        assert!(fast < 1e-3, "kaiser_fast leaks {}", fast);
+        // linear interpolation does not filter, so the sine folds into the passband
+        assert!(rms(ResType::Linear) > 0.1);
+    }
+}
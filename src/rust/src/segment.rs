@@ -0,0 +1,201 @@
+use ndarray::{Array1, Array2, ArrayView2};
+
+use crate::sequence::{self, Metric};
+use crate::util;
+
+pub enum RecurrenceMode {
+    Connectivity,
+    Distance,
+    Affinity,
+}
+
+/// k-nearest-neighbor recurrence matrix between the frames (rows) of `data`.
+/// Entry (i, j) is non-zero when frame j is one of the `k` nearest neighbors of frame i; a frame is never its own neighbor.
+/// `k` defaults to `2 * ceil(sqrt(t - 1))`. Returns a (t, t) array.
+pub fn recurrence_matrix(
+    data: &ArrayView2<f64>,
+    k: Option<usize>,
+    metric: &Metric,
+    mode: RecurrenceMode,
+) -> Array2<f64> {
+    let n_frames = data.nrows();
+    if n_frames < 2 {
+        panic!("data must have at least two frames");
+    }
+    let k = usize::min(
+        k.unwrap_or(2 * ((n_frames - 1) as f64).sqrt().ceil() as usize),
+        n_frames - 1,
+    );
+    if k == 0 {
+        panic!("k must be positive");
+    }
+
+    let distances = sequence::pairwise_distances(data, data, metric);
+    let mut links = Array2::<bool>::from_elem((n_frames, n_frames), false);
+    let mut kth_distances = Vec::with_capacity(n_frames);
+
+    for i in 0..n_frames {
+        let mut neighbors: Vec<usize> = (0..n_frames).filter(|j| *j != i).collect();
+        neighbors.sort_by(|a, b| {
+            distances[[i, *a]]
+                .partial_cmp(&distances[[i, *b]])
+                .expect("cannot compare NaN")
+        });
+        neighbors.truncate(k);
+        kth_distances.push(distances[[i, neighbors[k - 1]]]);
+        for j in neighbors {
+            links[[i, j]] = true;
+        }
+    }
+
+    // Affinity uses the median distance to the k-th neighbor as its bandwidth.
+    let bandwidth = match util::median(kth_distances) {
+        b if b > 0. => b,
+        _ => 1.,
+    };
+
+    Array2::from_shape_fn((n_frames, n_frames), |(i, j)| {
+        if !links[[i, j]] {
+            return 0.;
+        }
+        match mode {
+            RecurrenceMode::Connectivity => 1.,
+            RecurrenceMode::Distance => distances[[i, j]],
+            RecurrenceMode::Affinity => (-distances[[i, j]] / bandwidth).exp(),
+        }
+    })
+}
+
+/// Partition the frames (rows) of `data` into `k` contiguous segments by agglomerative clustering.
+/// Starting from one segment per frame, the adjacent pair with the smallest Ward linkage is merged until `k` remain.
+/// Returns the 0-based start frame of each segment.
+pub fn agglomerative(data: &ArrayView2<f64>, k: usize) -> Vec<usize> {
+    let n_frames = data.nrows();
+    if k == 0 || k > n_frames {
+        panic!("k must be between 1 and the number of frames");
+    }
+
+    let mut starts: Vec<usize> = (0..n_frames).collect();
+    let mut sizes: Vec<f64> = vec![1.; n_frames];
+    let mut sums: Vec<Array1<f64>> = data.outer_iter().map(|row| row.to_owned()).collect();
+
+    // Increase in within-segment variance caused by merging segment i with segment i + 1.
+    let ward = |i: usize, sizes: &[f64], sums: &[Array1<f64>]| {
+        let diff = &sums[i] / sizes[i] - &sums[i + 1] / sizes[i + 1];
+        sizes[i] * sizes[i + 1] / (sizes[i] + sizes[i + 1]) * diff.dot(&diff)
+    };
+
+    while starts.len() > k {
+        let best = (0..starts.len() - 1)
+            .map(|i| (i, ward(i, &sizes, &sums)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).expect("cannot compare NaN"))
+            .map(|(i, _)| i)
+            .expect("cannot find segments to merge");
+
+        sizes[best] += sizes.remove(best + 1);
+        let merged = sums.remove(best + 1);
+        sums[best] += &merged;
+        starts.remove(best + 1);
+    }
+
+    starts
+}
+
+pub enum NoveltyKernel {
+    Spectral,
+    Checkerboard,
+}
+
+/// Novelty curve over the frames (rows) of `data`, peaking where its content changes.
+/// `Spectral` is the spectral flux, the sum of the increases of every feature since the previous frame.
+/// `Checkerboard` correlates a Gaussian-tapered checkerboard kernel spanning `kernel_size` frames
+/// along the diagonal of the cosine self-similarity matrix (Foote), so it responds to changes between
+/// longer stretches, like sections, rather than to individual onsets.
+/// Returns one value per frame.
+pub fn novelty(data: &ArrayView2<f64>, kernel: &NoveltyKernel, kernel_size: usize) -> Array1<f64> {
+    let n_frames = data.nrows();
+    match kernel {
+        NoveltyKernel::Spectral => Array1::from_shape_fn(n_frames, |t| {
+            if t == 0 {
+                return 0.;
+            }
+            data.row(t)
+                .iter()
+                .zip(data.row(t - 1))
+                .map(|(now, before)| f64::max(now - before, 0.))
+                .sum()
+        }),
+        NoveltyKernel::Checkerboard => {
+            let half = (kernel_size / 2) as isize;
+            if half == 0 {
+                panic!("kernel_size must be at least 2");
+            }
+            let similarity =
+                sequence::pairwise_distances(data, data, &Metric::Cosine).mapv(|d| 1. - d);
+
+            // offsets -half..0 are the past and 0..half the future of the frame
+            let taper = |i: isize| {
+                let x = (i as f64 + 0.5) / half as f64;
+                (-2. * x * x).exp()
+            };
+            let sign = |i: isize| if i < 0 { -1. } else { 1. };
+            let weights: f64 = (-half..half)
+                .flat_map(|i| (-half..half).map(move |j| taper(i) * taper(j)))
+                .sum();
+
+            Array1::from_shape_fn(n_frames, |t| {
+                let mut acc = 0.;
+                for i in -half..half {
+                    for j in -half..half {
+                        let (a, b) = (t as isize + i, t as isize + j);
+                        if a < 0 || b < 0 || a >= n_frames as isize || b >= n_frames as isize {
+                            continue;
+                        }
+                        acc += sign(i)
+                            * sign(j)
+                            * taper(i)
+                            * taper(j)
+                            * similarity[[a as usize, b as usize]];
+                    }
+                }
+                acc / weights
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_segment {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_agglomerative_blocks() {
+        let data = array![[0.], [0.1], [0.], [5.], [5.1], [9.], [9.2], [9.1]];
+        assert_eq!(agglomerative(&data.view(), 3), vec![0, 3, 5]);
+    }
+
+    #[test]
+    fn test_novelty_peaks_at_boundary() {
+        let data = Array2::from_shape_fn(
+            (40, 2),
+            |(t, f)| if (t < 25) == (f == 0) { 1. } else { 0.1 },
+        );
+        let argmax = |x: Array1<f64>| {
+            x.iter()
+                .enumerate()
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                .unwrap()
+                .0
+        };
+
+        assert_eq!(
+            argmax(novelty(&data.view(), &NoveltyKernel::Spectral, 0)),
+            25
+        );
+        assert_eq!(
+            argmax(novelty(&data.view(), &NoveltyKernel::Checkerboard, 16)),
+            25
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use ndarray::{Array2, ArrayView1, ArrayView2};
+
+pub enum Metric {
+    Euclidean,
+    SqEuclidean,
+    Cityblock,
+    Cosine,
+}
+
+impl Metric {
+    pub fn from_name(name: &str) -> Metric {
+        match name {
+            "euclidean" => Metric::Euclidean,
+            "sqeuclidean" => Metric::SqEuclidean,
+            "cityblock" => Metric::Cityblock,
+            "cosine" => Metric::Cosine,
+            _ => panic!(
+                "metric must be one of \"euclidean\", \"sqeuclidean\", \"cityblock\" or \"cosine\""
+            ),
+        }
+    }
+
+    pub fn distance(&self, a: &ArrayView1<f64>, b: &ArrayView1<f64>) -> f64 {
+        match self {
+            Metric::Euclidean => Metric::SqEuclidean.distance(a, b).sqrt(),
+            Metric::SqEuclidean => a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum(),
+            Metric::Cityblock => a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum(),
+            Metric::Cosine => {
+                let norms = a.dot(a).sqrt() * b.dot(b).sqrt();
+                if norms == 0. {
+                    1.
+                } else {
+                    1. - a.dot(b) / norms
+                }
+            }
+        }
+    }
+}
+
+/// Distances between every row of `x` and every row of `y`. Returns a (x.nrows(), y.nrows()) array.
+pub fn pairwise_distances(
+    x: &ArrayView2<f64>,
+    y: &ArrayView2<f64>,
+    metric: &Metric,
+) -> Array2<f64> {
+    if x.ncols() != y.ncols() {
+        panic!("x and y must have the same number of features");
+    }
+
+    Array2::from_shape_fn((x.nrows(), y.nrows()), |(i, j)| {
+        metric.distance(&x.row(i), &y.row(j))
+    })
+}
+
+/// Dynamic time warping between the frames (rows) of `x` and `y`.
+/// `band` is an optional Sakoe-Chiba radius, in frames, around the diagonal joining both ends.
+/// Returns the accumulated cost matrix and the optimal path as (k, 2) frame index pairs, from start to end.
+pub fn dtw(
+    x: &ArrayView2<f64>,
+    y: &ArrayView2<f64>,
+    metric: &Metric,
+    band: Option<usize>,
+) -> (Array2<f64>, Array2<i32>) {
+    let (n, m) = (x.nrows(), y.nrows());
+    if n == 0 || m == 0 {
+        panic!("x and y must have at least one frame");
+    }
+
+    let mut cost = pairwise_distances(x, y, metric);
+    if let Some(radius) = band {
+        let slope = if m > 1 {
+            (n - 1) as f64 / (m - 1) as f64
+        } else {
+            0.
+        };
+        for ((i, j), c) in cost.indexed_iter_mut() {
+            if (i as f64 - j as f64 * slope).abs() > radius as f64 {
+                *c = f64::INFINITY;
+            }
+        }
+    }
+
+    // Accumulate in place: each cell adds the cheapest of its three predecessors.
+    for i in 0..n {
+        for j in 0..m {
+            let best = match (i, j) {
+                (0, 0) => 0.,
+                (0, _) => cost[[0, j - 1]],
+                (_, 0) => cost[[i - 1, 0]],
+                _ => cost[[i - 1, j - 1]]
+                    .min(cost[[i - 1, j]])
+                    .min(cost[[i, j - 1]]),
+            };
+            cost[[i, j]] += best;
+        }
+    }
+
+    if cost[[n - 1, m - 1]].is_infinite() {
+        panic!("no warping path fits within the band");
+    }
+
+    // Backtrack from the end, preferring the diagonal step on ties.
+    let (mut i, mut j) = (n - 1, m - 1);
+    let mut path = vec![(i, j)];
+    while (i, j) != (0, 0) {
+        (i, j) = match (i, j) {
+            (0, _) => (0, j - 1),
+            (_, 0) => (i - 1, 0),
+            _ => {
+                let diag = cost[[i - 1, j - 1]];
+                if diag <= cost[[i - 1, j]] && diag <= cost[[i, j - 1]] {
+                    (i - 1, j - 1)
+                } else if cost[[i - 1, j]] <= cost[[i, j - 1]] {
+                    (i - 1, j)
+                } else {
+                    (i, j - 1)
+                }
+            }
+        };
+        path.push((i, j));
+    }
+    path.reverse();
+
+    let path = Array2::from_shape_fn((path.len(), 2), |(k, col)| {
+        let (a, b) = path[k];
+        if col == 0 {
+            a as i32
+        } else {
+            b as i32
+        }
+    });
+
+    (cost, path)
+}
+
+#[cfg(test)]
+mod test_sequence {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_dtw_repeated_frame() {
+        // y repeats the second frame of x, so the path has to stay on x[1] for two steps.
+        let x = array![[0.], [1.], [2.]];
+        let y = array![[0.], [1.], [1.], [2.]];
+        let (cost, path) = dtw(&x.view(), &y.view(), &Metric::Euclidean, None);
+
+        assert_eq!(cost[[2, 3]], 0.);
+        assert_eq!(path, array![[0, 0], [1, 1], [1, 2], [2, 3]]);
+    }
+}
@@ -0,0 +1,292 @@
+use ndarray::{concatenate, s, Array2, Array3, ArrayView2, Axis};
+use num_complex::Complex;
+
+use crate::decode_symphonia::{AudioReader, OnError};
+use crate::features;
+
+/// STFT of a file computed a block of frames at a time, so the file never has to be held in memory.
+/// The frames are exactly those of `features::stft` over the whole file: the samples that the next
+/// block's frames share with the current one are kept in `buffer` instead of being read again.
+pub struct StftBlocks {
+    reader: AudioReader,
+    n_fft: usize,
+    hop_length: usize,
+    block_frames: usize,
+    n_frames: usize,
+    next_frame: usize,
+    // (channels, len) samples starting at sample `buffer_start` of the file. The start is
+    // negative while the buffer still holds the leading zero padding of the centered frames.
+    buffer: Array2<f64>,
+    buffer_start: i64,
+    read_pos: u64,
+}
+
+impl StftBlocks {
+    pub fn new(reader: AudioReader, n_fft: usize, hop_length: usize, block_frames: usize) -> Self {
+        let pad = n_fft / 2;
+        let n_padded = reader.n_frames as usize + 2 * pad;
+        if n_padded < n_fft {
+            panic!("n_fft is longer than the padded signal");
+        }
+        let n_frames = 1 + (n_padded - n_fft) / hop_length;
+
+        StftBlocks {
+            buffer: Array2::<f64>::zeros((reader.channels, pad)),
+            buffer_start: -(pad as i64),
+            reader,
+            n_fft,
+            hop_length,
+            block_frames,
+            n_frames,
+            next_frame: 0,
+            read_pos: 0,
+        }
+    }
+
+    /// Total number of frames over all blocks.
+    pub fn n_frames(&self) -> usize {
+        self.n_frames
+    }
+
+    /// The next complex (t, n_fft / 2 + 1, channels) block of at most `block_frames` frames,
+    /// or `None` once every frame has been returned.
+    pub fn next_block(&mut self) -> Option<Array3<Complex<f64>>> {
+        if self.next_frame >= self.n_frames {
+            return None;
+        }
+        let pad = (self.n_fft / 2) as i64;
+        let first = self.next_frame;
+        let last = usize::min(first + self.block_frames, self.n_frames);
+
+        // Extend the buffer up to the end of the last frame of the block, with samples from the
+        // file and, past its end, the trailing zero padding.
+        let needed_end = ((last - 1) * self.hop_length + self.n_fft) as i64 - pad;
+        let buffered_end = self.buffer_start + self.buffer.ncols() as i64;
+        if needed_end > buffered_end {
+            let file_end = i64::min(needed_end, self.reader.n_frames as i64) as u64;
+            let mut samples = Array2::<f64>::zeros((
+                self.reader.channels,
+                file_end.saturating_sub(self.read_pos) as usize,
+            ));
+            if samples.ncols() > 0 {
+                self.reader
+                    .read_into(self.read_pos, &mut samples.view_mut(), &OnError::Error);
+                self.read_pos = file_end;
+            }
+            let zeros = Array2::<f64>::zeros((
+                self.reader.channels,
+                (needed_end - buffered_end) as usize - samples.ncols(),
+            ));
+            self.buffer = concatenate![Axis(1), self.buffer, samples, zeros];
+        }
+
+        let channels: Vec<Array2<Complex<f64>>> = self
+            .buffer
+            .outer_iter()
+            .map(|channel| {
+                let mut frames = Array2::<f64>::zeros((last - first, self.n_fft));
+                for (k, mut row) in frames.outer_iter_mut().enumerate() {
+                    let start =
+                        (((first + k) * self.hop_length) as i64 - pad - self.buffer_start) as usize;
+                    row.assign(&channel.slice(s![start..start + self.n_fft]));
+                }
+                features::spectrum_frames(&frames.view())
+            })
+            .collect();
+        let views: Vec<ArrayView2<Complex<f64>>> = channels.iter().map(|c| c.view()).collect();
+
+        // Drop the samples that no later frame uses.
+        let keep_from = (last * self.hop_length) as i64 - pad - self.buffer_start;
+        if keep_from > 0 {
+            let keep_from = usize::min(keep_from as usize, self.buffer.ncols());
+            self.buffer = self.buffer.slice(s![.., keep_from..]).to_owned();
+            self.buffer_start += keep_from as i64;
+        }
+        self.next_frame = last;
+
+        Some(ndarray::stack(Axis(2), &views).expect("cannot stack channels"))
+    }
+}
+
+/// Sample encoding of the bytes returned by `PcmBlocks`: little-endian and interleaved, one frame
+/// of all channels after another, as `readBin` and most raw PCM consumers expect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PcmFormat {
+    S16,
+    S32,
+    F32,
+    F64,
+}
+
+impl PcmFormat {
+    pub fn from_name(name: &str) -> PcmFormat {
+        match name {
+            "s16le" => PcmFormat::S16,
+            "s32le" => PcmFormat::S32,
+            "f32le" => PcmFormat::F32,
+            "f64le" => PcmFormat::F64,
+            _ => panic!("format must be one of \"s16le\", \"s32le\", \"f32le\" or \"f64le\""),
+        }
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            PcmFormat::S16 => 2,
+            PcmFormat::S32 | PcmFormat::F32 => 4,
+            PcmFormat::F64 => 8,
+        }
+    }
+
+    /// Encode a (channels, n) block of samples at full scale. Integer formats scale by
+    /// 2^(bits - 1), the inverse of decoding, and saturate, so a 1 becomes the largest integer.
+    pub fn encode(&self, block: &ArrayView2<f64>) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(block.len() * self.bytes_per_sample());
+        for frame in block.columns() {
+            for &v in frame {
+                match self {
+                    PcmFormat::S16 => bytes.extend_from_slice(
+                        &((v * 32768.).round().clamp(-32768., 32767.) as i16).to_le_bytes(),
+                    ),
+                    PcmFormat::S32 => bytes.extend_from_slice(
+                        &((v * 2147483648.).round().clamp(-2147483648., 2147483647.) as i32)
+                            .to_le_bytes(),
+                    ),
+                    PcmFormat::F32 => bytes.extend_from_slice(&(v as f32).to_le_bytes()),
+                    PcmFormat::F64 => bytes.extend_from_slice(&v.to_le_bytes()),
+                }
+            }
+        }
+        bytes
+    }
+}
+
+/// Decoded samples of a segment of a file, pulled a given number of frames at a time.
+pub struct PcmBlocks {
+    reader: AudioReader,
+    next: u64,
+    end: u64,
+}
+
+impl PcmBlocks {
+    /// Blocks of the `n_frames` frames starting at `offset_frames`, as given by `AudioReader::segment`.
+    pub fn new(reader: AudioReader, offset_frames: u64, n_frames: usize) -> Self {
+        PcmBlocks {
+            reader,
+            next: offset_frames,
+            end: offset_frames + n_frames as u64,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.reader.channels
+    }
+
+    pub fn sr(&self) -> u32 {
+        self.reader.sr
+    }
+
+    /// Number of frames left to read.
+    pub fn remaining(&self) -> u64 {
+        self.end - self.next
+    }
+
+    /// The next (channels, n) block of at most `max_frames` frames, or `None` once the whole
+    /// segment has been returned.
+    pub fn next_frames(&mut self, max_frames: usize) -> Option<Array2<f64>> {
+        let n = u64::min(max_frames as u64, self.remaining()) as usize;
+        if n == 0 {
+            return None;
+        }
+        let mut block = Array2::<f64>::zeros((self.reader.channels, n));
+        self.reader
+            .read_into(self.next, &mut block.view_mut(), &OnError::Error);
+        self.next += n as u64;
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod test_stream {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_stream_matches_stft() {
+        let path = Path::new("../../test_files/float32.wav");
+        let full = features::stft(
+            &crate::decode_symphonia::load(
+                path,
+                false,
+                0.,
+                None,
+                None,
+                false,
+                &crate::decode_symphonia::Norm::FullScale,
+                Some("wav"),
+            )
+            .row(0),
+            256,
+            64,
+            &features::PadMode::Constant,
+        );
+
+        let reader = AudioReader::open(path, None, false, Some("wav"));
+        let mut blocks = StftBlocks::new(reader, 256, 64, 3);
+        let mut streamed = Vec::new();
+        while let Some(block) = blocks.next_block() {
+            streamed.push(block.slice_move(s![.., .., 0]));
+        }
+        let views: Vec<ArrayView2<Complex<f64>>> = streamed.iter().map(|b| b.view()).collect();
+
+        assert_eq!(blocks.n_frames(), full.nrows());
+        assert_eq!(
+            concatenate(Axis(0), &views).expect("cannot concatenate"),
+            full
+        );
+    }
+
+    #[test]
+    fn test_pcm_blocks() {
+        let path = Path::new("../../test_files/float32.wav");
+        let reader = AudioReader::open(path, None, false, Some("wav"));
+        let (offset, n) = reader.segment(0.01, Some(0.1));
+        let full = crate::decode_symphonia::load(
+            path,
+            false,
+            0.01,
+            Some(0.1),
+            None,
+            false,
+            &crate::decode_symphonia::Norm::FullScale,
+            Some("wav"),
+        );
+
+        let mut blocks = PcmBlocks::new(reader, offset, n);
+        let mut pulled = Vec::new();
+        while let Some(block) = blocks.next_frames(300) {
+            pulled.push(block);
+        }
+        assert_eq!(
+            pulled.iter().map(|b| b.ncols()).collect::<Vec<_>>(),
+            [300, 300, 200]
+        );
+        let views: Vec<ArrayView2<f64>> = pulled.iter().map(|b| b.view()).collect();
+        assert_eq!(
+            concatenate(Axis(1), &views).expect("cannot concatenate"),
+            full
+        );
+        assert_eq!(blocks.remaining(), 0);
+    }
+
+    #[test]
+    fn test_pcm_encode() {
+        let block = ndarray::array![[0.5, 1., -1.], [-0.25, 2., 0.]];
+        assert_eq!(
+            PcmFormat::S16.encode(&block.view()),
+            [0, 64, 0, 224, 255, 127, 255, 127, 0, 128, 0, 0]
+        );
+        let floats = PcmFormat::F32.encode(&block.view());
+        assert_eq!(floats.len(), 6 * 4);
+        assert_eq!(floats[4..8], (-0.25_f32).to_le_bytes());
+    }
+}
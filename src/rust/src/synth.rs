@@ -0,0 +1,256 @@
+use std::f64::consts::PI;
+
+use ndarray::{Array1, Array2, ArrayView3, Axis};
+use num_complex::Complex;
+
+use crate::features;
+use crate::util::SplitMix64;
+
+/// Length of one click, in seconds.
+pub const CLICK_DURATION: f64 = 0.1;
+
+/// `n` samples of a `freq` Hz sine with peak `amplitude`.
+pub fn tone(freq: f64, sr: u32, n: usize, amplitude: f64) -> Array1<f64> {
+    Array1::from_shape_fn(n, |i| {
+        amplitude * (2. * PI * freq * i as f64 / sr as f64).sin()
+    })
+}
+
+/// `n` samples of a sine sweeping from `fmin` to `fmax` Hz with peak `amplitude`.
+/// The frequency rises linearly in time when `linear` is set, and exponentially otherwise.
+pub fn chirp(fmin: f64, fmax: f64, sr: u32, n: usize, linear: bool, amplitude: f64) -> Array1<f64> {
+    let duration = n as f64 / sr as f64;
+    let ratio = fmax / fmin;
+    Array1::from_shape_fn(n, |i| {
+        let t = i as f64 / sr as f64;
+        let phase = if linear {
+            fmin * t + (fmax - fmin) * t * t / (2. * duration)
+        } else if ratio == 1. {
+            fmin * t
+        } else {
+            fmin * duration / ratio.ln() * (ratio.powf(t / duration) - 1.)
+        };
+        amplitude * (2. * PI * phase).sin()
+    })
+}
+
+/// Colour of the noise made by `noise`.
+pub enum NoiseKind {
+    /// Flat spectrum, uniformly distributed samples.
+    White,
+    /// Power falling 3 dB per octave, i.e. proportional to 1 / f.
+    Pink,
+}
+
+impl NoiseKind {
+    pub fn from_name(name: &str) -> NoiseKind {
+        match name {
+            "white" => NoiseKind::White,
+            "pink" => NoiseKind::Pink,
+            _ => panic!("kind must be either \"white\" or \"pink\""),
+        }
+    }
+}
+
+/// `n` samples of noise with peak at most `amplitude`, drawn from `rng`.
+/// Pink noise shapes the spectrum of Gaussian white noise by 1 / sqrt(f) and is scaled so its peak is `amplitude`.
+pub fn noise(kind: &NoiseKind, n: usize, amplitude: f64, rng: &mut SplitMix64) -> Array1<f64> {
+    match kind {
+        NoiseKind::White => Array1::from_shape_fn(n, |_| amplitude * (2. * rng.next_f64() - 1.)),
+        NoiseKind::Pink => {
+            if n == 0 {
+                return Array1::zeros(0);
+            }
+            let forward = features::forward_fft(n);
+            let inverse = features::inverse_fft(n);
+            let mut signal: Vec<f64> = (0..n).map(|_| rng.next_normal()).collect();
+            let mut spectrum = forward.make_output_vec();
+            forward
+                .process(&mut signal, &mut spectrum)
+                .expect("cannot compute the fft");
+
+            spectrum[0] = Complex::new(0., 0.);
+            for (k, bin) in spectrum.iter_mut().enumerate().skip(1) {
+                *bin /= (k as f64).sqrt();
+            }
+            // the Nyquist bin of an even length is real
+            let last = spectrum.len() - 1;
+            if 2 * last == n {
+                spectrum[last].im = 0.;
+            }
+            inverse
+                .process(&mut spectrum, &mut signal)
+                .expect("cannot compute the inverse fft");
+
+            let peak = signal.iter().fold(0., |peak: f64, v| peak.max(v.abs()));
+            let scale = if peak > 0. { amplitude / peak } else { 0. };
+            Array1::from_iter(signal.into_iter().map(|v| v * scale))
+        }
+    }
+}
+
+/// Shepard tone for pitch class `pitch_class` (0 = C), summing its octaves below the Nyquist
+/// frequency with a Gaussian weight around A4 (440 Hz). The weights sum to 1, so the peak is at most 1.
+fn pitch_class_tone(pitch_class: usize, sr: u32, n: usize) -> Array1<f64> {
+    let octaves: Vec<(f64, f64)> = (-4..=3)
+        .map(|octave| 440. * 2_f64.powf((pitch_class as f64 - 9.) / 12. + octave as f64))
+        .filter(|&freq| freq > 20. && freq < sr as f64 / 2.)
+        .map(|freq| (freq, (-0.5 * (freq / 440.).log2().powi(2)).exp()))
+        .collect();
+    let total: f64 = octaves.iter().map(|(_, weight)| weight).sum();
+
+    Array1::from_shape_fn(n, |i| {
+        let t = i as f64 / sr as f64;
+        octaves
+            .iter()
+            .map(|(freq, weight)| weight * (2. * PI * freq * t).sin())
+            .sum::<f64>()
+            / total
+    })
+}
+
+/// Sonify a (t, 12, channels) chromagram as a (channels, t * hop_length) array.
+/// Each frame is centered on sample `frame * hop_length`, and the pitch class weights are
+/// interpolated linearly between frames and normalised to sum to 1, so the output stays within [-1, 1].
+pub fn chroma_to_audio(chroma: &ArrayView3<f64>, sr: u32, hop_length: usize) -> Array2<f64> {
+    let (n_frames, n_chroma, channels) = chroma.dim();
+    if n_chroma != 12 {
+        panic!("chroma must have 12 pitch classes, got {}", n_chroma);
+    }
+    let n = n_frames * hop_length;
+    let tones: Vec<Array1<f64>> = (0..12).map(|p| pitch_class_tone(p, sr, n)).collect();
+
+    let mut out = Array2::<f64>::zeros((channels, n));
+    for (c, mut channel) in out.axis_iter_mut(Axis(0)).enumerate() {
+        let chroma = chroma.index_axis(Axis(2), c);
+        for (i, y) in channel.iter_mut().enumerate() {
+            let pos = i as f64 / hop_length as f64;
+            let frame = usize::min(pos as usize, n_frames - 1);
+            let next = usize::min(frame + 1, n_frames - 1);
+            let frac = pos - frame as f64;
+
+            let weights: Vec<f64> = (0..12)
+                .map(|p| {
+                    f64::max(
+                        chroma[[frame, p]] * (1. - frac) + chroma[[next, p]] * frac,
+                        0.,
+                    )
+                })
+                .collect();
+            let total: f64 = weights.iter().sum();
+            if total > 0. {
+                *y = (0..12).map(|p| weights[p] * tones[p][i]).sum::<f64>() / total;
+            }
+        }
+    }
+
+    out
+}
+
+/// `length` samples of silence with a decaying `click_freq` Hz click starting at each of `times` (seconds).
+/// Clicks that would start past the end are dropped and overlapping clicks add up.
+pub fn clicks(times: &[f64], sr: u32, length: usize, click_freq: f64) -> Array1<f64> {
+    let click_len = (CLICK_DURATION * sr as f64).round() as usize;
+    let click = Array1::from_shape_fn(click_len, |i| {
+        // decays from 1 to 2^-10 over the click
+        let decay = 2_f64.powf(-10. * i as f64 / click_len as f64);
+        decay * (2. * PI * click_freq * i as f64 / sr as f64).sin()
+    });
+
+    let mut out = Array1::<f64>::zeros(length);
+    for &time in times {
+        if time.is_nan() || time < 0. {
+            panic!("times must be non-negative");
+        }
+        let start = (time * sr as f64).round() as usize;
+        for (i, value) in click.iter().enumerate().take(length.saturating_sub(start)) {
+            out[start + i] += value;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test_synth {
+    use super::*;
+    use ndarray::Array3;
+
+    // magnitude of the correlation of `x` with a `freq` Hz sinusoid
+    fn tone_strength(x: &[f64], freq: f64, sr: f64) -> f64 {
+        let (re, im) = x.iter().enumerate().fold((0., 0.), |(re, im), (i, v)| {
+            let phase = 2. * PI * freq * i as f64 / sr;
+            (re + v * phase.cos(), im + v * phase.sin())
+        });
+        (re * re + im * im).sqrt() / x.len() as f64
+    }
+
+    #[test]
+    fn test_chroma_to_audio() {
+        let sr = 22050;
+        let mut chroma = Array3::<f64>::zeros((20, 12, 2));
+        chroma.slice_mut(ndarray::s![.., 9, 0]).fill(1.);
+
+        let y = chroma_to_audio(&chroma.view(), sr, 512);
+        assert_eq!(y.dim(), (2, 20 * 512));
+        assert!(y.iter().all(|v| v.abs() <= 1.));
+        assert!(y.row(1).iter().all(|v| *v == 0.));
+
+        let a = y.row(0).to_vec();
+        assert!(tone_strength(&a, 440., sr as f64) > 10. * tone_strength(&a, 466.16, sr as f64));
+    }
+
+    #[test]
+    fn test_generators() {
+        let sr = 8000;
+        let y = tone(1000., sr, 800, 0.5);
+        assert!(y.iter().all(|v| v.abs() <= 0.5));
+        assert!((tone_strength(y.as_slice().unwrap(), 1000., sr as f64) - 0.25).abs() < 1e-9);
+
+        // an exponential sweep spends as long in each octave, a linear one as long on each Hz
+        for linear in [false, true] {
+            let y = chirp(100., 3200., sr, 8000, linear, 0.5);
+            assert!(y.iter().all(|v| v.abs() <= 0.5));
+            let (first, last) = (y.slice(ndarray::s![..1600]), y.slice(ndarray::s![6400..]));
+            let low = tone_strength(first.as_slice().unwrap(), 150., sr as f64);
+            let high = tone_strength(last.as_slice().unwrap(), 150., sr as f64);
+            assert!(low > high);
+        }
+    }
+
+    #[test]
+    fn test_noise() {
+        let white = noise(&NoiseKind::White, 4096, 0.5, &mut SplitMix64::new(1));
+        assert_eq!(
+            white,
+            noise(&NoiseKind::White, 4096, 0.5, &mut SplitMix64::new(1))
+        );
+        assert!(white.iter().all(|v| v.abs() <= 0.5));
+
+        // power per octave band is flat for pink noise, so per bin it halves every octave
+        let pink = noise(&NoiseKind::Pink, 1 << 16, 0.5, &mut SplitMix64::new(1));
+        assert!((pink.iter().fold(0., |m: f64, v| m.max(v.abs())) - 0.5).abs() < 1e-12);
+        let spec = features::stft(&pink.view(), 1024, 512, &features::PadMode::Constant)
+            .mapv(|c| c.norm_sqr());
+        let band = |lo: usize, hi: usize| spec.slice(ndarray::s![.., lo..hi]).mean().unwrap();
+        let ratio = band(32, 64) / band(64, 128);
+        assert!((1.6..2.4).contains(&ratio), "octave power ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_clicks() {
+        let y = clicks(&[0., 0.5], 1000, 600, 100.);
+        let click_len = (CLICK_DURATION * 1000.) as usize;
+
+        assert_eq!(y.len(), 600);
+        assert!(y
+            .slice(ndarray::s![click_len..500])
+            .iter()
+            .all(|v| *v == 0.));
+        assert_eq!(
+            y.slice(ndarray::s![..click_len]),
+            y.slice(ndarray::s![500..])
+        );
+        assert!(y.iter().any(|v| v.abs() > 0.5));
+    }
+}
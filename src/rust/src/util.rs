@@ -0,0 +1,227 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use ndarray::{s, Array2, ArrayView2, Axis, Slice};
+use rayon::prelude::*;
+
+/// Crop or pad `arr` along `axis` to exactly `size` entries.
+/// Padding is appended at the end, or split evenly on both sides when `center` is set.
+/// Cropping keeps the start of the data, or its middle when `center` is set.
+pub fn fix_length(
+    arr: &ArrayView2<f64>,
+    size: usize,
+    axis: Axis,
+    pad_value: f64,
+    center: bool,
+) -> Array2<f64> {
+    let n = arr.len_of(axis);
+    let mut shape = arr.raw_dim();
+    shape[axis.index()] = size;
+    let mut out = Array2::<f64>::from_elem(shape, pad_value);
+
+    if n >= size {
+        let start = if center { (n - size) / 2 } else { 0 };
+        out.assign(&arr.slice_axis(axis, Slice::from(start..start + size)));
+    } else {
+        let start = if center { (size - n) / 2 } else { 0 };
+        out.slice_axis_mut(axis, Slice::from(start..start + n))
+            .assign(arr);
+    }
+
+    out
+}
+
+/// Median of `values`, averaging the two middle values for even lengths.
+pub fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).expect("cannot compare NaN"));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        (values[mid - 1] + values[mid]) / 2.
+    }
+}
+
+/// `p`th percentile of `values`, interpolating linearly between the closest ranks, as the default
+/// of `numpy.percentile` and type 7 of R's `quantile()`.
+pub fn percentile(mut values: Vec<f64>, p: f64) -> f64 {
+    if values.is_empty() {
+        panic!("cannot take a percentile of no values");
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).expect("cannot compare NaN"));
+    let rank = p / 100. * (values.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    let above = usize::min(below + 1, values.len() - 1);
+    values[below] + (rank - below as f64) * (values[above] - values[below])
+}
+
+/// Reduce each channel (row) of `arr` to about `n_points` samples for drawing a waveform: the
+/// samples are split into `n_points / 2` buckets of equal length, each replaced by its minimum
+/// and maximum in the order they occur, so peaks survive where striding would skip them.
+/// Arrays with at most `n_points` samples are returned unchanged.
+pub fn minmax_decimate(arr: &ArrayView2<f64>, n_points: usize) -> Array2<f64> {
+    let n = arr.ncols();
+    let n_buckets = usize::max(n_points / 2, 1);
+    if n <= n_points {
+        return arr.to_owned();
+    }
+
+    let mut out = Array2::<f64>::zeros((arr.nrows(), 2 * n_buckets));
+    for (channel, mut row) in arr.outer_iter().zip(out.outer_iter_mut()) {
+        for b in 0..n_buckets {
+            let bucket = channel.slice(s![b * n / n_buckets..(b + 1) * n / n_buckets]);
+            let (mut lo, mut hi) = (0, 0);
+            for (i, v) in bucket.iter().enumerate() {
+                if *v < bucket[lo] {
+                    lo = i;
+                }
+                if *v > bucket[hi] {
+                    hi = i;
+                }
+            }
+            let (first, second) = if lo <= hi { (lo, hi) } else { (hi, lo) };
+            row[2 * b] = bucket[first];
+            row[2 * b + 1] = bucket[second];
+        }
+    }
+    out
+}
+
+/// Apply `f` to every item on the rayon thread pool.
+/// A panic while processing one item yields `None` for that item instead of aborting the whole batch.
+/// With `num_threads`, the items are processed on a pool of that many threads built for this call
+/// instead of the global pool, to bound the threads of one process among many on a shared machine.
+pub fn par_map_catch<T, U, F>(items: &[T], num_threads: Option<usize>, f: F) -> Vec<Option<U>>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    let map = || {
+        items
+            .par_iter()
+            .map(|item| catch_unwind(AssertUnwindSafe(|| f(item))).ok())
+            .collect()
+    };
+    match num_threads {
+        Some(n) => rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()
+            .expect("cannot build the thread pool")
+            .install(map),
+        None => map(),
+    }
+}
+
+/// SplitMix64 pseudo-random number generator. Small and fast, and the same seed gives the same
+/// sequence on every platform, which is all the noise generators need.
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    /// Seeded from the system clock, for when reproducibility is not asked for.
+    pub fn from_time() -> SplitMix64 {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system clock is before 1970")
+            .as_nanos();
+        SplitMix64(nanos as u64)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// Standard normal, by the Box-Muller transform.
+    pub fn next_normal(&mut self) -> f64 {
+        let u = 1. - self.next_f64();
+        let v = self.next_f64();
+        (-2. * u.ln()).sqrt() * (2. * std::f64::consts::PI * v).cos()
+    }
+}
+
+#[cfg(test)]
+mod test_util {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_fix_length() {
+        let arr = array![[1., 2., 3.], [4., 5., 6.]];
+
+        let padded = fix_length(&arr.view(), 5, Axis(1), 0., false);
+        assert_eq!(padded, array![[1., 2., 3., 0., 0.], [4., 5., 6., 0., 0.]]);
+
+        let centered = fix_length(&arr.view(), 5, Axis(1), -1., true);
+        assert_eq!(
+            centered,
+            array![[-1., 1., 2., 3., -1.], [-1., 4., 5., 6., -1.]]
+        );
+
+        let cropped = fix_length(&arr.view(), 2, Axis(1), 0., false);
+        assert_eq!(cropped, array![[1., 2.], [4., 5.]]);
+    }
+
+    #[test]
+    fn test_minmax_decimate() {
+        let mut arr = Array2::<f64>::zeros((1, 1000));
+        arr[[0, 123]] = 1.;
+        arr[[0, 700]] = -2.;
+        arr[[0, 710]] = 0.5;
+        let out = minmax_decimate(&arr.view(), 20);
+        assert_eq!(out.dim(), (1, 20));
+        // buckets of 100 samples, each giving its extremes in time order
+        assert_eq!(out.slice(s![0, 2..4]), array![0., 1.]);
+        assert_eq!(out.slice(s![0, 14..16]), array![-2., 0.5]);
+        assert_eq!(out.sum(), -0.5);
+
+        assert_eq!(minmax_decimate(&arr.view(), 1000), arr);
+    }
+
+    #[test]
+    fn test_splitmix64() {
+        let (mut a, mut b) = (SplitMix64::new(42), SplitMix64::new(42));
+        let xs: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+        assert_eq!(xs, (0..4).map(|_| b.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(xs[0], SplitMix64::new(43).next_u64());
+        assert!((0..1000)
+            .map(|_| a.next_f64())
+            .all(|x| (0. ..1.).contains(&x)));
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values = vec![3., 1., 4., 1., 5.];
+        assert_eq!(percentile(values.clone(), 0.), 1.);
+        assert_eq!(percentile(values.clone(), 50.), median(values.clone()));
+        assert_eq!(percentile(values.clone(), 100.), 5.);
+        // quantile(c(3, 1, 4, 1, 5), 0.9) in R.
+        assert!((percentile(values, 90.) - 4.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_par_map_catch() {
+        let out = par_map_catch(&[1_i32, 0, 4], None, |x| {
+            if *x == 0 {
+                panic!("zero");
+            }
+            10 / x
+        });
+        assert_eq!(out, vec![Some(10), None, Some(2)]);
+
+        let items: Vec<i32> = (0..64).collect();
+        let threads = par_map_catch(&items, Some(2), |_| rayon::current_num_threads());
+        assert!(threads.iter().all(|n| *n == Some(2)));
+    }
+}
@@ -0,0 +1,172 @@
+"""Reference Kaldi features of 0.1 s of mono.wav, the 4410 samples from 1 s on, for the tests of src/rust/src/kaldi.rs.
+
+Writes kaldi_mono.ark, a Kaldi text archive of three matrices:
+
+    fbank                 compute-fbank-feats --dither=0
+    mfcc                  compute-mfcc-feats --dither=0
+    fbank_energy_nosnip   compute-fbank-feats --dither=0 --use-energy=true --snip-edges=false
+
+all with --sample-frequency=44100. Kaldi itself is not a dependency of this repository, so the values are
+computed by a standalone transcription of ProcessWindow and ExtractWindow (feature-window.cc), MelBanks
+(mel-computations.cc) and the Fbank and Mfcc computers, in double precision and with a plain radix-2 FFT. It
+shares no code with kaldi.rs. The archive from Kaldi itself is a drop-in replacement, e.g.
+
+    compute-fbank-feats --dither=0 --sample-frequency=44100 \
+        "scp:echo mono sox mono.wav -t wav - trim 44100s 4410s |" ark,t:-
+
+Kaldi computes in single precision, which the tolerance of the tests allows for.
+
+Run from this directory with `python3 kaldi_reference.py`; only the standard library is needed.
+"""
+
+import cmath
+import math
+import wave
+from array import array
+
+FLT_EPSILON = 1.1920928955078125e-07
+SR = 44100
+OFFSET = 44100
+N_SAMPLES = 4410
+
+
+def read_samples():
+    with wave.open("mono.wav") as w:
+        assert w.getnchannels() == 1 and w.getsampwidth() == 2 and w.getframerate() == SR
+        w.setpos(OFFSET)
+        samples = array("h", w.readframes(N_SAMPLES))
+    return [float(v) for v in samples]
+
+
+def fft(x):
+    n = len(x)
+    if n == 1:
+        return [complex(x[0])]
+    even = fft(x[0::2])
+    odd = fft(x[1::2])
+    out = [0j] * n
+    for k in range(n // 2):
+        t = cmath.exp(-2j * math.pi * k / n) * odd[k]
+        out[k] = even[k] + t
+        out[k + n // 2] = even[k] - t
+    return out
+
+
+def mel_scale(f):
+    return 1127.0 * math.log(1.0 + f / 700.0)
+
+
+def mel_banks(n_mels, padded, low_freq=20.0):
+    """MelBanks::MelBanks: the weights of each bank over the padded / 2 FFT bins below Nyquist."""
+    num_fft_bins = padded // 2
+    fft_bin_width = SR / padded
+    mel_low = mel_scale(low_freq)
+    mel_high = mel_scale(SR / 2.0)
+    delta = (mel_high - mel_low) / (n_mels + 1)
+    banks = []
+    for b in range(n_mels):
+        left = mel_low + b * delta
+        center = mel_low + (b + 1) * delta
+        right = mel_low + (b + 2) * delta
+        weights = []
+        for i in range(num_fft_bins):
+            mel = mel_scale(fft_bin_width * i)
+            if left < mel < right:
+                if mel <= center:
+                    weights.append((mel - left) / (center - left))
+                else:
+                    weights.append((right - mel) / (right - center))
+            else:
+                weights.append(0.0)
+        banks.append(weights)
+    return banks
+
+
+def frames(wave_data, snip_edges, frame_length_ms=25.0, frame_shift_ms=10.0):
+    """NumFrames, FirstSampleOfFrame and ExtractWindow, before ProcessWindow."""
+    size = int(SR * 0.001 * frame_length_ms)
+    shift = int(SR * 0.001 * frame_shift_ms)
+    n = len(wave_data)
+    if snip_edges:
+        count = 0 if n < size else 1 + (n - size) // shift
+    else:
+        count = (n + shift // 2) // shift
+    for f in range(count):
+        if snip_edges:
+            start = f * shift
+        else:
+            start = f * shift + shift // 2 - size // 2
+        window = []
+        for i in range(size):
+            s = start + i
+            while s < 0 or s >= n:
+                s = -s - 1 if s < 0 else 2 * n - 1 - s
+            window.append(wave_data[s])
+        yield window
+
+
+def process_window(window):
+    """ProcessWindow with dither 0: the raw log energy and the padded, windowed frame."""
+    size = len(window)
+    mean = sum(window) / size
+    window = [v - mean for v in window]
+    raw_log_energy = math.log(max(sum(v * v for v in window), FLT_EPSILON))
+    for i in range(size - 1, 0, -1):
+        window[i] -= 0.97 * window[i - 1]
+    window[0] -= 0.97 * window[0]
+    a = 2.0 * math.pi / (size - 1)
+    window = [v * (0.5 - 0.5 * math.cos(a * i)) ** 0.85 for i, v in enumerate(window)]
+    padded = 1
+    while padded < size:
+        padded *= 2
+    return raw_log_energy, window + [0.0] * (padded - size)
+
+
+def log_mel_energies(frame, banks):
+    spectrum = fft(frame)
+    power = [abs(z) ** 2 for z in spectrum[: len(frame) // 2]]
+    return [math.log(max(sum(w * p for w, p in zip(bank, power)), FLT_EPSILON)) for bank in banks]
+
+
+def fbank(wave_data, use_energy=False, snip_edges=True, n_mels=23):
+    rows = []
+    banks = None
+    for window in frames(wave_data, snip_edges):
+        log_energy, frame = process_window(window)
+        banks = banks or mel_banks(n_mels, len(frame))
+        row = log_mel_energies(frame, banks)
+        rows.append([log_energy] + row if use_energy else row)
+    return rows
+
+
+def mfcc(wave_data, n_ceps=13, lifter=22.0, n_mels=23):
+    """Mfcc::Compute with the default --use-energy=true: C0 is the raw log energy, after liftering."""
+    rows = []
+    banks = None
+    for window in frames(wave_data, True):
+        log_energy, frame = process_window(window)
+        banks = banks or mel_banks(n_mels, len(frame))
+        log_mel = log_mel_energies(frame, banks)
+        row = []
+        for k in range(n_ceps):
+            # ComputeDctMatrix: the orthonormal DCT-II.
+            norm = math.sqrt(1.0 / n_mels) if k == 0 else math.sqrt(2.0 / n_mels)
+            c = norm * sum(v * math.cos(math.pi / n_mels * (j + 0.5) * k) for j, v in enumerate(log_mel))
+            row.append(c * (1.0 + 0.5 * lifter * math.sin(math.pi * k / lifter)))
+        row[0] = log_energy
+        rows.append(row)
+    return rows
+
+
+def write_ark(out, key, rows):
+    out.write(key + "  [\n")
+    for i, row in enumerate(rows):
+        out.write("  " + " ".join("%.9g" % v for v in row) + (" ]\n" if i == len(rows) - 1 else " \n"))
+
+
+if __name__ == "__main__":
+    x = read_samples()
+    with open("kaldi_mono.ark", "w") as out:
+        write_ark(out, "fbank", fbank(x))
+        write_ark(out, "mfcc", mfcc(x))
+        write_ark(out, "fbank_energy_nosnip", fbank(x, use_energy=True, snip_edges=False))